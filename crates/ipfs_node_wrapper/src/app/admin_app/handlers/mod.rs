@@ -7,9 +7,11 @@ use crate::app::admin_app::AdminAppState;
 use crate::error_convert;
 
 mod pin;
+mod rate_limit;
 mod traffic;
 
 pub use pin::*;
+pub use rate_limit::*;
 pub use traffic::*;
 
 /// Get IPFS node's information.
@@ -0,0 +1,27 @@
+use tracing::debug;
+use axum::extract::State;
+use ipfs_node_wrapper_structs::admin::dtos;
+use ipfs_node_wrapper_structs::StandardApiResult;
+use crate::app::admin_app::AdminAppState;
+
+/// List the state of download rate limiters. Clients not listed have full budgets.
+#[axum_macros::debug_handler]
+pub async fn list_rate_limiters(State(state): State<AdminAppState>) -> StandardApiResult<dtos::ListRateLimitersResponse> {
+    debug!("List rate limiters");
+    let limiters = [
+        ("ip", &state.app_state.download_rate_limit_per_ip),
+        ("user", &state.app_state.download_rate_limit_per_user),
+    ];
+    let list = limiters.into_iter()
+        .filter_map(|(scope, limiter)| limiter.as_ref().map(|v| dtos::RateLimiterState {
+            scope: scope.to_string(),
+            rate: v.rate(),
+            buckets: v.states(),
+        }))
+        .collect();
+
+    let res = dtos::ListRateLimitersResponse {
+        list,
+    };
+    Ok(res.into())
+}
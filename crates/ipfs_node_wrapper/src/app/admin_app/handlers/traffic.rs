@@ -7,6 +7,8 @@ use ipfs_node_wrapper_structs::StandardApiResult;
 
 // TODO 也许一般需要的是过去的一个时间段内的下载量（或者，为了防止节点太新，使用平均下载量增加速度）
 /// Get a list of the number of times files has been downloaded.
+///
+/// Only downloads from the beginning of files are counted. See `public_app::handlers::get_file`.
 #[axum_macros::debug_handler]
 pub async fn get_download_time_list(State(state): State<AdminAppState>) -> StandardApiResult<dtos::GetDownloadTimeListResponse> {
     info!("Get download time list");
@@ -0,0 +1,113 @@
+//! Register self to the Manager and keep sending heartbeats.
+
+use std::sync::Arc;
+#[allow(unused_imports)]
+use tracing::{error, debug, warn, info, trace};
+use ipfs_node_wrapper_structs::manager::dtos;
+use ipfs_node_wrapper_structs::StandardApiJsonBody;
+use crate::app::AppState;
+
+static REGISTER_RETRY_INTERVAL_TIME_MS: u64 = 3000;
+
+/// Config to register self to the Manager.
+#[derive(Debug, Clone)]
+pub struct RegisterConfig {
+    pub manager_address: String,
+    pub join_secret: String,
+    pub rpc_address: String,
+    pub wrapper_public_address: String,
+    pub wrapper_admin_address: String,
+    pub heartbeat_interval_ms: u64,
+}
+
+/// Spawn a task to register self to the Manager, then send heartbeats regularly.
+///
+/// Register again when the Manager doesn't know self.
+pub fn spawn_register_and_heartbeat(app_state: Arc<AppState>, config: RegisterConfig) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        loop {
+            let peer_id = register_until_success(&app_state, &client, &config).await;
+
+            let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(config.heartbeat_interval_ms));
+            loop {
+                interval.tick().await;
+                match send_heartbeat(&client, &config, &peer_id).await {
+                    Ok(()) => trace!("Heartbeat sent"),
+                    Err(reqwest::StatusCode::NOT_FOUND) => {
+                        warn!("Manager doesn't know self. Register again");
+                        break;
+                    }
+                    Err(status) => warn!("Failed to send heartbeat. Status code: {}", status),
+                }
+            }
+        }
+    })
+}
+
+/// Regularly try until register successfully. Return the peer id of self.
+#[tracing::instrument(skip_all)]
+async fn register_until_success(app_state: &AppState, client: &reqwest::Client, config: &RegisterConfig) -> String {
+    loop {
+        match register(app_state, client, config).await {
+            Ok((peer_id, node_id)) => {
+                info!("Registered to Manager. node_id: {}, peer_id: {}", node_id, peer_id);
+                return peer_id;
+            }
+            Err(e) => {
+                error!("Failed to register to Manager. Try again in {} ms. msg: {}", REGISTER_RETRY_INTERVAL_TIME_MS, e);
+                tokio::time::sleep(tokio::time::Duration::from_millis(REGISTER_RETRY_INTERVAL_TIME_MS)).await;
+            }
+        }
+    }
+}
+
+/// Return `(peer_id, node_id)`.
+async fn register(app_state: &AppState, client: &reqwest::Client, config: &RegisterConfig) -> Result<(String, String), String> {
+    let peer_id = app_state.ipfs_client.get_id_info().await
+        .map_err(|e| format!("Failed to get peer id: {e:?}"))?
+        .id;
+
+    let args = dtos::RegisterNodeArgs {
+        join_secret: config.join_secret.clone(),
+        peer_id: peer_id.clone(),
+        rpc_address: config.rpc_address.clone(),
+        wrapper_public_address: config.wrapper_public_address.clone(),
+        wrapper_admin_address: config.wrapper_admin_address.clone(),
+    };
+    let url = format!("http://{}/api/node/register", config.manager_address);
+    let res = client.post(url)
+        .json(&args)
+        .send().await
+        .map_err(|e| format!("Failed to send request: {e:?}"))?;
+    let status = res.status();
+    if !status.is_success() {
+        return Err(format!("Manager responds status code {}: {:?}", status, res.text().await));
+    }
+    let body: StandardApiJsonBody<dtos::RegisterNodeResponse> = res.json().await
+        .map_err(|e| format!("Unexpected response body: {e:?}"))?;
+
+    Ok((peer_id, body.data.node_id))
+}
+
+/// Return the status code when failed.
+async fn send_heartbeat(client: &reqwest::Client, config: &RegisterConfig, peer_id: &str) -> Result<(), reqwest::StatusCode> {
+    let args = dtos::HeartbeatArgs {
+        join_secret: config.join_secret.clone(),
+        peer_id: peer_id.to_string(),
+    };
+    let url = format!("http://{}/api/node/heartbeat", config.manager_address);
+    let res = client.post(url)
+        .json(&args)
+        .send().await
+        .map_err(|_e| {
+            error!("Failed to send heartbeat request. msg: {:?}", _e);
+            reqwest::StatusCode::SERVICE_UNAVAILABLE
+        })?;
+    let status = res.status();
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(status)
+    }
+}
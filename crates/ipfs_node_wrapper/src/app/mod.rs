@@ -1,9 +1,11 @@
 
 
 use tiny_ipfs_client::ReqwestIpfsClient;
+use ipfs_node_wrapper_structs::rate_limit::RateLimiter;
 
 pub mod admin_app;
 pub mod public_app;
+pub mod manager_helper;
 
 /// Public state among all apps.
 /// Should never be Cloned.
@@ -13,6 +15,17 @@ pub struct AppState {
     pub ipfs_client: ReqwestIpfsClient,
     /// Count the number of downloads of files. `cid -> count`.
     pub file_traffic_counter: scc::HashMap<String, usize>,
+    /// Secret shared with the Manager to verify signed download URLs.
+    /// Files could be downloaded without signature if `None`.
+    pub download_url_secret: Option<String>,
+    /// Allow downloading without signature even if `download_url_secret` is set.
+    pub allow_unsigned_download: bool,
+    /// Max age of files in HTTP caches. Files are not cached by HTTP caches if `None`.
+    pub http_cache_max_age_secs: Option<u64>,
+    /// Rate limit of downloads by client IP.
+    pub download_rate_limit_per_ip: Option<RateLimiter>,
+    /// Rate limit of downloads by the user of signed URL.
+    pub download_rate_limit_per_user: Option<RateLimiter>,
 }
 
 
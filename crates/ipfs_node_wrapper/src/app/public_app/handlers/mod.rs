@@ -1,47 +1,437 @@
 #[allow(unused_imports)]
-use tracing::{info, trace, error};
-use axum::extract::{Path, Query, State};
+use tracing::{info, trace, error, debug};
+use std::net::SocketAddr;
+use axum::extract::{ConnectInfo, Path, Query, State};
 use axum::body::Body;
-use axum::response::IntoResponse;
-use ipfs_node_wrapper_structs::public::dtos;
-use ipfs_node_wrapper_structs::ApiResponseResult;
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use ipfs_node_wrapper_structs::public::{dtos, signature};
+use ipfs_node_wrapper_structs::rate_limit;
+use ipfs_node_wrapper_structs::{errors, ApiResponseResult, StandardApiResult};
 use crate::app::public_app::PublicAppState;
-use crate::utils::HttpHeaderPorterFromReqwest;
+use crate::utils::{HttpHeaderPorterFromReqwest, HttpHeaderPorterToReqwest};
 use crate::error_convert;
 
 /// Get file from IPFS node's gateway.
+///
+/// The URL should be signed by the Manager if `download_url_secret` is set.
+///
+/// `Range` and `If-Range` are passed to the gateway, whose status, like `206 Partial Content`,
+/// and headers about range are passed back. `HEAD` gets headers only.
+///
+/// The traffic counter counts only downloads from the beginning, which are `200 OK`
+/// and `206 Partial Content` from byte 0, so that resuming or seeking in a file and `HEAD` are not counted.
+///
+/// Files are immutable. If HTTP cache is enabled, `ETag` is the CID,
+/// and `If-None-Match` is answered by `304 Not Modified` without contacting the gateway.
+/// So `304` is answered even if the node does not have the file, which is fine
+/// since the client already has the content of the CID, which never changes.
+/// Files of signed URLs are only cached privately until the URL expires.
+///
+/// Respond `504 Gateway Timeout` if the gateway could not be connected or does not respond in time,
+/// e.g. when the node could not find the blocks of the file.
 #[axum_macros::debug_handler]
 pub async fn get_file(
     State(state): State<PublicAppState>,
+    client_addr: Option<ConnectInfo<SocketAddr>>,
+    method: Method,
+    req_headers: HeaderMap,
     Path(cid): Path<String>,
     Query(query): Query<dtos::GetFileArgs>)
     -> ApiResponseResult {
-    info!("Get File cid: {}", cid);
-    // TODO timeout
+    info!("{} File cid: {}", method, cid);
+    if let Some(res) = check_signature(&state, &cid, &query) {
+        return Ok(res);
+    }
+    if let Some(res) = check_rate_limit(&state, client_addr.map(|v| v.0), &query) {
+        return Ok(res);
+    }
+    let cache_headers = generate_cache_headers(&state, &cid, &query);
+    if !cache_headers.is_empty() && is_not_modified(&req_headers, &cid) {
+        debug!("File is not modified. cid: {}", cid);
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let is_head = method == Method::HEAD;
+    let gateway_method = if is_head { reqwest::Method::HEAD } else { reqwest::Method::GET };
+    let gateway_headers = HttpHeaderPorterToReqwest::new(&req_headers)
+        .transfer_when_exist_with_static_key("range")
+        .transfer_when_exist_with_static_key("if-range")
+        .finish();
+    // If the client disconnects, the handler future or the body stream is dropped,
+    // which cancels the gateway request.
     let ipfs_res = state.app_state.ipfs_client
         .get_file_by_gateway(
             &cid,
             query.filename.as_deref(),
+            gateway_method,
+            gateway_headers,
         ).await
         .map_err(error_convert::from_ipfs_client_error)?;
-
-    // count traffic
-    state.app_state.file_traffic_counter
-        .entry_async(cid).await
-        .and_modify(|v| *v += 1)
-        .or_insert(1);
+    let status = StatusCode::from_u16(ipfs_res.status().as_u16())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
 
     // construct header
     let ipfs_res_header = ipfs_res.headers();
     // trace!("Header: {:#?}", ipfs_res_header);
-    let header = HttpHeaderPorterFromReqwest::new(ipfs_res_header)
+    let mut header = HttpHeaderPorterFromReqwest::new(ipfs_res_header)
         .transfer_when_exist_with_static_key("content-type")
         .transfer_when_exist_with_static_key("content-disposition")
+        .transfer_when_exist_with_static_key("content-length")
+        .transfer_when_exist_with_static_key("content-range")
+        .transfer_when_exist_with_static_key("accept-ranges")
         .finish();
+    if status.is_success() {
+        header.extend(cache_headers);
+    }
 
+    // count traffic of downloads from the beginning, so that seeking in a file is not counted
+    if !is_head && is_download_from_beginning(status, &header) {
+        state.app_state.file_traffic_counter
+            .entry_async(cid).await
+            .and_modify(|v| *v += 1)
+            .or_insert(1);
+    }
+
+    if is_head {
+        return Ok((status, header).into_response());
+    }
     // read file
-    let body = Body::from_stream(ipfs_res.bytes_stream());
+    let body = Body::from_stream(state.app_state.ipfs_client.gateway_bytes_stream(ipfs_res));
+
+    Ok((status, header, body).into_response())
+}
+
+/// `ETag` and `Cache-Control` of the file. Empty if HTTP cache is disabled.
+///
+/// If download URLs should be signed, shared caches could serve the file without checking the signature,
+/// so the file is cached privately and not longer than the URL is valid.
+fn generate_cache_headers(state: &PublicAppState, cid: &str, query: &dtos::GetFileArgs) -> HeaderMap {
+    let mut header = HeaderMap::new();
+    let Some(max_age) = state.app_state.http_cache_max_age_secs else {
+        return header;
+    };
+    let cache_control = match &state.app_state.download_url_secret {
+        Some(_) => {
+            let max_age = match query.expires {
+                Some(expires) => max_age.min(expires.saturating_sub(unix_now()).max(0) as u64),
+                None => max_age,
+            };
+            format!("private, max-age={max_age}")
+        }
+        None => format!("public, max-age={max_age}, immutable"),
+    };
+    // a CID is alphanumeric, while other paths should not be cached
+    if cid.is_empty() || !cid.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return header;
+    }
+    if let (Ok(etag), Ok(cache_control)) = (HeaderValue::from_str(&format!("\"{cid}\"")), HeaderValue::from_str(&cache_control)) {
+        header.insert(header::ETAG, etag);
+        header.insert(header::CACHE_CONTROL, cache_control);
+    }
+    header
+}
+
+/// Whether `If-None-Match` matches the `ETag` of the CID. Weak tags match too.
+///
+/// Whether the node has the file is not checked.
+/// `*` is not matched, since it only means that any file exists, which is unknown without the gateway.
+fn is_not_modified(req_headers: &HeaderMap, cid: &str) -> bool {
+    let etag = format!("\"{cid}\"");
+    req_headers.get_all(header::IF_NONE_MATCH).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim())
+        .any(|v| v.strip_prefix("W/").unwrap_or(v) == etag)
+}
 
-    Ok((header, body).into_response())
+fn is_download_from_beginning(status: StatusCode, header: &HeaderMap) -> bool {
+    match status {
+        StatusCode::OK => true,
+        StatusCode::PARTIAL_CONTENT => header.get(header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("bytes 0-")),
+        _ => false,
+    }
+}
+
+/// Return a 403 response if the download is not allowed.
+fn check_signature(state: &PublicAppState, cid: &str, query: &dtos::GetFileArgs) -> Option<Response> {
+    let Some(secret) = &state.app_state.download_url_secret else {
+        return None;
+    };
+    let msg = match signature::verify(secret.as_bytes(), cid, query, unix_now()) {
+        Ok(()) => return None,
+        Err(signature::SignatureError::Missing) if state.app_state.allow_unsigned_download => return None,
+        Err(signature::SignatureError::Missing) => "Download URL should be signed",
+        Err(signature::SignatureError::Expired) => "Download URL is expired",
+        Err(signature::SignatureError::Invalid) => "Download URL has invalid signature",
+    };
+    debug!("Reject downloading cid {}: {}", cid, msg);
+    Some(errors::DOWNLOAD_DENIED.clone_to_error()
+        .modify_msg(msg)
+        .modify_status_code(StatusCode::FORBIDDEN)
+        .into_response())
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|v| v.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Return a 429 response if the client downloads too much.
+fn check_rate_limit(state: &PublicAppState, client_addr: Option<SocketAddr>, query: &dtos::GetFileArgs) -> Option<Response> {
+    let ip = client_addr.map(|v| v.ip().to_string());
+    // the user is trusted only if the signature is verified
+    let user = match (&state.app_state.download_url_secret, &query.signature) {
+        (Some(_), Some(_)) => query.user.as_deref(),
+        _ => None,
+    };
+    let limits = [
+        (&state.app_state.download_rate_limit_per_ip, ip.as_deref()),
+        (&state.app_state.download_rate_limit_per_user, user),
+    ];
+    let limits = limits.into_iter()
+        .filter_map(|(limiter, key)| Some((limiter.as_ref()?, key?)));
+    // a download rejected by one limit is not charged by the others
+    let Err(wait) = rate_limit::check_all(limits) else {
+        return None;
+    };
+    debug!("Rate limit downloading from ip {:?}, user {:?}", ip, user);
+    let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+    let mut res = errors::RATE_LIMITED.clone_to_error()
+        .modify_status_code(StatusCode::TOO_MANY_REQUESTS)
+        .into_response();
+    res.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    Some(res)
+}
+
+/// Check if the public service is alive.
+#[axum_macros::debug_handler]
+pub async fn health_check() -> StandardApiResult<()> {
+    trace!("Health check");
+    Ok(().into())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tiny_ipfs_client::ReqwestIpfsClient;
+    use ipfs_node_wrapper_structs::rate_limit::RateLimiter;
+    use crate::app::AppState;
+
+    fn test_state(app_state: AppState) -> PublicAppState {
+        PublicAppState { app_state: Arc::new(app_state) }
+    }
+
+    fn args(expires: Option<i64>, user: Option<&str>, signature: Option<String>) -> dtos::GetFileArgs {
+        dtos::GetFileArgs {
+            filename: None,
+            expires,
+            user: user.map(str::to_owned),
+            signature,
+        }
+    }
+
+    /// Serve a fake gateway of a file of 10 bytes, supporting `Range` of `bytes={start}-`.
+    async fn serve_fake_gateway() -> String {
+        const FILE: &[u8] = b"0123456789";
+        let gateway_app = axum::Router::new().route("/ipfs/:cid", axum::routing::get(|req_headers: HeaderMap| async move {
+            let start = req_headers.get(header::RANGE)
+                .and_then(|v| v.to_str().ok()?.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok());
+            match start {
+                None => (StatusCode::OK, FILE).into_response(),
+                Some(start) if start < FILE.len() => (
+                    StatusCode::PARTIAL_CONTENT,
+                    [(header::CONTENT_RANGE, format!("bytes {start}-{}/{}", FILE.len() - 1, FILE.len()))],
+                    &FILE[start..],
+                ).into_response(),
+                Some(_) => (
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    [(header::CONTENT_RANGE, format!("bytes */{}", FILE.len()))],
+                ).into_response(),
+            }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move { axum::serve(listener, gateway_app).await.unwrap() });
+        address
+    }
+
+    async fn call(state: &PublicAppState, method: Method, req_headers: HeaderMap, query: dtos::GetFileArgs) -> Response {
+        get_file(State(state.clone()), None, method, req_headers, Path("cid".to_string()), Query(query))
+            .await
+            .into_response()
+    }
+
+    #[tokio::test]
+    async fn test_reject_unsigned_download() {
+        let state = test_state(AppState {
+            download_url_secret: Some("secret".to_string()),
+            ..Default::default()
+        });
+        let expires = unix_now() + 60;
+        let signature = signature::sign(b"secret", "cid", expires, Some("alice"));
+        let expired_signature = signature::sign(b"secret", "cid", expires - 120, Some("alice"));
+        for query in [
+            args(None, None, None),
+            args(Some(expires), Some("alice"), None),
+            args(Some(expires - 120), Some("alice"), Some(expired_signature)),
+            args(Some(expires), Some("bob"), Some(signature.clone())),
+            args(Some(expires), Some("alice"), Some("not hex".to_string())),
+        ] {
+            let res = call(&state, Method::GET, HeaderMap::new(), query.clone()).await;
+            assert_eq!(res.status(), StatusCode::FORBIDDEN, "{query:?}");
+        }
+        assert!(check_signature(&state, "cid", &args(Some(expires), Some("alice"), Some(signature))).is_none());
+    }
+
+    #[test]
+    fn test_allow_unsigned_download() {
+        let state = test_state(AppState {
+            download_url_secret: Some("secret".to_string()),
+            allow_unsigned_download: true,
+            ..Default::default()
+        });
+        assert!(check_signature(&state, "cid", &args(None, None, None)).is_none());
+        // a wrong signature is still rejected
+        let res = check_signature(&state, "cid", &args(Some(unix_now() + 60), None, Some("00".to_string())));
+        assert_eq!(res.unwrap().status(), StatusCode::FORBIDDEN);
+        // nothing to check without secret
+        let state = test_state(AppState::default());
+        assert!(check_signature(&state, "cid", &args(None, None, None)).is_none());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let state = test_state(AppState {
+            download_url_secret: Some("secret".to_string()),
+            download_rate_limit_per_ip: Some(RateLimiter::new("2/m".parse().unwrap())),
+            download_rate_limit_per_user: Some(RateLimiter::new("1/m".parse().unwrap())),
+            ..Default::default()
+        });
+        let client_addr = Some("1.1.1.1:1234".parse().unwrap());
+        let expires = unix_now() + 60;
+        let signed = args(Some(expires), Some("alice"), Some(signature::sign(b"secret", "cid", expires, Some("alice"))));
+        assert!(check_rate_limit(&state, client_addr, &signed).is_none());
+        for _ in 0..2 {
+            let res = check_rate_limit(&state, client_addr, &signed).unwrap();
+            assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+            let retry_after: u64 = res.headers()[header::RETRY_AFTER].to_str().unwrap().parse().unwrap();
+            assert!(retry_after > 0 && retry_after <= 60);
+        }
+        // downloads rejected by the limit of the user are not charged to the ip
+        assert!(check_rate_limit(&state, client_addr, &args(None, None, None)).is_none());
+        assert!(check_rate_limit(&state, client_addr, &args(None, None, None)).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_file_by_range() {
+        let gateway_address = serve_fake_gateway().await;
+        let state = test_state(AppState {
+            ipfs_client: ReqwestIpfsClient::new("127.0.0.1:1".to_string())
+                .with_gateway_address(gateway_address),
+            ..Default::default()
+        });
+        let count = || state.app_state.file_traffic_counter.read("cid", |_, v| *v);
+        let range = |range: &'static str| HeaderMap::from_iter([(header::RANGE, HeaderValue::from_static(range))]);
+        let body = |res: Response| async move {
+            axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap()
+        };
+
+        let res = call(&state, Method::GET, HeaderMap::new(), args(None, None, None)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body(res).await, "0123456789");
+        assert_eq!(count(), Some(1));
+
+        let res = call(&state, Method::GET, range("bytes=0-"), args(None, None, None)).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 0-9/10");
+        assert_eq!(body(res).await, "0123456789");
+        assert_eq!(count(), Some(2));
+
+        // seeking is not counted
+        let res = call(&state, Method::GET, range("bytes=5-"), args(None, None, None)).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 5-9/10");
+        assert_eq!(body(res).await, "56789");
+        assert_eq!(count(), Some(2));
+
+        let res = call(&state, Method::GET, range("bytes=20-"), args(None, None, None)).await;
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes */10");
+        assert_eq!(count(), Some(2));
+
+        let res = call(&state, Method::HEAD, HeaderMap::new(), args(None, None, None)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(body(res).await.is_empty());
+        assert_eq!(count(), Some(2));
+    }
+
+    #[test]
+    fn test_is_not_modified() {
+        let if_none_match = |v: &'static str| HeaderMap::from_iter([(header::IF_NONE_MATCH, HeaderValue::from_static(v))]);
+        assert!(is_not_modified(&if_none_match("\"cid\""), "cid"));
+        assert!(is_not_modified(&if_none_match("W/\"cid\""), "cid"));
+        assert!(is_not_modified(&if_none_match("\"other\", W/\"cid\""), "cid"));
+        assert!(!is_not_modified(&if_none_match("*"), "cid"));
+        assert!(!is_not_modified(&if_none_match("\"other\""), "cid"));
+        assert!(!is_not_modified(&HeaderMap::new(), "cid"));
+    }
+
+    #[tokio::test]
+    async fn test_not_modified() {
+        // nothing listens, so the gateway is not contacted
+        let state = test_state(AppState {
+            ipfs_client: ReqwestIpfsClient::new("127.0.0.1:1".to_string())
+                .with_gateway_address("127.0.0.1:1".to_string()),
+            http_cache_max_age_secs: Some(3600),
+            ..Default::default()
+        });
+        let req_headers = HeaderMap::from_iter([(header::IF_NONE_MATCH, HeaderValue::from_static("W/\"cid\""))]);
+        let res = call(&state, Method::GET, req_headers, args(None, None, None)).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::ETAG], "\"cid\"");
+        let req_headers = HeaderMap::from_iter([(header::IF_NONE_MATCH, HeaderValue::from_static("*"))]);
+        let res = call(&state, Method::GET, req_headers, args(None, None, None)).await;
+        assert_ne!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn test_cache_headers() {
+        let state = test_state(AppState {
+            http_cache_max_age_secs: Some(3600),
+            ..Default::default()
+        });
+        let headers = generate_cache_headers(&state, "cid", &args(None, None, None));
+        assert_eq!(headers[header::ETAG], "\"cid\"");
+        assert_eq!(headers[header::CACHE_CONTROL], "public, max-age=3600, immutable");
+        // not a CID
+        for path in ["", "cid.txt", "a b", "ci/d"] {
+            assert!(generate_cache_headers(&state, path, &args(None, None, None)).is_empty(), "{path}");
+        }
+
+        // signed URLs are cached privately until expiry
+        let state = test_state(AppState {
+            download_url_secret: Some("secret".to_string()),
+            http_cache_max_age_secs: Some(3600),
+            ..Default::default()
+        });
+        let expires = unix_now() + 60;
+        let headers = generate_cache_headers(&state, "cid", &args(Some(expires), None, Some("00".to_string())));
+        let cache_control = headers[header::CACHE_CONTROL].to_str().unwrap();
+        let max_age: u64 = cache_control.strip_prefix("private, max-age=").unwrap().parse().unwrap();
+        assert!(max_age > 0 && max_age <= 60, "{cache_control}");
+        let headers = generate_cache_headers(&state, "cid", &args(Some(unix_now() + 7200), None, Some("00".to_string())));
+        assert_eq!(headers[header::CACHE_CONTROL], "private, max-age=3600");
+        let headers = generate_cache_headers(&state, "cid", &args(Some(unix_now() - 10), None, Some("00".to_string())));
+        assert_eq!(headers[header::CACHE_CONTROL], "private, max-age=0");
+
+        // disabled
+        let state = test_state(AppState::default());
+        assert!(generate_cache_headers(&state, "cid", &args(None, None, None)).is_empty());
+    }
+}
@@ -22,6 +22,7 @@ pub fn generate_public_app(app_state: &Arc<AppState>) -> Router {
     };
 
     let app = Router::new()
+        .route("/health", get(health_check))
         .route("/:cid", get(get_file));
 
     let app = Router::new()
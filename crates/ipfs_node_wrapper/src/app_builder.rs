@@ -1,16 +1,96 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use axum::Router;
 use tokio::net::ToSocketAddrs;
 use tracing::info;
-use tiny_ipfs_client::ReqwestIpfsClient;
+use tiny_ipfs_client::{GatewayTimeouts, ReqwestIpfsClient};
 use serde::Deserialize;
-use crate::app::{admin_app, AppState, public_app};
+use ipfs_node_wrapper_structs::rate_limit::{Rate, RateLimiter};
+use crate::app::{admin_app, AppState, public_app, manager_helper};
 
 #[derive(Deserialize)]
 pub struct AppConfig {
     // Ipfs node config
     pub ipfs_gateway_address: String,
     pub ipfs_rpc_address: String,
+    /// Timeouts of fetching files from the gateway, in milliseconds. `0` means no timeout.
+    #[serde(default = "default_gateway_connect_timeout_ms")]
+    pub gateway_connect_timeout_ms: u64,
+    /// Until the gateway responds, e.g. while the node is looking for the blocks of a file.
+    #[serde(default = "default_gateway_first_byte_timeout_ms")]
+    pub gateway_first_byte_timeout_ms: u64,
+    /// Max time between two chunks of a file.
+    #[serde(default = "default_gateway_idle_timeout_ms")]
+    pub gateway_idle_timeout_ms: u64,
+    // Manager config
+    /// Address of the Manager. Register self to the Manager if set.
+    pub manager_address: Option<String>,
+    /// Secret shared with the Manager.
+    pub join_secret: Option<String>,
+    /// Address of IPFS node's RPC, reachable by Manager. Default to `ipfs_rpc_address`.
+    pub advertised_rpc_address: Option<String>,
+    /// Public address reachable by users.
+    pub advertised_public_address: Option<String>,
+    /// Admin address reachable by Manager.
+    pub advertised_admin_address: Option<String>,
+    #[serde(default = "default_heartbeat_interval_ms")]
+    pub heartbeat_interval_ms: u64,
+    // Download config
+    /// Secret shared with the Manager to sign download URLs.
+    /// Require signed URLs to download if set.
+    pub download_url_secret: Option<String>,
+    /// Still allow unsigned URLs when `download_url_secret` is set.
+    #[serde(default)]
+    pub allow_unsigned_download: bool,
+    /// Whether to let HTTP caches, like CDN, keep files by `ETag` and `Cache-Control`.
+    #[serde(default = "default_true")]
+    pub http_cache_enabled: bool,
+    /// Files are immutable, so they could be cached for long.
+    #[serde(default = "default_http_cache_max_age_secs")]
+    pub http_cache_max_age_secs: u64,
+    /// Rate limits of downloads by client IP and by the user of signed URL, like `10/s`, `600/m` or `1000/h`.
+    /// Unlimited if not set.
+    #[serde(default)]
+    pub rate_limit_download_per_ip: Option<Rate>,
+    #[serde(default)]
+    pub rate_limit_download_per_user: Option<Rate>,
+}
+
+fn default_gateway_connect_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_gateway_first_byte_timeout_ms() -> u64 {
+    60 * 1000
+}
+
+fn default_gateway_idle_timeout_ms() -> u64 {
+    30 * 1000
+}
+
+fn default_heartbeat_interval_ms() -> u64 {
+    5000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_http_cache_max_age_secs() -> u64 {
+    // a year
+    31536000
+}
+
+impl AppConfig {
+    fn gateway_timeouts(&self) -> GatewayTimeouts {
+        let to_duration = |ms: u64| (ms > 0).then(|| Duration::from_millis(ms));
+        GatewayTimeouts {
+            connect: to_duration(self.gateway_connect_timeout_ms),
+            first_byte: to_duration(self.gateway_first_byte_timeout_ms),
+            idle: to_duration(self.gateway_idle_timeout_ms),
+        }
+    }
 }
 
 #[tracing::instrument(skip_all)]
@@ -21,14 +101,24 @@ pub async fn serve(app_config: AppConfig) {
 
     info!("IPFS Node gateway at: {}", app_config.ipfs_gateway_address);
     info!("IPFS Node rpc     at: {}", app_config.ipfs_rpc_address);
+    if app_config.download_url_secret.is_some() {
+        info!("Download URLs should be signed. Allow unsigned: {}", app_config.allow_unsigned_download);
+    }
 
     let app_state = Arc::new(AppState {
-        ipfs_client: ReqwestIpfsClient::new(
-            app_config.ipfs_gateway_address.to_string(),
-            app_config.ipfs_rpc_address.to_string()
-        ),
+        ipfs_client: ReqwestIpfsClient::new(app_config.ipfs_rpc_address.to_string())
+            .with_gateway_address(app_config.ipfs_gateway_address.to_string())
+            .with_gateway_timeouts(app_config.gateway_timeouts())
+            .expect("Failed to build the client of IPFS gateway"),
         file_traffic_counter: scc::HashMap::new(),
+        download_url_secret: app_config.download_url_secret.clone(),
+        allow_unsigned_download: app_config.allow_unsigned_download,
+        http_cache_max_age_secs: app_config.http_cache_enabled
+            .then_some(app_config.http_cache_max_age_secs),
+        download_rate_limit_per_ip: app_config.rate_limit_download_per_ip.map(RateLimiter::new),
+        download_rate_limit_per_user: app_config.rate_limit_download_per_user.map(RateLimiter::new),
     });
+    spawn_rate_limit_purger(app_state.clone());
 
     let public_server = generate_server(
         "0.0.0.0:3000",
@@ -39,12 +129,44 @@ pub async fn serve(app_config: AppConfig) {
         admin_app::generate_admin_app(&app_state).await,
     );
 
+    if let Some(manager_address) = &app_config.manager_address {
+        let register_config = manager_helper::RegisterConfig {
+            manager_address: manager_address.clone(),
+            join_secret: app_config.join_secret.clone()
+                .expect("`join_secret` is required when `manager_address` is set"),
+            rpc_address: app_config.advertised_rpc_address.clone()
+                .unwrap_or_else(|| app_config.ipfs_rpc_address.clone()),
+            wrapper_public_address: app_config.advertised_public_address.clone()
+                .expect("`advertised_public_address` is required when `manager_address` is set"),
+            wrapper_admin_address: app_config.advertised_admin_address.clone()
+                .expect("`advertised_admin_address` is required when `manager_address` is set"),
+            heartbeat_interval_ms: app_config.heartbeat_interval_ms,
+        };
+        info!("Would register self to Manager at: {}", manager_address);
+        manager_helper::spawn_register_and_heartbeat(app_state.clone(), register_config);
+    }
+
     info!("========*** Server start successfully ***========");
     tokio::join!(public_server, admin_server);
 }
 
+/// Free the memory of idle clients in rate limiters regularly.
+fn spawn_rate_limit_purger(app_state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            let limiters = [&app_state.download_rate_limit_per_ip, &app_state.download_rate_limit_per_user];
+            for limiter in limiters.into_iter().flatten() {
+                limiter.purge_full();
+            }
+        }
+    });
+}
+
 /// Tool to bind server to port
 async fn generate_server(address: impl ToSocketAddrs, app: Router) {
     let listener = tokio::net::TcpListener::bind(address).await.unwrap();
-    axum::serve(listener, app).await.unwrap()
+    // client address is used by rate limits
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
 }
@@ -1,3 +1,4 @@
+use axum::http::StatusCode;
 use ipfs_node_wrapper_structs::errors::*;
 use tiny_ipfs_client::IpfsClientError;
 
@@ -10,6 +11,8 @@ pub fn from_ipfs_client_error(value: IpfsClientError) -> ResponseError {
         IpfsClientError::RpcReject => IPFS_REQUEST_ERROR.clone_to_error(),
         IpfsClientError::RpcInternalServerError => IPFS_RESPOND_ERROR.clone_to_error(),
         IpfsClientError::UnexpectedResponseBody => IPFS_FAIL.clone_to_error(),
+        IpfsClientError::Timeout => IPFS_TIMEOUT.clone_to_error()
+            .modify_status_code(StatusCode::GATEWAY_TIMEOUT),
     }
 }
 
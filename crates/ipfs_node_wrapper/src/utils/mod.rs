@@ -31,3 +31,35 @@ impl<'a> HttpHeaderPorterFromReqwest<'a> {
     }
 }
 
+
+/// Transfer header map from axum to reqwest.
+pub struct HttpHeaderPorterToReqwest<'a> {
+    header: reqwest::header::HeaderMap,
+    axum_header_map: &'a axum::http::HeaderMap,
+}
+
+impl<'a> HttpHeaderPorterToReqwest<'a> {
+    pub fn new(axum_header_map: &'a axum::http::HeaderMap) -> Self {
+        HttpHeaderPorterToReqwest {
+            header: reqwest::header::HeaderMap::new(),
+            axum_header_map,
+        }
+    }
+
+    pub fn transfer_when_exist_with_static_key(mut self, key: &'static str) -> Self {
+        let header_value = self.axum_header_map
+            .get(key);
+        if let Some(header_value) = header_value {
+            let hv = reqwest::header::HeaderValue::from_bytes(header_value.as_ref());
+            if let Ok(hv) = hv {
+                self.header.insert(key, hv);
+            }
+        }
+
+        self
+    }
+
+    pub fn finish(self) -> reqwest::header::HeaderMap {
+        self.header
+    }
+}
@@ -18,6 +18,14 @@ impl IpfsNodeWrapperAdminClient {
             base_url: address,
         }
     }
+
+    /// Relatively cheap to create (only address changed).
+    pub fn new_with_reqwest_client(address: String, client: reqwest::Client) -> Self {
+        IpfsNodeWrapperAdminClient {
+            client,
+            base_url: address,
+        }
+    }
 }
 
 /// private tools
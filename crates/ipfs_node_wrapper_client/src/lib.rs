@@ -1,6 +1,7 @@
 /// A client to contact `ipfs_node_wrapper`.
 
 pub mod admin;
+pub mod public;
 pub mod common;
 mod client_tools;
 mod client;
@@ -0,0 +1,34 @@
+mod normal;
+
+pub struct IpfsNodeWrapperPublicClient {
+    client: reqwest::Client,
+    pub base_url: String,
+}
+
+impl IpfsNodeWrapperPublicClient {
+    /// Arg example: "127.0.0.1:3000"
+    pub fn new(address: String) -> Self {
+        IpfsNodeWrapperPublicClient {
+            client: reqwest::Client::new(),
+            base_url: address,
+        }
+    }
+
+    /// Relatively cheap to create (only address changed).
+    pub fn new_with_reqwest_client(address: String, client: reqwest::Client) -> Self {
+        IpfsNodeWrapperPublicClient {
+            client,
+            base_url: address,
+        }
+    }
+}
+
+/// private tools
+impl IpfsNodeWrapperPublicClient {
+    /// Generate `http://{base_url}{url_content}`
+    fn generate_url(&self, url_content: &str) -> String {
+        format!("http://{base_url}{url_content}",
+                base_url = self.base_url,
+                url_content = url_content)
+    }
+}
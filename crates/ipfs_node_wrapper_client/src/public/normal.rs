@@ -0,0 +1,13 @@
+use crate::public::IpfsNodeWrapperPublicClient;
+use crate::common::StandardClientResult;
+use crate::client_tools::handle_client_response;
+
+impl IpfsNodeWrapperPublicClient {
+    /// Check if the public service is alive.
+    pub async fn check_health(&self) -> StandardClientResult<()> {
+        let url = self.generate_url("/api/health");
+        let res = self.client.get(url)
+            .send().await;
+        handle_client_response(res).await
+    }
+}
@@ -40,3 +40,21 @@ pub struct GetDownloadTimeListResponse {
     pub list: HashMap<String, usize>,
 }
 
+
+/// State of a rate limiter of downloads.
+#[cfg(feature = "rate_limit")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimiterState {
+    /// What is limited, `ip` or `user`.
+    pub scope: String,
+    pub rate: crate::rate_limit::Rate,
+    /// Keys not listed have full buckets.
+    pub buckets: Vec<crate::rate_limit::BucketState>,
+}
+
+#[cfg(feature = "rate_limit")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListRateLimitersResponse {
+    pub list: Vec<RateLimiterState>,
+}
@@ -14,4 +14,8 @@ define_static_error!(IPFS_CLIENT_ERROR, "C0601", "Error when send request to IPF
 define_static_error!(IPFS_NOT_FOUND, "C0602", "IPFS not found");
 define_static_error!(IPFS_REQUEST_ERROR, "C0603", "IPFS node rejects the request");
 define_static_error!(IPFS_RESPOND_ERROR, "C0604", "IPFS node responds an error");
+define_static_error!(IPFS_TIMEOUT, "C0605", "IPFS node did not respond in time");
 
+
+define_static_error!(RATE_LIMITED, "A0501", "Too many requests");
+define_static_error!(DOWNLOAD_DENIED, "C0700", "Download URL is not signed, invalid or expired");
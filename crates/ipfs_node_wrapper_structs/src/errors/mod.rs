@@ -14,9 +14,13 @@ mod errors_list;
 pub use errors_list::*;
 
 /// Can be handler's return type.
-/// The http status code always be StatusCode::INTERNAL_SERVER_ERROR.
+/// The http status code is StatusCode::INTERNAL_SERVER_ERROR unless set.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ResponseError {
+    /// Set status code. Default 500.
+    /// Kept as `u16`, as `http` is only available with feature `server`.
+    #[serde(skip)]
+    pub status_code: Option<u16>,
     pub code: String,
     pub message: String,
 }
@@ -24,6 +28,7 @@ pub struct ResponseError {
 impl ResponseError {
     pub fn new(code: &str, msg: &str) -> Self {
         ResponseError {
+            status_code: None,
             code: code.to_string(),
             message: msg.to_string(),
         }
@@ -34,6 +39,12 @@ impl ResponseError {
         self
     }
 
+    #[cfg(feature = "server")]
+    pub fn modify_status_code(mut self, new_status_code: StatusCode) -> Self {
+        self.status_code = Some(new_status_code.as_u16());
+        self
+    }
+
     pub fn log(self) -> Self {
         error!(self.message);
         self
@@ -43,13 +54,17 @@ impl ResponseError {
 #[cfg(feature = "server")]
 impl IntoResponse for ResponseError {
     fn into_response(self) -> Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+        let status_code = self.status_code
+            .and_then(|v| StatusCode::from_u16(v).ok())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        (status_code, Json(self)).into_response()
     }
 }
 
 impl From<ResponseErrorStatic> for ResponseError {
     fn from(value: ResponseErrorStatic) -> Self {
         ResponseError {
+            status_code: None,
             code: value.code.to_string(),
             message: value.message.to_string(),
         }
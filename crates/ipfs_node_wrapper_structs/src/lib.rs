@@ -2,8 +2,11 @@
 
 pub mod admin;
 pub mod public;
+pub mod manager;
 pub mod errors;
 pub mod models;
+#[cfg(feature = "rate_limit")]
+pub mod rate_limit;
 mod common;
 
 pub use common::*;
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterNodeArgs {
+    /// Secret shared between Manager and Wrappers.
+    pub join_secret: String,
+    /// Peer id of the IPFS node.
+    pub peer_id: String,
+    /// Address of IPFS node's RPC, reachable by Manager.
+    pub rpc_address: String,
+    /// Public address of the Wrapper, reachable by users.
+    pub wrapper_public_address: String,
+    /// Admin address of the Wrapper, reachable by Manager.
+    pub wrapper_admin_address: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterNodeResponse {
+    /// Node's id in Manager.
+    pub node_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeartbeatArgs {
+    /// Secret shared between Manager and Wrappers.
+    pub join_secret: String,
+    /// Peer id of the IPFS node.
+    pub peer_id: String,
+}
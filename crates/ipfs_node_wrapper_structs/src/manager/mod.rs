@@ -0,0 +1,3 @@
+//! Types used when Wrapper contacts the Manager.
+
+pub mod dtos;
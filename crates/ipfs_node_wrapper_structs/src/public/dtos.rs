@@ -3,5 +3,10 @@ use serde::Deserialize;
 #[derive(Debug, Clone, Deserialize)]
 pub struct GetFileArgs {
     pub filename: Option<String>,
+    /// Unix timestamp in seconds, after which the signed URL is expired.
+    pub expires: Option<i64>,
+    /// User the URL is signed for.
+    pub user: Option<String>,
+    /// Hex of HMAC-SHA256. See `public::signature`.
+    pub signature: Option<String>,
 }
-
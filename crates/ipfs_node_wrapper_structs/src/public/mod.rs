@@ -1,2 +1,3 @@
-pub mod models;
 pub mod dtos;
+#[cfg(feature = "signature")]
+pub mod signature;
@@ -0,0 +1,88 @@
+//! Signed download URLs.
+//!
+//! The Manager signs `cid`, `expires` and optional `user` with a secret shared with Wrappers,
+//! and Wrappers verify it before serving the file.
+//! The signature is the hex of HMAC-SHA256 over `"{cid}\n{expires}\n{user}"`, where `user` is empty if absent.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::public::dtos::GetFileArgs;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    /// `expires` or `signature` is absent.
+    Missing,
+    Expired,
+    /// Not signed by the secret, or the URL is tampered.
+    Invalid,
+}
+
+fn new_mac(secret: &[u8], cid: &str, expires: i64, user: Option<&str>) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(format!("{cid}\n{expires}\n{}", user.unwrap_or_default()).as_bytes());
+    mac
+}
+
+/// Sign the download of `cid` until `expires` (unix timestamp in seconds).
+pub fn sign(secret: &[u8], cid: &str, expires: i64, user: Option<&str>) -> String {
+    hex::encode(new_mac(secret, cid, expires, user).finalize().into_bytes())
+}
+
+/// Query string to append to download URL, without leading `?`.
+///
+/// `user` is put into URL as is, so it should be URL safe, like ids of users.
+pub fn signed_query(secret: &[u8], cid: &str, expires: i64, user: Option<&str>) -> String {
+    let signature = sign(secret, cid, expires, user);
+    match user {
+        Some(user) => format!("expires={expires}&user={user}&signature={signature}"),
+        None => format!("expires={expires}&signature={signature}"),
+    }
+}
+
+/// Verify the signature in `args` of downloading `cid` at `now` (unix timestamp in seconds).
+pub fn verify(secret: &[u8], cid: &str, args: &GetFileArgs, now: i64) -> Result<(), SignatureError> {
+    let (Some(expires), Some(signature)) = (args.expires, args.signature.as_deref()) else {
+        return Err(SignatureError::Missing);
+    };
+    let signature = hex::decode(signature).map_err(|_| SignatureError::Invalid)?;
+    // verify before checking expiry, so that a tampered `expires` is reported as invalid
+    new_mac(secret, cid, expires, args.user.as_deref())
+        .verify_slice(&signature)
+        .map_err(|_| SignatureError::Invalid)?;
+    if expires < now {
+        return Err(SignatureError::Expired);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(expires: Option<i64>, user: Option<&str>, signature: Option<String>) -> GetFileArgs {
+        GetFileArgs {
+            filename: None,
+            expires,
+            user: user.map(str::to_owned),
+            signature,
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let secret = b"secret";
+        let signature = sign(secret, "cid", 100, Some("user"));
+        assert_eq!(verify(secret, "cid", &args(Some(100), Some("user"), Some(signature.clone())), 99), Ok(()));
+        assert_eq!(verify(secret, "cid", &args(Some(100), Some("user"), Some(signature.clone())), 101), Err(SignatureError::Expired));
+        assert_eq!(verify(secret, "cid", &args(Some(200), Some("user"), Some(signature.clone())), 99), Err(SignatureError::Invalid));
+        assert_eq!(verify(secret, "cid", &args(Some(100), None, Some(signature.clone())), 99), Err(SignatureError::Invalid));
+        assert_eq!(verify(secret, "other", &args(Some(100), Some("user"), Some(signature.clone())), 99), Err(SignatureError::Invalid));
+        assert_eq!(verify(b"other", "cid", &args(Some(100), Some("user"), Some(signature)), 99), Err(SignatureError::Invalid));
+        assert_eq!(verify(secret, "cid", &args(Some(100), None, Some("not hex".to_owned())), 99), Err(SignatureError::Invalid));
+        assert_eq!(verify(secret, "cid", &args(None, None, None), 99), Err(SignatureError::Missing));
+
+        assert_eq!(signed_query(secret, "cid", 100, None), format!("expires=100&signature={}", sign(secret, "cid", 100, None)));
+    }
+}
@@ -0,0 +1,215 @@
+//! Token bucket rate limiting, used by the Manager and Wrappers.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+/// A rate like `10/s`, `600/m` or `1000/h`.
+///
+/// At most `burst` requests are allowed at once, and they are refilled evenly in `period`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rate {
+    pub burst: u32,
+    pub period: Duration,
+}
+
+impl Rate {
+    fn tokens_per_sec(&self) -> f64 {
+        self.burst as f64 / self.period.as_secs_f64()
+    }
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Invalid rate: {s}. Should be like `10/s`, `600/m` or `1000/h`");
+        let (burst, unit) = s.trim().split_once('/').ok_or_else(err)?;
+        let burst: u32 = burst.trim().parse().map_err(|_| err())?;
+        let period = match unit.trim() {
+            "s" => Duration::from_secs(1),
+            "m" => Duration::from_secs(60),
+            "h" => Duration::from_secs(3600),
+            _ => return Err(err()),
+        };
+        if burst == 0 {
+            return Err(err());
+        }
+        Ok(Rate { burst, period })
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self.period.as_secs() {
+            1 => "s",
+            60 => "m",
+            3600 => "h",
+            _ => return write!(f, "{}/{}s", self.burst, self.period.as_secs_f64()),
+        };
+        write!(f, "{}/{}", self.burst, unit)
+    }
+}
+
+impl TryFrom<String> for Rate {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Rate> for String {
+    fn from(value: Rate) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// State of the bucket of a key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketState {
+    pub key: String,
+    /// Requests allowed right now.
+    pub tokens: f64,
+}
+
+/// Limit the rate of requests of each key, like client IP.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: Rate,
+    buckets: scc::HashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: Rate) -> Self {
+        RateLimiter {
+            rate,
+            buckets: scc::HashMap::new(),
+        }
+    }
+
+    pub fn rate(&self) -> Rate {
+        self.rate
+    }
+
+    /// Take a token of the key. Return how long to wait if there is none.
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut entry = self.buckets.entry(key.to_owned()).or_insert(Bucket {
+            tokens: self.rate.burst as f64,
+            updated: now,
+        });
+        let bucket = entry.get_mut();
+        bucket.tokens = self.refilled_tokens(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate.tokens_per_sec()))
+    }
+
+    /// Give back a token taken by `check`, e.g. when the request is rejected by another limiter.
+    pub fn refund(&self, key: &str) {
+        let burst = self.rate.burst as f64;
+        self.buckets.update(key, |_, bucket| bucket.tokens = (bucket.tokens + 1.0).min(burst));
+    }
+
+    fn refilled_tokens(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate.tokens_per_sec()).min(self.rate.burst as f64)
+    }
+
+    /// States of all buckets, ascending by tokens.
+    pub fn states(&self) -> Vec<BucketState> {
+        let now = Instant::now();
+        let mut list = Vec::with_capacity(self.buckets.len());
+        self.buckets.scan(|k, v| list.push(BucketState {
+            key: k.clone(),
+            tokens: self.refilled_tokens(v, now),
+        }));
+        list.sort_by(|a, b| a.tokens.total_cmp(&b.tokens));
+        list
+    }
+
+    /// Forget the keys with full buckets, which behave the same as unknown keys.
+    /// Should be called regularly to free memory.
+    pub fn purge_full(&self) {
+        let now = Instant::now();
+        let burst = self.rate.burst as f64;
+        self.buckets.retain(|_, v| self.refilled_tokens(v, now) < burst);
+    }
+}
+
+/// Take a token of each key from its limiter. Return how long to wait if any is exhausted,
+/// and then the tokens already taken are given back, so a rejected request costs nothing.
+pub fn check_all<'a>(limits: impl IntoIterator<Item = (&'a RateLimiter, &'a str)>) -> Result<(), Duration> {
+    let mut taken: Vec<(&RateLimiter, &str)> = Vec::new();
+    for (limiter, key) in limits {
+        if let Err(wait) = limiter.check(key) {
+            for (limiter, key) in taken {
+                limiter.refund(key);
+            }
+            return Err(wait);
+        }
+        taken.push((limiter, key));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!("10/s".parse::<Rate>().unwrap(), Rate { burst: 10, period: Duration::from_secs(1) });
+        assert_eq!("600/m".parse::<Rate>().unwrap().to_string(), "600/m");
+        assert!("0/s".parse::<Rate>().is_err());
+        assert!("10/d".parse::<Rate>().is_err());
+        assert!("10".parse::<Rate>().is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new("2/s".parse().unwrap());
+        let now = Instant::now();
+        assert!(limiter.check_at("a", now).is_ok());
+        assert!(limiter.check_at("a", now).is_ok());
+        let wait = limiter.check_at("a", now).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+        // keys are independent
+        assert!(limiter.check_at("b", now).is_ok());
+        // refilled
+        assert!(limiter.check_at("a", now + Duration::from_millis(500)).is_ok());
+        assert!(limiter.check_at("a", now + Duration::from_millis(500)).is_err());
+
+        limiter.purge_full();
+        assert_eq!(limiter.states().len(), 2);
+    }
+
+    #[test]
+    fn test_check_all() {
+        let per_ip = RateLimiter::new("2/m".parse().unwrap());
+        let per_user = RateLimiter::new("1/m".parse().unwrap());
+        assert!(check_all([(&per_ip, "ip"), (&per_user, "user")]).is_ok());
+        // rejected by the user, without taking the token of the ip
+        for _ in 0..3 {
+            assert!(check_all([(&per_ip, "ip"), (&per_user, "user")]).is_err());
+        }
+        assert!(check_all([(&per_ip, "ip"), (&per_user, "other user")]).is_ok());
+        assert!(check_all([(&per_ip, "ip")]).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use axum::extract::Path;
+use axum::extract::{Path, State};
 use tracing::info;
 use axum::Json;
 use axum::async_trait;
@@ -28,7 +28,9 @@ struct MyApi {}
 
 #[async_trait]
 impl IpfsPinServiceApi for MyApi {
-    async fn get_pins(token: AuthContext, EnhancedQuery(get_pins_args): EnhancedQuery<GetPinsArgs>)
+    type State = ();
+
+    async fn get_pins(State(_state): State<()>, token: AuthContext, EnhancedQuery(get_pins_args): EnhancedQuery<GetPinsArgs>)
                       -> ApiResponse<GetPinsResponse> {
         info!("get_pins args: {:?}", get_pins_args);
         info!("get_pins auth: {:?}", token.token());
@@ -63,7 +65,7 @@ impl IpfsPinServiceApi for MyApi {
         ])))
     }
 
-    async fn add_pin(token: AuthContext, Json(pin): Json<Pin>) -> ApiResponse<AddPinResponse> {
+    async fn add_pin(State(_state): State<()>, token: AuthContext, Json(pin): Json<Pin>) -> ApiResponse<AddPinResponse> {
         info!("add_pin args: {:?}", pin);
         info!("add_pin auth: {:?}", token.token());
         Ok(AddPinResponse::new(PinStatus::new(
@@ -81,7 +83,7 @@ impl IpfsPinServiceApi for MyApi {
         )))
     }
 
-    async fn get_pin_by_request_id(token: AuthContext, Path(requestid): Path<String>) -> ApiResponse<GetPinByRequestIdResponse> {
+    async fn get_pin_by_request_id(State(_state): State<()>, token: AuthContext, Path(requestid): Path<String>) -> ApiResponse<GetPinByRequestIdResponse> {
         info!("get_pin_by_request_id args: {:?}", requestid);
         info!("get_pin_by_request_id auth: {:?}", token.token());
         Ok(GetPinByRequestIdResponse::new(PinStatus::new(
@@ -99,7 +101,7 @@ impl IpfsPinServiceApi for MyApi {
         ))
     }
 
-    async fn replace_pin_by_request_id(token: AuthContext, Path(requestid): Path<String>, Json(pin): Json<Pin>) -> ApiResponse<AddPinResponse> {
+    async fn replace_pin_by_request_id(State(_state): State<()>, token: AuthContext, Path(requestid): Path<String>, Json(pin): Json<Pin>) -> ApiResponse<AddPinResponse> {
         info!("replace_pin_by_request_id args: {:?}: {:?}", requestid, pin);
         info!("replace_pin_by_request_id auth: {:?}", token.token());
         Ok(AddPinResponse::new(PinStatus::new(
@@ -117,7 +119,7 @@ impl IpfsPinServiceApi for MyApi {
         ))
     }
 
-    async fn delete_pin_by_request_id(token: AuthContext, Path(requestid): Path<String>) -> ApiResponse<DeletePinByRequestIdResponse> {
+    async fn delete_pin_by_request_id(State(_state): State<()>, token: AuthContext, Path(requestid): Path<String>) -> ApiResponse<DeletePinByRequestIdResponse> {
         info!("delete_pin_by_request_id args: {:?}", requestid);
         info!("delete_pin_by_request_id auth: {:?}", token.token());
         Ok(DeletePinByRequestIdResponse::new())
@@ -1,7 +1,7 @@
 use std::fmt::Debug;
 use axum::{http, Json, response, Router};
 use axum::routing::{get, post, delete};
-use axum::extract::{FromRequestParts, Path};
+use axum::extract::{FromRequestParts, Path, State};
 use axum::http::request::Parts;
 use axum::response::IntoResponse;
 use axum::async_trait;
@@ -19,20 +19,26 @@ pub type ApiResponse<T> = Result<T, ResponseError>;
 /// Should be `'static`.
 #[async_trait]
 pub trait IpfsPinServiceApi {
+    /// State of the router, passed to every API like `axum::extract::State`. Use `()` if not needed.
+    type State: Clone + Send + Sync + 'static;
+
     /// List pin objects.
     async fn get_pins(
+        State(state): State<Self::State>,
         token: AuthContext,
         EnhancedQuery(get_pins_args): EnhancedQuery<dto::GetPinsArgs>,
     ) -> ApiResponse<dto::GetPinsResponse>;
 
     /// Add pin object.
     async fn add_pin(
+        State(state): State<Self::State>,
         token: AuthContext,
         Json(pin): Json<models::Pin>,
     ) -> ApiResponse<dto::AddPinResponse>;
 
     /// Get pin object.
     async fn get_pin_by_request_id(
+        State(state): State<Self::State>,
         token: AuthContext,
         Path(requestid): Path<String>,
     ) -> ApiResponse<dto::GetPinByRequestIdResponse>;
@@ -47,6 +53,7 @@ pub trait IpfsPinServiceApi {
     /// **NOTE**: **Replace pin** and **Add pin** are basically equivalent in response to business needs,
     /// so `replace_pin_by_request_id` returns `vo::AddPinResponse`
     async fn replace_pin_by_request_id(
+        State(state): State<Self::State>,
         token: AuthContext,
         Path(requestid): Path<String>,
         Json(pin): Json<models::Pin>,
@@ -54,13 +61,15 @@ pub trait IpfsPinServiceApi {
 
     /// Remove pin object.
     async fn delete_pin_by_request_id(
+        State(state): State<Self::State>,
         token: AuthContext,
         Path(requestid): Path<String>,
     ) -> ApiResponse<dto::DeletePinByRequestIdResponse>;
 }
 
 /// Generate axum router by type impl `IpfsPinServiceApi`.
-pub fn generate_router<T>() -> Router
+/// Call `with_state` on it to provide `T::State`.
+pub fn generate_router<T>() -> Router<T::State>
     where T: IpfsPinServiceApi + 'static {
     let ipfs_pin_service_app = Router::new()
         .route("/", get(T::get_pins))
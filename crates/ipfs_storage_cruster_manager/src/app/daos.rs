@@ -25,21 +25,247 @@ pub async fn find_ipfs_node_rpc_by_id(node_id: String, db_conn: &DatabaseConnect
 /// Find all nodes that store the pin with certain CID.
 pub async fn find_nodes_with_pin_cid(cid: &str, db_conn: &DatabaseConnection) -> DbResult<Vec<TargetPublicWrapperMessage>> {
     Node::find()
-        .join(
-            JoinType::InnerJoin,
-            Node::belongs_to(PinsStoredNodes)
-                .from(node::Column::Id)
-                .to(pins_stored_nodes::Column::NodeId)
-                .into(),
-        )
-        .join(
-            JoinType::InnerJoin,
-            PinsStoredNodes::belongs_to(Pin)
-                .from(pins_stored_nodes::Column::PinId)
-                .to(pin::Column::Id)
-                .into(),
-        )
+        .inner_join(Pin)
         .filter(pin::Column::Cid.eq(cid))
         .into_partial_model::<TargetPublicWrapperMessage>()
         .all(db_conn).await
 }
+
+/// Find the admin addresses of Wrappers whose node is not `Offline`.
+///
+/// Return `(node_id, wrapper_admin_address)`. Nodes without admin address are skipped.
+pub async fn find_available_wrapper_admin_addresses(db_conn: &DatabaseConnection) -> DbResult<Vec<(String, String)>> {
+    #[derive(DerivePartialModel, FromQueryResult)]
+    #[sea_orm(entity = "Node")]
+    struct PartialNode {
+        id: String,
+        wrapper_admin_address: Option<String>,
+    }
+    let models = Node::find()
+        .filter(node::Column::NodeStatus.ne(sea_orm_active_enums::NodeStatus::Offline))
+        .into_partial_model::<PartialNode>()
+        .all(db_conn).await?;
+
+    let res = models.into_iter()
+        .filter_map(|v| v.wrapper_admin_address.map(|addr| (v.id, addr)))
+        .collect();
+    Ok(res)
+}
+
+/// Set the status of a node if it's still `old_status`,
+/// so that a status set by others meanwhile, like `Maintenance` by admin, is not overwritten.
+///
+/// Return false if nothing is updated.
+pub async fn update_node_status_from(node_id: &str,
+                                     old_status: sea_orm_active_enums::NodeStatus,
+                                     new_status: sea_orm_active_enums::NodeStatus,
+                                     db_conn: &DatabaseConnection) -> DbResult<bool> {
+    let res = Node::update_many()
+        .col_expr(node::Column::NodeStatus, Expr::value(new_status))
+        .filter(node::Column::Id.eq(node_id))
+        .filter(node::Column::NodeStatus.eq(old_status))
+        .exec(db_conn).await?;
+    Ok(res.rows_affected > 0)
+}
+
+/// Pattern of `LIKE` to match the text containing `text`, in which `%`, `_` and `\` are matched literally.
+pub fn contains_like_pattern(text: &str) -> sea_query::LikeExpr {
+    let escaped = text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    sea_query::LikeExpr::new(format!("%{escaped}%")).escape('\\')
+}
+
+/// Condition that the value of `key` in the `meta` column of `table` equals `value`.
+///
+/// `table` is `pin` or `users_pins`.
+pub fn meta_eq_condition(backend: DbBackend, table: &str, key: &str, value: &str) -> sea_query::SimpleExpr {
+    let json_path = format!("$.\"{}\"", key.replace('\\', "\\\\").replace('"', "\\\""));
+    match backend {
+        DbBackend::MySql => Expr::cust_with_values(
+            format!("JSON_UNQUOTE(JSON_EXTRACT(`{table}`.`meta`, ?)) = ?"), [json_path, value.to_owned()]),
+        DbBackend::Postgres => Expr::cust_with_values(
+            format!(r#""{table}"."meta" ->> ? = ?"#), [key.to_owned(), value.to_owned()]),
+        DbBackend::Sqlite => Expr::cust_with_values(
+            format!(r#"json_extract("{table}"."meta", ?) = ?"#), [json_path, value.to_owned()]),
+    }
+}
+
+/// Set the status of a pin, and update its updated time.
+pub async fn update_pin_status<C: ConnectionTrait>(pin_id: &str, status: sea_orm_active_enums::Status, db_conn: &C) -> DbResult<()> {
+    Pin::update_many()
+        .col_expr(pin::Column::Status, Expr::value(status))
+        .col_expr(pin::Column::Updated, Expr::value(chrono::Utc::now()))
+        .filter(pin::Column::Id.eq(pin_id))
+        .exec(db_conn).await?;
+    Ok(())
+}
+
+/// Mark the expired pin as `Deleted` to unpin it,
+/// unless it's extended or deleted after read, e.g. by an upload of the same CID.
+///
+/// Return true if the pin is claimed.
+pub async fn claim_expired_pin(pin_id: &str, now: DateTimeUtc, db_conn: &DatabaseConnection) -> DbResult<bool> {
+    let res = Pin::update_many()
+        .col_expr(pin::Column::Status, Expr::value(sea_orm_active_enums::Status::Deleted))
+        .col_expr(pin::Column::Updated, Expr::value(now))
+        .filter(pin::Column::Id.eq(pin_id))
+        .filter(pin::Column::ExpiresAt.lte(now))
+        .filter(pin::Column::Status.ne(sea_orm_active_enums::Status::Deleted))
+        .exec(db_conn).await?;
+    Ok(res.rows_affected > 0)
+}
+
+/// Keep the pin at least until `expires_at`, which is `None` for forever.
+///
+/// The expiry time is never brought forward, because the pin might be shared.
+/// It's compared in database, since the pin might be changed after read.
+///
+/// Return false if the pin is `Deleted` (e.g. claimed by the expiry reaper), in which case nothing is changed.
+pub async fn keep_pin_until(pin_id: &str, expires_at: Option<DateTimeUtc>, db_conn: &DatabaseConnection) -> DbResult<bool> {
+    let mut update = Pin::update_many()
+        .col_expr(pin::Column::ExpiresAt, Expr::value(expires_at))
+        .col_expr(pin::Column::Updated, Expr::value(chrono::Utc::now()))
+        .filter(pin::Column::Id.eq(pin_id))
+        .filter(pin::Column::Status.ne(sea_orm_active_enums::Status::Deleted))
+        .filter(pin::Column::ExpiresAt.is_not_null());
+    if let Some(expires_at) = expires_at {
+        update = update.filter(pin::Column::ExpiresAt.lt(expires_at));
+    }
+    let res = update.exec(db_conn).await?;
+    if res.rows_affected > 0 {
+        return Ok(true);
+    }
+    // no need to extend, or deleted
+    let alive = Pin::find_by_id(pin_id)
+        .filter(pin::Column::Status.ne(sea_orm_active_enums::Status::Deleted))
+        .count(db_conn).await? > 0;
+    Ok(alive)
+}
+
+/// Find all pins stored in the node, which have no other replica on an `Online` node.
+///
+/// Replicas on nodes not `Online` are not counted, since they might never come back.
+pub async fn find_pins_only_stored_in_node(node_id: &str, db_conn: &DatabaseConnection) -> DbResult<Vec<pin::Model>> {
+    let pin_ids: Vec<String> = PinsStoredNodes::find()
+        .select_only()
+        .column(pins_stored_nodes::Column::PinId)
+        .filter(pins_stored_nodes::Column::NodeId.eq(node_id))
+        .into_tuple()
+        .all(db_conn).await?;
+    if pin_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let pin_ids_with_other_replica: Vec<String> = PinsStoredNodes::find()
+        .select_only()
+        .column(pins_stored_nodes::Column::PinId)
+        .distinct()
+        .inner_join(Node)
+        .filter(pins_stored_nodes::Column::PinId.is_in(pin_ids.clone()))
+        .filter(pins_stored_nodes::Column::NodeId.ne(node_id))
+        .filter(node::Column::NodeStatus.eq(sea_orm_active_enums::NodeStatus::Online))
+        .into_tuple()
+        .all(db_conn).await?;
+    let sole_pin_ids: Vec<String> = pin_ids.into_iter()
+        .filter(|v| !pin_ids_with_other_replica.contains(v))
+        .collect();
+    if sole_pin_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    Pin::find()
+        .filter(pin::Column::Id.is_in(sole_pin_ids))
+        .all(db_conn).await
+}
+
+/// Check whether the user has a pin request of the CID.
+pub async fn has_pin_request_of_cid(user_id: &str, cid: &str, db_conn: &DatabaseConnection) -> DbResult<bool> {
+    let count = UsersPins::find()
+        .inner_join(Pin)
+        .filter(users_pins::Column::UserId.eq(user_id))
+        .filter(pin::Column::Cid.eq(cid))
+        .count(db_conn).await?;
+    Ok(count > 0)
+}
+
+/// Insert a pin request of the user.
+///
+/// `meta` and `origins` belong to the request, because the pin might be shared by requests of other users.
+pub async fn insert_pin_request<C: ConnectionTrait>(user_id: &str,
+                                                    pin_id: &str,
+                                                    pin_name: Option<String>,
+                                                    meta: Option<Json>,
+                                                    origins: Option<Json>,
+                                                    db: &C)
+                                                    -> DbResult<users_pins::Model> {
+    new_pin_request(user_id, pin_id, pin_name, meta, origins)
+        .into_active_model()
+        .insert(db).await
+}
+
+/// A pin request to insert.
+pub fn new_pin_request(user_id: &str,
+                       pin_id: &str,
+                       pin_name: Option<String>,
+                       meta: Option<Json>,
+                       origins: Option<Json>) -> users_pins::Model {
+    users_pins::Model {
+        id: Uuid::new_v4().to_string(),
+        user_id: user_id.to_owned(),
+        pin_id: pin_id.to_owned(),
+        pin_name,
+        created: chrono::Utc::now(),
+        meta,
+        origins,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::daos;
+    use crate::repository::Repository;
+    use crate::repository::seaorm::SeaOrmRepository;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn try_update_node_status_from() {
+        let conn = connect_test_db().await;
+        insert_test_node(test_node("node", "1.1.1.1:5001"), &conn).await;
+        // set by admin during a check
+        daos::update_node_status_from("node", sea_orm_active_enums::NodeStatus::Online,
+                                      sea_orm_active_enums::NodeStatus::Maintenance, &conn).await.unwrap();
+        let updated = daos::update_node_status_from("node", sea_orm_active_enums::NodeStatus::Online,
+                                                    sea_orm_active_enums::NodeStatus::Unhealthy, &conn).await.unwrap();
+        assert!(!updated);
+        let node_model = Node::find_by_id("node").one(&conn).await.unwrap().unwrap();
+        assert_eq!(node_model.node_status, sea_orm_active_enums::NodeStatus::Maintenance);
+    }
+
+    #[tokio::test]
+    async fn try_find_pins_only_stored_in_node() {
+        let conn = connect_test_db().await;
+        let repository = SeaOrmRepository::new(conn.clone());
+        for (id, node_status) in [
+            ("a", sea_orm_active_enums::NodeStatus::Online),
+            ("b", sea_orm_active_enums::NodeStatus::Offline),
+            ("c", sea_orm_active_enums::NodeStatus::Online),
+        ] {
+            insert_test_node(node::Model {
+                node_status,
+                ..test_node(id, &format!("{id}:5001"))
+            }, &conn).await;
+        }
+        for (pin_id, node_ids) in [("pin1", ["a", "b"]), ("pin2", ["a", "c"])] {
+            repository.insert_pin(test_pin(pin_id, pin_id)).await.unwrap();
+            repository.add_replicas(pin_id, node_ids.map(str::to_string).to_vec()).await.unwrap();
+        }
+
+        // the replica on an offline node is not counted
+        let sole_pins = daos::find_pins_only_stored_in_node("a", &conn).await.unwrap();
+        let sole_pin_ids: Vec<_> = sole_pins.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(sole_pin_ids, ["pin1"]);
+        assert!(daos::find_pins_only_stored_in_node("c", &conn).await.unwrap().is_empty());
+    }
+}
@@ -1,5 +1,11 @@
 use serde::{Serialize, Deserialize};
+use deserialize_form_style_query_parameter::option_form_vec_deserialize;
 use ipfs_storage_cruster_manager_entity::*;
+use crate::file_decision::NodeDownloadEstimate;
+use crate::app::services::health::NodeHealthRecord;
+use crate::app::services::peering::NodeConnectivity;
+use crate::app::services::quota::{Quota, Usage};
+use crate::app::services::rate_limit::RateLimiterState;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all(deserialize = "PascalCase", serialize = "camelCase"))]
@@ -9,6 +15,15 @@ pub struct IpfsAddFileResponse {
     pub size: String,
 }
 
+/// Expiry of a pin. Use one of them, or none to never expire.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinExpiryArgs {
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Expire after these seconds.
+    pub ttl_secs: Option<u64>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UploadFileResponse {
@@ -26,6 +41,18 @@ pub struct DownloadFileAdviceArgs {
 #[serde(rename_all = "camelCase")]
 pub struct DownloadFileAdviceResponse {
     pub url: String,
+    /// Id of the node to download from. Used to report download feedback.
+    pub node_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportDownloadArgs {
+    pub node_id: String,
+    /// Size of the downloaded file in bytes.
+    pub size: Option<u64>,
+    /// Time spent on downloading.
+    pub duration_ms: u64,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -34,6 +61,40 @@ pub struct ListIpfsNodesResponse {
     pub list: Vec<node::Model>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListDownloadEstimatesResponse {
+    pub list: Vec<NodeDownloadEstimate>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeHealth {
+    pub node_id: String,
+    #[serde(flatten)]
+    pub record: NodeHealthRecord,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListNodesHealthResponse {
+    pub list: Vec<NodeHealth>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeConnectivityWithId {
+    pub node_id: String,
+    #[serde(flatten)]
+    pub connectivity: NodeConnectivity,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListNodesConnectivityResponse {
+    pub list: Vec<NodeConnectivityWithId>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AddIpfsNodeArgs {
@@ -42,6 +103,120 @@ pub struct AddIpfsNodeArgs {
     pub wrapper_admin_address: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveIpfsNodeArgs {
+    /// Re-home the pins whose only replica is in the node, instead of refusing.
+    pub force: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveIpfsNodeResponse {
+    /// CIDs of the pins moved to other nodes.
+    pub rehomed_pins_cid: Vec<String>,
+}
+
+/// Strategy to match the name of pins.
+///
+/// `Exact` is case-sensitive, except under the default collation of MySQL.
+/// `Partial` is only case-sensitive in PostgreSQL, since `LIKE` ignores ASCII case in SQLite and MySQL by default.
+/// `%`, `_` and `\` in the name are matched literally.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NameMatchStrategy {
+    #[default]
+    Exact,
+    /// Case-insensitive exact match.
+    Iexact,
+    Partial,
+    /// Case-insensitive partial match.
+    Ipartial,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListPinsArgs {
+    /// Like `cid=a,b,c`.
+    #[serde(deserialize_with = "option_form_vec_deserialize", default)]
+    pub cid: Option<Vec<String>>,
+    /// Like `status=Pinned,Failed`.
+    #[serde(deserialize_with = "option_form_vec_deserialize", default)]
+    pub status: Option<Vec<String>>,
+    pub name: Option<String>,
+    pub name_match: Option<NameMatchStrategy>,
+    /// Only pins created before it.
+    pub before: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only pins created after it.
+    pub after: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only pins whose meta has `metaKey` equal to `metaValue`.
+    pub meta_key: Option<String>,
+    pub meta_value: Option<String>,
+    /// Default 100, max 1000.
+    pub limit: Option<u64>,
+    /// `nextCursor` of the previous page.
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListPinsResponse {
+    /// The number of all matched pins.
+    pub count: u64,
+    /// Pins in this page, newest first.
+    pub list: Vec<pin::Model>,
+    /// Cursor of the next page. `None` if no more.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListExpiringPinsArgs {
+    /// List pins expire in these seconds. Default 1 day.
+    pub within_secs: Option<u64>,
+    /// Default 100, max 1000.
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListExpiringPinsResponse {
+    /// Pins expire before this time.
+    pub before: chrono::DateTime<chrono::Utc>,
+    /// Pins going to expire first come first.
+    pub list: Vec<pin::Model>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListAuditLogArgs {
+    /// Only entries before it.
+    pub before: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only entries after it.
+    pub after: Option<chrono::DateTime<chrono::Utc>>,
+    pub actor: Option<String>,
+    /// Like `action=pin,unpin`.
+    #[serde(deserialize_with = "option_form_vec_deserialize", default)]
+    pub action: Option<Vec<String>>,
+    pub cid: Option<String>,
+    pub node_id: Option<String>,
+    /// `success` or `failure`.
+    pub outcome: Option<String>,
+    /// Default 100, max 1000.
+    pub limit: Option<u64>,
+    /// `nextCursor` of the previous page.
+    pub cursor: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListAuditLogResponse {
+    /// Entries in this page, newest first.
+    pub list: Vec<audit_log::Model>,
+    /// Cursor of the next page. `None` if no more.
+    pub next_cursor: Option<i64>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ListPinsInOneNodeActuallyArgs {
@@ -81,3 +256,52 @@ pub struct ListNodesWithPinResponse {
     pub pin_id: String,
     pub nodes: Vec<node::Model>,
 }
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateUserArgs {
+    pub name: String,
+    /// Default `user`.
+    pub role: Option<sea_orm_active_enums::UserRole>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListUsersResponse {
+    pub list: Vec<users::Model>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateApiTokenArgs {
+    /// Note to tell tokens apart.
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateApiTokenResponse {
+    /// Only shown here. Keep it secret.
+    pub token: String,
+    pub api_token: api_token::Model,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListApiTokensResponse {
+    pub list: Vec<api_token::Model>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageResponse {
+    pub usage: Usage,
+    /// Quota in effect, including the defaults.
+    pub quota: Quota,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListRateLimitersResponse {
+    pub list: Vec<RateLimiterState>,
+}
@@ -1,4 +1,6 @@
+use axum::http::StatusCode;
 use tiny_ipfs_client::IpfsClientError;
+use ipfs_node_wrapper_client::common::{ClientErrorType, CommunicationErrorType};
 use crate::app::errors::*;
 
 impl From<IpfsClientError> for ResponseError {
@@ -11,6 +13,21 @@ impl From<IpfsClientError> for ResponseError {
             IpfsClientError::RpcReject => IPFS_REQUEST_ERROR.clone_to_error(),
             IpfsClientError::RpcInternalServerError => IPFS_RESPOND_ERROR.clone_to_error(),
             IpfsClientError::UnexpectedResponseBody => IPFS_FAIL.clone_to_error(),
+            IpfsClientError::Timeout => IPFS_TIMEOUT.clone_to_error()
+                .modify_status_code(StatusCode::GATEWAY_TIMEOUT),
+        }
+    }
+}
+
+impl From<ClientErrorType> for ResponseError {
+    fn from(value: ClientErrorType) -> Self {
+        match value {
+            ClientErrorType::ServerExplictError(e) => WRAPPER_RESPOND_ERROR.clone_to_error()
+                .modify_msg(&e.message),
+            ClientErrorType::CommunicationError(e) => match e {
+                CommunicationErrorType::RequestError => WRAPPER_CLIENT_ERROR.clone_to_error(),
+                _ => WRAPPER_FAIL.clone_to_error(),
+            },
         }
     }
 }
@@ -9,8 +9,16 @@ macro_rules! define_static_error {
     };
 }
 
+define_static_error!(PERMISSION_DENIED, "A0300", "Permission denied");
+
+define_static_error!(REQUEST_ARGS_ERROR, "A0400", "Request arguments error");
+define_static_error!(RATE_LIMITED, "A0501", "Too many requests");
+define_static_error!(QUOTA_EXCEEDED, "A0604", "Quota exceeded");
+
 define_static_error!(DB_DATA_FAIL, "A1100", "Error about data in database");
 define_static_error!(DB_TARGET_DATA_NOT_EXIST, "A1101", "Target data doesn't exist in database");
+define_static_error!(IPFS_NODE_HOLDS_SOLE_REPLICA, "A1102", "IPFS node holds the only replica of some pins");
+define_static_error!(PIN_EXPIRED, "A1103", "Pin has expired and been deleted");
 
 define_static_error!(SYSTEM_EXECUTION_ERROR, "B0001", "Error in system");
 
@@ -21,5 +29,12 @@ define_static_error!(IPFS_CLIENT_ERROR, "C0601", "Error when send request to IPF
 define_static_error!(IPFS_NOT_FOUND, "C0602", "IPFS not found");
 define_static_error!(IPFS_REQUEST_ERROR, "C0603", "IPFS node rejects the request");
 define_static_error!(IPFS_RESPOND_ERROR, "C0604", "IPFS node responds an error");
+define_static_error!(IPFS_TIMEOUT, "C0605", "IPFS node did not respond in time");
 define_static_error!(IPFS_NODE_CLUSTER_ERROR, "C0650", "Error about IPFS node cluster");
 define_static_error!(IPFS_NODE_CLUSTER_UNHEALTHY, "C0650", "IPFS node cluster is too unhealthy to finish the task");
+
+define_static_error!(WRAPPER_FAIL, "C0700", "Error about Wrapper");
+define_static_error!(WRAPPER_CLIENT_ERROR, "C0701", "Error when send request to Wrapper");
+define_static_error!(WRAPPER_RESPOND_ERROR, "C0702", "Wrapper responds an error");
+define_static_error!(WRAPPER_PIN_FAILED, "C0703", "Wrapper failed to pin");
+define_static_error!(WRAPPER_PIN_TIMEOUT, "C0704", "Wrapper pin timeout");
@@ -0,0 +1,80 @@
+//! API about audit log.
+
+#[allow(unused_imports)]
+use tracing::{trace, debug, info};
+use axum::extract::{State, Query};
+use axum::http::StatusCode;
+use crate::imports::dao_imports::*;
+use crate::app::AppState;
+use crate::app::common::StandardApiResult;
+use crate::app::{dtos, errors, services};
+
+/// List audit log with filters, newest first.
+///
+/// Paginate by `limit` and `cursor`.
+// #[axum_macros::debug_handler]
+pub async fn list_audit_log(State(state): State<AppState>, Query(args): Query<dtos::ListAuditLogArgs>)
+                            -> StandardApiResult<dtos::ListAuditLogResponse> {
+    const DEFAULT_LIMIT: u64 = 100;
+    const MAX_LIMIT: u64 = 1000;
+    debug!("List audit log. {:?}", args);
+    let args_error = |msg: &'static str| errors::REQUEST_ARGS_ERROR.clone_to_error()
+        .modify_msg(msg)
+        .modify_status_code(StatusCode::BAD_REQUEST);
+    let limit = args.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(args_error("limit should be in [1, 1000]"));
+    }
+
+    let mut query = AuditLog::find();
+    if let Some(before) = args.before {
+        query = query.filter(audit_log::Column::Created.lt(before));
+    }
+    if let Some(after) = args.after {
+        query = query.filter(audit_log::Column::Created.gt(after));
+    }
+    if let Some(actor) = args.actor {
+        query = query.filter(audit_log::Column::Actor.eq(actor));
+    }
+    if let Some(action) = args.action {
+        let action = action.into_iter()
+            .map(|v| sea_orm_active_enums::AuditAction::try_from_value(&v))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| args_error("Invalid action"))?;
+        query = query.filter(audit_log::Column::Action.is_in(action));
+    }
+    if let Some(cid) = args.cid {
+        query = query.filter(audit_log::Column::Cid.eq(cid));
+    }
+    if let Some(node_id) = args.node_id {
+        query = query.filter(audit_log::Column::NodeId.eq(node_id));
+    }
+    if let Some(outcome) = args.outcome {
+        let outcome = sea_orm_active_enums::AuditOutcome::try_from_value(&outcome)
+            .map_err(|_| args_error("Invalid outcome"))?;
+        query = query.filter(audit_log::Column::Outcome.eq(outcome));
+    }
+    // ids increase with time, since the log is append-only
+    if let Some(cursor) = args.cursor {
+        query = query.filter(audit_log::Column::Id.lt(cursor));
+    }
+
+    let mut list = query
+        .order_by_desc(audit_log::Column::Id)
+        .limit(limit + 1)
+        .all(&state.db_conn).await
+        .map_err(services::db::handle_db_error)?;
+    let next_cursor = if list.len() as u64 > limit {
+        list.truncate(limit as usize);
+        list.last().map(|v| v.id)
+    } else {
+        None
+    };
+
+    debug!("Find {} audit log entries", list.len());
+    let res = dtos::ListAuditLogResponse {
+        list,
+        next_cursor,
+    };
+    Ok(res.into())
+}
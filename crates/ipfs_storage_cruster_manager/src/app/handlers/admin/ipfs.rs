@@ -2,11 +2,13 @@
 
 #[allow(unused_imports)]
 use tracing::{trace, debug, info};
-use axum::extract::{State, Json};
+use axum::extract::{State, Json, Path, Query};
 use crate::imports::dao_imports::*;
 use crate::app::AppState;
 use crate::app::common::StandardApiResult;
 use crate::app::{dtos, services};
+use crate::app::services::audit::{AuditAction, AuditEntry};
+use crate::app::services::auth::AuthUser;
 
 /// List all added IPFS nodes.
 // #[axum_macros::debug_handler]
@@ -22,61 +24,164 @@ pub async fn list_ipfs_nodes(State(state): State<AppState>) -> StandardApiResult
     Ok(res.into())
 }
 
+/// List the estimates of nodes used by the download decision maker.
+// #[axum_macros::debug_handler]
+pub async fn list_download_estimates(State(state): State<AppState>) -> StandardApiResult<dtos::ListDownloadEstimatesResponse> {
+    info!("List download estimates");
+    let list = state.file_download_decision_maker
+        .list_node_estimates().await?;
+    let res = dtos::ListDownloadEstimatesResponse {
+        list
+    };
+
+    Ok(res.into())
+}
+
+/// List the health records of nodes kept by the health monitor.
+// #[axum_macros::debug_handler]
+pub async fn list_nodes_health(State(state): State<AppState>) -> StandardApiResult<dtos::ListNodesHealthResponse> {
+    info!("List nodes health");
+    let mut list = Vec::with_capacity(state.node_health_records.len());
+    state.node_health_records.scan_async(|k, v| {
+        list.push(dtos::NodeHealth {
+            node_id: k.clone(),
+            record: v.clone(),
+        });
+    }).await;
+    let res = dtos::ListNodesHealthResponse {
+        list
+    };
+
+    Ok(res.into())
+}
+
+/// List the swarm connectivity of nodes kept by the peering maintainer.
+// #[axum_macros::debug_handler]
+pub async fn list_nodes_connectivity(State(state): State<AppState>) -> StandardApiResult<dtos::ListNodesConnectivityResponse> {
+    info!("List nodes connectivity");
+    let mut list = Vec::with_capacity(state.node_connectivity.len());
+    state.node_connectivity.scan_async(|k, v| {
+        list.push(dtos::NodeConnectivityWithId {
+            node_id: k.clone(),
+            connectivity: v.clone(),
+        });
+    }).await;
+    let res = dtos::ListNodesConnectivityResponse {
+        list
+    };
+
+    Ok(res.into())
+}
+
 /// Let target IPFS node bootstrap self.
 /// Would set the status of node to `Online`.
 /// Upsert the database entry.
 // #[axum_macros::debug_handler]
-pub async fn add_ipfs_node(State(state): State<AppState>, Json(args): Json<dtos::AddIpfsNodeArgs>) -> StandardApiResult<()> {
-    info!("Add IPFS node. {:?}", args);
-    let target_ipfs_client = state.get_ipfs_client_with_rpc_addr(args.rpc_address.clone());
-    target_ipfs_client.bootstrap_add(
-        &state.ipfs_metadata.ipfs_swarm_multi_address,
-        &state.ipfs_metadata.ipfs_peer_id,
-    ).await?;
-
-    let aim_peer_id = target_ipfs_client.get_id_info()
-        .await?
-        .id;
-    debug!("Add IPFS node target peer id: {}", aim_peer_id);
-
-    let new_node = node::ActiveModel {
-        id: Set(Uuid::new_v4().to_string()),
-        peer_id: Set(aim_peer_id),
-        rpc_address: Set(args.rpc_address),
-        wrapper_public_address: Set(Some(args.wrapper_public_address)),
-        wrapper_admin_address: Set(Some(args.wrapper_admin_address)),
-        node_status: Set(sea_orm_active_enums::NodeStatus::Online),
+pub async fn add_ipfs_node(State(state): State<AppState>,
+                           user: AuthUser,
+                           Json(args): Json<dtos::AddIpfsNodeArgs>) -> StandardApiResult<()> {
+    info!("Add IPFS node by {}. {:?}", user.id(), args);
+    let actor = user.actor();
+    let rpc_address = args.rpc_address.clone();
+    let res = services::ipfs::add_node_to_cluster(
+        &state,
+        args.rpc_address,
+        args.wrapper_public_address,
+        args.wrapper_admin_address,
+        None,
+    ).await;
+    let audit_entry = match &res {
+        Ok(node_model) => AuditEntry::new(&actor, AuditAction::AddNode).node(node_model.id.clone()),
+        Err(e) => AuditEntry::new(&actor, AuditAction::AddNode).failed(e),
     };
-    // upsert
-    let dup_conflict = sea_query::OnConflict::column(node::Column::PeerId)
-        .update_columns([
-            node::Column::RpcAddress,
-            node::Column::WrapperPublicAddress,
-            node::Column::WrapperAdminAddress,
-            node::Column::NodeStatus,
-        ])
-        .to_owned();
-    Node::insert(new_node)
-        .on_conflict(dup_conflict)
-        .exec(&state.db_conn)
-        .await.map_err(services::db::handle_db_error)?;
+    services::audit::record(&state, audit_entry.detail(format!("rpc: {rpc_address}"))).await;
+    res?;
+
+    Ok(().into())
+}
+
+/// Remove a node from the cluster, and delete it in database.
+///
+/// Refuse when the node holds the only replica of some pins, unless `force` is `true`.
+// #[axum_macros::debug_handler]
+pub async fn remove_ipfs_node(State(state): State<AppState>,
+                              user: AuthUser,
+                              Path(node_id): Path<String>,
+                              Query(args): Query<dtos::RemoveIpfsNodeArgs>)
+                              -> StandardApiResult<dtos::RemoveIpfsNodeResponse> {
+    info!("Remove IPFS node {} by {}. {:?}", node_id, user.id(), args);
+    let actor = user.actor();
+    let res = services::ipfs::remove_node_from_cluster(
+        &state,
+        &node_id,
+        args.force.unwrap_or(false),
+        &actor,
+    ).await;
+    let audit_entry = AuditEntry::new(&actor, AuditAction::RemoveNode)
+        .node(node_id.clone())
+        .result(&res);
+    services::audit::record(&state, audit_entry).await;
+    let rehomed_pins_cid = res?;
 
+    let res = dtos::RemoveIpfsNodeResponse {
+        rehomed_pins_cid,
+    };
+    Ok(res.into())
+}
+
+/// Put a node into maintenance.
+///
+/// The node would not be chosen to store new pins,
+/// and is only used to download when no other replica exists.
+// #[axum_macros::debug_handler]
+pub async fn set_ipfs_node_maintenance(State(state): State<AppState>,
+                                       user: AuthUser,
+                                       Path(node_id): Path<String>) -> StandardApiResult<()> {
+    info!("Set IPFS node {} to maintenance by {}", node_id, user.id());
+    let res = services::ipfs::set_node_maintenance(&state, &node_id).await;
+    let audit_entry = AuditEntry::new(&user.actor(), AuditAction::SetMaintenance)
+        .node(node_id.clone())
+        .result(&res);
+    services::audit::record(&state, audit_entry).await;
+    res?;
     Ok(().into())
 }
 
-/// Re-bootstrap all nodes in database that is not `Offline`.
+/// Take a node out of maintenance.
+///
+/// The node is bootstrapped again, then becomes `Online` or `Unhealthy`.
+// #[axum_macros::debug_handler]
+pub async fn clear_ipfs_node_maintenance(State(state): State<AppState>,
+                                         user: AuthUser,
+                                         Path(node_id): Path<String>) -> StandardApiResult<node::Model> {
+    info!("Clear maintenance of IPFS node {} by {}", node_id, user.id());
+    let actor = user.actor();
+    let res = services::ipfs::clear_node_maintenance(&state, &node_id, &actor).await;
+    let audit_entry = AuditEntry::new(&actor, AuditAction::ClearMaintenance)
+        .node(node_id.clone())
+        .result(&res);
+    services::audit::record(&state, audit_entry).await;
+    let node_model = res?;
+    Ok(node_model.into())
+}
+
+/// Re-bootstrap all nodes in database that is not `Offline` or `Maintenance`.
 // #[axum_macros::debug_handler]
-pub async fn re_bootstrap_all_ipfs_node(State(state): State<AppState>) -> StandardApiResult<()> {
-    info!("Re-bootstrap All IPFS Node.");
+pub async fn re_bootstrap_all_ipfs_node(State(state): State<AppState>, user: AuthUser) -> StandardApiResult<()> {
+    info!("Re-bootstrap All IPFS Node by {}.", user.id());
+    let actor = user.actor();
     let node_vec: Vec<node::Model> = Node::find()
-        .filter(node::Column::NodeStatus.ne(sea_orm_active_enums::NodeStatus::Offline)) // No offline
+        .filter(node::Column::NodeStatus.is_not_in([
+            sea_orm_active_enums::NodeStatus::Offline,
+            sea_orm_active_enums::NodeStatus::Maintenance,
+        ])) // No offline, and keep maintenance
         .all(&state.db_conn)
         .await.map_err(services::db::handle_db_error)?;
 
     let mut join_set = tokio::task::JoinSet::new();
     for node_model in node_vec {
         let task = services::ipfs::bootstrap_and_check_health(
-            state.clone(), node_model,
+            state.clone(), node_model, actor.clone(),
         );
         join_set.spawn(task);
     }
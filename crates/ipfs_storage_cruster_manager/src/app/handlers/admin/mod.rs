@@ -1,21 +1,45 @@
-//! Admin APIs.
+//! Admin APIs. Only for users with the admin role.
 
-use axum::Router;
-use axum::routing::{get, post};
-use crate::app::AppState;
+use axum::{middleware, Router};
+use axum::routing::{get, post, put, delete};
+use crate::app::{AppState, services};
 
+use audit::*;
 use ipfs::*;
 use pin::*;
+use rate_limit::*;
+use user::*;
 
+mod audit;
 mod ipfs;
 mod pin;
+mod rate_limit;
+mod user;
 
 pub fn generate_admin_router() -> Router<AppState> {
     Router::new()
         .route("/ipfs", get(list_ipfs_nodes))
         .route("/ipfs", post(add_ipfs_node))
+        .route("/ipfs/:id", delete(remove_ipfs_node))
+        .route("/ipfs/:id/maintenance", put(set_ipfs_node_maintenance))
+        .route("/ipfs/:id/maintenance", delete(clear_ipfs_node_maintenance))
         .route("/ipfs/re-bootstrap", get(re_bootstrap_all_ipfs_node))
+        .route("/ipfs/download-estimates", get(list_download_estimates))
+        .route("/ipfs/health", get(list_nodes_health))
+        .route("/ipfs/connectivity", get(list_nodes_connectivity))
+        .route("/audit", get(list_audit_log))
+        .route("/rate-limit", get(list_rate_limiters))
+        .route("/pin", get(list_pins))
+        .route("/pin/expiring", get(list_expiring_pins))
         .route("/pin/ls_pins_of_node_actually", get(list_pins_in_one_node_actually))
         .route("/pin/ls_pins_of_node", get(list_pins_in_one_node))
         .route("/pin/ls_nodes_of_pin", get(list_nodes_with_pin))
+        .route("/user", get(list_users))
+        .route("/user", post(create_user))
+        .route("/user/:id/token", get(list_api_tokens))
+        .route("/user/:id/token", post(create_api_token))
+        .route("/user/:id/token/:token_id", delete(revoke_api_token))
+        .route("/user/:id/quota", put(set_user_quota))
+        .route("/user/:id/usage", get(get_user_usage))
+        .route_layer(middleware::from_fn(services::auth::require_admin))
 }
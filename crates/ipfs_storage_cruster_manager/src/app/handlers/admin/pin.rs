@@ -1,12 +1,141 @@
 //! API about pins.
 
 use axum::extract::{State, Query};
+use axum::http::StatusCode;
 use tracing::debug;
 use crate::imports::dao_imports::*;
 use crate::app::{AppState, daos};
 use crate::app::common::StandardApiResult;
 use crate::app::{dtos, services, errors};
 
+/// List pins in the cluster with filters, newest first.
+///
+/// Paginate by `limit` and `cursor`.
+// #[axum_macros::debug_handler]
+pub async fn list_pins(State(state): State<AppState>, Query(args): Query<dtos::ListPinsArgs>)
+                       -> StandardApiResult<dtos::ListPinsResponse> {
+    const DEFAULT_LIMIT: u64 = 100;
+    const MAX_LIMIT: u64 = 1000;
+    debug!("List pins. {:?}", args);
+    let args_error = |msg: &'static str| errors::REQUEST_ARGS_ERROR.clone_to_error()
+        .modify_msg(msg)
+        .modify_status_code(StatusCode::BAD_REQUEST);
+    let limit = args.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(args_error("limit should be in [1, 1000]"));
+    }
+
+    let mut query = Pin::find();
+    if let Some(cid) = args.cid {
+        query = query.filter(pin::Column::Cid.is_in(cid));
+    }
+    if let Some(status) = args.status {
+        let status = status.into_iter()
+            .map(|v| sea_orm_active_enums::Status::try_from_value(&v))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| args_error("Invalid status"))?;
+        query = query.filter(pin::Column::Status.is_in(status));
+    }
+    if let Some(name) = args.name {
+        let lower_name_col = Expr::expr(sea_query::Func::lower(Expr::col((pin::Entity, pin::Column::Name))));
+        query = match args.name_match.unwrap_or_default() {
+            dtos::NameMatchStrategy::Exact => query.filter(pin::Column::Name.eq(name)),
+            dtos::NameMatchStrategy::Iexact => query.filter(lower_name_col.eq(name.to_lowercase())),
+            dtos::NameMatchStrategy::Partial => query.filter(Expr::col((pin::Entity, pin::Column::Name)).like(daos::contains_like_pattern(&name))),
+            dtos::NameMatchStrategy::Ipartial => query.filter(lower_name_col.like(daos::contains_like_pattern(&name.to_lowercase()))),
+        };
+    }
+    if let Some(before) = args.before {
+        query = query.filter(pin::Column::Created.lt(before));
+    }
+    if let Some(after) = args.after {
+        query = query.filter(pin::Column::Created.gt(after));
+    }
+    match (args.meta_key, args.meta_value) {
+        (Some(key), Some(value)) => {
+            let backend = state.db_conn.get_database_backend();
+            query = query.filter(daos::meta_eq_condition(backend, "pin", &key, &value));
+        }
+        (None, None) => {}
+        _ => return Err(args_error("metaKey and metaValue should be used together")),
+    }
+
+    let count = query.clone()
+        .count(&state.db_conn).await
+        .map_err(services::db::handle_db_error)?;
+
+    // keyset pagination by (created, id)
+    if let Some(cursor) = args.cursor {
+        let cursor_pin = Pin::find_by_id(cursor)
+            .one(&state.db_conn).await
+            .map_err(services::db::handle_db_error)?
+            .ok_or_else(|| args_error("Invalid cursor"))?;
+        query = query.filter(
+            Condition::any()
+                .add(pin::Column::Created.lt(cursor_pin.created))
+                .add(pin::Column::Created.eq(cursor_pin.created)
+                    .and(pin::Column::Id.lt(cursor_pin.id)))
+        );
+    }
+    let mut list = query
+        .order_by_desc(pin::Column::Created)
+        .order_by_desc(pin::Column::Id)
+        .limit(limit + 1)
+        .all(&state.db_conn).await
+        .map_err(services::db::handle_db_error)?;
+    let next_cursor = if list.len() as u64 > limit {
+        list.truncate(limit as usize);
+        list.last().map(|v| v.id.clone())
+    } else {
+        None
+    };
+
+    let res = dtos::ListPinsResponse {
+        count,
+        list,
+        next_cursor,
+    };
+    debug!("Find {} pins in total, return {}", res.count, res.list.len());
+    Ok(res.into())
+}
+
+/// List pins that expire soon, including expired ones not deleted yet.
+// #[axum_macros::debug_handler]
+pub async fn list_expiring_pins(State(state): State<AppState>, Query(args): Query<dtos::ListExpiringPinsArgs>)
+                                -> StandardApiResult<dtos::ListExpiringPinsResponse> {
+    const DEFAULT_WITHIN_SECS: u64 = 24 * 3600;
+    const DEFAULT_LIMIT: u64 = 100;
+    const MAX_LIMIT: u64 = 1000;
+    let limit = args.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(errors::REQUEST_ARGS_ERROR.clone_to_error()
+            .modify_msg("limit should be in [1, 1000]")
+            .modify_status_code(StatusCode::BAD_REQUEST));
+    }
+    let within_secs = i64::try_from(args.within_secs.unwrap_or(DEFAULT_WITHIN_SECS))
+        .ok()
+        .and_then(chrono::Duration::try_seconds)
+        .ok_or_else(|| errors::REQUEST_ARGS_ERROR.clone_to_error()
+            .modify_msg("withinSecs is too large")
+            .modify_status_code(StatusCode::BAD_REQUEST))?;
+    let before = chrono::Utc::now() + within_secs;
+
+    let list = Pin::find()
+        .filter(pin::Column::ExpiresAt.lte(before))
+        .filter(pin::Column::Status.ne(sea_orm_active_enums::Status::Deleted))
+        .order_by_asc(pin::Column::ExpiresAt)
+        .limit(limit)
+        .all(&state.db_conn).await
+        .map_err(services::db::handle_db_error)?;
+
+    debug!("Find {} pins expire before {}", list.len(), before);
+    let res = dtos::ListExpiringPinsResponse {
+        before,
+        list,
+    };
+    Ok(res.into())
+}
+
 /// List all pins in the certain node.
 /// Just query by IPFS RPC, which returns pins that actually stored in target node.
 // #[axum_macros::debug_handler]
@@ -34,13 +163,7 @@ pub async fn list_pins_in_one_node_actually(State(state): State<AppState>, Query
 pub async fn list_pins_in_one_node(State(state): State<AppState>, Query(args): Query<dtos::ListPinsInOneNodeArgs>)
                                    -> StandardApiResult<dtos::ListPinsInOneNodeResponse> {
     let pins = Pin::find()
-        .join(
-            JoinType::InnerJoin,
-            Pin::belongs_to(PinsStoredNodes)
-                .from(pin::Column::Id)
-                .to(pins_stored_nodes::Column::PinId)
-                .into(),
-        )
+        .inner_join(PinsStoredNodes)
         .filter(pins_stored_nodes::Column::NodeId.eq(args.node_id.clone()))
         .all(&state.db_conn).await
         .map_err(services::db::handle_db_error)?;
@@ -59,13 +182,7 @@ pub async fn list_pins_in_one_node(State(state): State<AppState>, Query(args): Q
 pub async fn list_nodes_with_pin(State(state): State<AppState>, Query(args): Query<dtos::ListNodesWithPinArgs>)
                                  -> StandardApiResult<dtos::ListNodesWithPinResponse> {
     let nodes = Node::find()
-        .join(
-            JoinType::InnerJoin,
-            Node::belongs_to(PinsStoredNodes)
-                .from(node::Column::Id)
-                .to(pins_stored_nodes::Column::NodeId)
-                .into(),
-        )
+        .inner_join(PinsStoredNodes)
         .filter(pins_stored_nodes::Column::PinId.eq(args.pin_id.clone()))
         .all(&state.db_conn).await
         .map_err(services::db::handle_db_error)?;
@@ -78,3 +195,45 @@ pub async fn list_nodes_with_pin(State(state): State<AppState>, Query(args): Que
     Ok(res.into())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Request};
+    use tower::ServiceExt;
+    use crate::app::services;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn test_list_pins_by_partial_name() {
+        let conn = connect_test_db().await;
+        let state = test_db_state(&conn);
+        for (id, name) in [("a", "100%_done"), ("b", "1000 done"), ("c", "50\\_done")] {
+            state.repository.insert_pin(pin::Model {
+                name: Some(name.to_string()),
+                ..test_pin(id, &format!("cid {id}"))
+            }).await.unwrap();
+        }
+
+        services::auth::ensure_bootstrap_admin(&conn, "admin token").await.unwrap();
+        let app = crate::app::handlers::generate_router(&state)
+            .layer(axum::middleware::from_fn_with_state(state.clone(), services::auth::resolve_user))
+            .with_state(state.clone());
+        // `0%_` and `0\_`
+        for (name, expected_cid) in [("0%25_", "cid a"), ("0%5C_", "cid c")] {
+            for name_match in ["partial", "ipartial"] {
+                let req = Request::builder()
+                    .uri(format!("/admin/pin?name={name}&nameMatch={name_match}"))
+                    .header(header::AUTHORIZATION, "Bearer admin token")
+                    .body(Body::empty())
+                    .unwrap();
+                let res = app.clone().oneshot(req).await.unwrap();
+                assert_eq!(res.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+                let res: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                let cids: Vec<_> = res["data"]["list"].as_array().unwrap().iter().map(|v| v["cid"].clone()).collect();
+                assert_eq!(cids, [expected_cid], "{name} {name_match}");
+            }
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! API about rate limits.
+
+#[allow(unused_imports)]
+use tracing::{trace, debug, info};
+use axum::extract::State;
+use crate::app::AppState;
+use crate::app::common::StandardApiResult;
+use crate::app::{dtos, services};
+
+/// List the state of rate limiters. Clients not listed have full budgets.
+// #[axum_macros::debug_handler]
+pub async fn list_rate_limiters(State(state): State<AppState>) -> StandardApiResult<dtos::ListRateLimitersResponse> {
+    debug!("List rate limiters");
+    let res = dtos::ListRateLimitersResponse {
+        list: services::rate_limit::list_limiter_states(&state),
+    };
+
+    Ok(res.into())
+}
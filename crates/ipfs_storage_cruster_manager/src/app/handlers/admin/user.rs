@@ -0,0 +1,100 @@
+//! API about users and their API tokens.
+
+#[allow(unused_imports)]
+use tracing::{trace, debug, info};
+use axum::extract::{State, Json, Path};
+use axum::http::StatusCode;
+use crate::imports::dao_imports::*;
+use crate::app::AppState;
+use crate::app::common::StandardApiResult;
+use crate::app::{dtos, errors, services};
+
+/// List all users.
+// #[axum_macros::debug_handler]
+pub async fn list_users(State(state): State<AppState>) -> StandardApiResult<dtos::ListUsersResponse> {
+    info!("List users");
+    let list = Users::find()
+        .order_by_asc(users::Column::Created)
+        .all(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+    let res = dtos::ListUsersResponse {
+        list
+    };
+
+    Ok(res.into())
+}
+
+/// Create a user. The user has no token until one is created.
+// #[axum_macros::debug_handler]
+pub async fn create_user(State(state): State<AppState>, Json(args): Json<dtos::CreateUserArgs>) -> StandardApiResult<users::Model> {
+    info!("Create user. {:?}", args);
+    let role = args.role.unwrap_or(sea_orm_active_enums::UserRole::User);
+    let user_model = services::auth::create_user(&state, &args.name, role).await?;
+    Ok(user_model.into())
+}
+
+/// List tokens of a user. Tokens themselves are not shown.
+// #[axum_macros::debug_handler]
+pub async fn list_api_tokens(State(state): State<AppState>, Path(user_id): Path<String>) -> StandardApiResult<dtos::ListApiTokensResponse> {
+    info!("List tokens of user {}", user_id);
+    let list = ApiToken::find()
+        .filter(api_token::Column::UserId.eq(user_id))
+        .order_by_asc(api_token::Column::Created)
+        .all(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+    let res = dtos::ListApiTokensResponse {
+        list
+    };
+
+    Ok(res.into())
+}
+
+/// Create a token of a user. The token is only returned here.
+// #[axum_macros::debug_handler]
+pub async fn create_api_token(State(state): State<AppState>,
+                              Path(user_id): Path<String>,
+                              Json(args): Json<dtos::CreateApiTokenArgs>) -> StandardApiResult<dtos::CreateApiTokenResponse> {
+    info!("Create token of user {}. {:?}", user_id, args);
+    let (api_token, token) = services::auth::create_token(&state, &user_id, args.name).await?;
+    let res = dtos::CreateApiTokenResponse {
+        token,
+        api_token,
+    };
+
+    Ok(res.into())
+}
+
+/// Revoke a token of a user.
+// #[axum_macros::debug_handler]
+pub async fn revoke_api_token(State(state): State<AppState>, Path((user_id, token_id)): Path<(String, String)>) -> StandardApiResult<()> {
+    info!("Revoke token {} of user {}", token_id, user_id);
+    services::auth::revoke_token(&state, &user_id, &token_id).await?;
+    Ok(().into())
+}
+
+/// Set quota of a user. Limits not set fall back to the defaults in config.
+// #[axum_macros::debug_handler]
+pub async fn set_user_quota(State(state): State<AppState>,
+                            Path(user_id): Path<String>,
+                            Json(args): Json<services::quota::Quota>) -> StandardApiResult<users::Model> {
+    info!("Set quota of user {}. {:?}", user_id, args);
+    let user_model = services::quota::set_quota(&state, &user_id, args).await?;
+    Ok(user_model.into())
+}
+
+/// Get usage and quota of a user.
+// #[axum_macros::debug_handler]
+pub async fn get_user_usage(State(state): State<AppState>, Path(user_id): Path<String>) -> StandardApiResult<dtos::UsageResponse> {
+    info!("Get usage of user {}", user_id);
+    let user_model = Users::find_by_id(user_id)
+        .one(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?
+        .ok_or_else(|| errors::DB_TARGET_DATA_NOT_EXIST.clone_to_error()
+            .modify_status_code(StatusCode::NOT_FOUND))?;
+    let res = dtos::UsageResponse {
+        usage: services::quota::get_usage(&state, &user_model.id).await?,
+        quota: services::quota::Quota::of_user(&user_model, &state.default_quota),
+    };
+
+    Ok(res.into())
+}
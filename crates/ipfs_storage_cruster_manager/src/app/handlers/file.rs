@@ -1,57 +1,115 @@
-use axum::extract::{Query, State};
-use sea_orm::ActiveValue::Set;
+use axum::extract::{Json, Query, State};
+use axum::http::StatusCode;
 #[allow(unused_imports)]
 use tracing::{info, debug, trace, warn, error};
 use crate::imports::dao_imports::*;
 use crate::app::AppState;
 use crate::app::common::StandardApiResult;
 use crate::app::dtos;
-use crate::app::services;
+use crate::app::{daos, errors, services};
+use crate::app::services::auth::AuthUser;
+use crate::repository::{NewReplication, NodeFilter};
+
+const MAX_PIN_NAME_CHARS: usize = 100;
 
 /// Upload file.
 /// Use [reverse-proxy](https://github.com/tokio-rs/axum/tree/main/examples/reverse-proxy)
 /// to send stream data.
 ///
 /// Seems no request size limitation.
+///
+/// Set `expiresAt` or `ttlSecs` in query to make the file expire.
+///
+/// The upload is recorded as a pin request of the user, like pin service.
+/// It's aborted if the file exceeds the quota of the user.
 // #[axum_macros::debug_handler]
-pub async fn upload_file(State(state): State<AppState>, req: axum::extract::Request) -> StandardApiResult<dtos::UploadFileResponse> {
-    let upload_res = services::file::add_file_to_ipfs(&state, req).await?;
+pub async fn upload_file(State(state): State<AppState>,
+                         user: AuthUser,
+                         Query(args): Query<dtos::PinExpiryArgs>,
+                         req: axum::extract::Request) -> StandardApiResult<dtos::UploadFileResponse> {
+    let expires_at = services::expiry::resolve_expires_at(&args)?;
+    let actor = user.actor();
+    // kept until the request is recorded
+    let permit = services::quota::check_upload(&state, &user.0).await?;
+    let upload_res = services::file::add_file_to_ipfs(&state, req, permit.max_bytes).await;
+    let audit_entry = match &upload_res {
+        Ok(v) => services::audit::AuditEntry::new(&actor, services::audit::AuditAction::Upload)
+            .cid(v.hash.clone())
+            .detail(format!("name: {}, size: {}", v.name, v.size)),
+        Err(e) => services::audit::AuditEntry::new(&actor, services::audit::AuditAction::Upload)
+            .failed(e),
+    };
+    services::audit::record(&state, audit_entry).await;
+    let upload_res = upload_res?;
 
-    let new_pin_id = Uuid::new_v4().to_string();
-    let new_pin = pin::ActiveModel {
-        id: Set(new_pin_id.clone()),
-        status: Set(sea_orm_active_enums::Status::Queued),
-        cid: Set(upload_res.hash.clone()),
+    let now = chrono::Utc::now();
+    let new_pin = pin::Model {
+        id: Uuid::new_v4().to_string(),
+        status: sea_orm_active_enums::Status::Queued,
+        cid: upload_res.hash.clone(),
+        created: now,
+        updated: now,
+        size: upload_res.size.parse().ok(),
+        name: Some(upload_res.name.clone()),
+        meta: None,
+        origins: None,
+        expires_at,
     };
-    let add_pin_res = new_pin.insert(&state.db_conn).await
-        .map_err(services::db::check_duplicate_key_error);
-    // TODO insert users_pins
-    if let Err(e) = add_pin_res {
-        // throw db error
-        let _ = e.map_err(services::db::handle_db_error)?;
-        // no need to do anything when dup key
-        info!("cid {} has been stored, skip it", upload_res.hash.clone());
-    } else {
+    // name of request is limited like pin service
+    let pin_name = upload_res.name.chars().take(MAX_PIN_NAME_CHARS).collect();
+    let request = daos::new_pin_request(user.id(), &new_pin.id, Some(pin_name), None, None);
+    // the request is recorded with the intent, so that a stored pin always has its owner
+    let new_replication = || NewReplication {
+        request: Some(request.clone()),
+        ..services::replication::new_replication(&state, vec![])
+    };
+    let intent = state.repository
+        .insert_pin_to_replicate(new_pin, new_replication())
+        .await.map_err(services::db::handle_db_error)?;
+    let intent = match intent {
+        Some(intent) => Some(intent),
+        None => {
+            let pin_model = state.repository.find_pin_by_cid(&upload_res.hash)
+                .await.map_err(services::db::handle_db_error)?
+                .ok_or_else(|| errors::DB_DATA_FAIL.clone_to_error_with_log())?;
+            // keep it as long as the new upload, unless it has failed or expired
+            let kept = match pin_model.status {
+                sea_orm_active_enums::Status::Failed
+                | sea_orm_active_enums::Status::NotFound
+                | sea_orm_active_enums::Status::Deleted => false,
+                _ => state.repository.keep_pin_until(&pin_model.id, expires_at)
+                    .await.map_err(services::db::handle_db_error)?,
+            };
+            if kept {
+                state.repository.insert_pin_request(users_pins::Model { pin_id: pin_model.id.clone(), ..request.clone() })
+                    .await.map_err(services::db::handle_db_error)?;
+                info!("cid {} has been stored, skip it", upload_res.hash.clone());
+                None
+            } else {
+                info!("Retry to store {:?} pin of cid {}", pin_model.status, upload_res.hash.clone());
+                let pin_model = pin::Model {
+                    status: sea_orm_active_enums::Status::Queued,
+                    expires_at,
+                    updated: chrono::Utc::now(),
+                    ..pin_model
+                };
+                let intent = state.repository
+                    .update_pin_to_replicate(pin_model, new_replication())
+                    .await.map_err(services::db::handle_db_error)?;
+                Some(intent)
+            }
+        }
+    };
+    drop(permit);
+    if let Some(intent) = intent {
         // TODO here async
-        // make decision and store
-        let stored_node_list = services::file::store_file_to_cluster(&state, upload_res.hash.clone()).await?;
-
-        // TODO modify pin's status.
-        // store decision to database
-        let node_models: Vec<_> = stored_node_list.into_iter()
-            .map(|v| pins_stored_nodes::ActiveModel {
-                id: Set(Uuid::new_v4().to_string()),
-                pin_id: Set(new_pin_id.clone()),
-                node_id: Set(v.id),
-            }).collect();
-        PinsStoredNodes::insert_many(node_models)
-            .exec(&state.db_conn).await
-            .map_err(services::db::handle_db_error)?;
+        // make decision, store, and record it to database
+        services::replication::process_replication(&state, intent, None).await?;
     }
 
     info!("Finish storing cid {}", upload_res.hash.clone());
     let res = dtos::UploadFileResponse {
-        request_id: new_pin_id,
+        request_id: request.id,
         file_metadata: upload_res,
     };
     Ok(res.into())
@@ -60,15 +118,157 @@ pub async fn upload_file(State(state): State<AppState>, req: axum::extract::Requ
 /// Get the advice that which Wrapper to download the file.
 ///
 /// Return the url of target Wrapper (no scheme like "http://").
+/// The url is signed for the user if `download_url_secret` is set,
+/// in which case only users with a pin request of the file get the advice.
 // #[axum_macros::debug_handler]
-pub async fn download_file_advice(State(state): State<AppState>, Query(args): Query<dtos::DownloadFileAdviceArgs>) -> StandardApiResult<dtos::DownloadFileAdviceResponse> {
+pub async fn download_file_advice(State(state): State<AppState>,
+                                  user: Option<AuthUser>,
+                                  Query(args): Query<dtos::DownloadFileAdviceArgs>) -> StandardApiResult<dtos::DownloadFileAdviceResponse> {
+    if state.download_url_config.is_some() {
+        let user = user.as_ref().ok_or_else(services::auth::unauthorized)?;
+        let is_owner = state.repository.has_pin_request_of_cid(user.id(), &args.cid)
+            .await.map_err(services::db::handle_db_error)?;
+        if !is_owner {
+            warn!("User {} is denied to download cid {}", user.id(), args.cid);
+            return Err(errors::PERMISSION_DENIED.clone_to_error()
+                .modify_status_code(StatusCode::FORBIDDEN));
+        }
+    }
     let target_wrapper = state.file_download_decision_maker
-        .decide_download_node(&args.cid, &state.db_conn, &state.reqwest_client).await?;
+        .decide_download_node(&args.cid, state.repository.as_ref(), &state.reqwest_client).await?;
     let target_wrapper_pub_addr = target_wrapper.wrapper_public_address;
     let target_url = target_wrapper_pub_addr + "/api/" + &args.cid;
+    let target_url = match &state.download_url_config {
+        Some(config) => services::file::sign_download_url(config, &target_url, &args.cid, user.as_ref().map(|v| v.id())),
+        None => target_url,
+    };
     info!("cid {} would be downloaded at target url: {}", args.cid, target_url);
     let res = dtos::DownloadFileAdviceResponse {
         url: target_url,
+        node_id: target_wrapper.id,
     };
     Ok(res.into())
 }
+
+/// Report how long it took to download a file from the advised node.
+/// Help to make better download advice.
+///
+/// Only users could report, so that anonymous clients could not skew the estimates.
+/// Reports about unknown nodes are ignored.
+// #[axum_macros::debug_handler]
+pub async fn report_download(State(state): State<AppState>,
+                             user: AuthUser,
+                             Json(args): Json<dtos::ReportDownloadArgs>) -> StandardApiResult<()> {
+    debug!("Report download by user {}. {:?}", user.id(), args);
+    let filter = NodeFilter {
+        id_in: vec![args.node_id.clone()],
+        ..Default::default()
+    };
+    let is_known_node = !state.repository.find_admin_nodes(&filter)
+        .await.map_err(services::db::handle_db_error)?
+        .is_empty();
+    if !is_known_node {
+        debug!("Ignore the report about unknown node {}", args.node_id);
+        return Ok(().into());
+    }
+    state.file_download_decision_maker
+        .report_download(&args.node_id, args.size, args.duration_ms).await?;
+    Ok(().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use axum::body::Body;
+    use axum::http::{header, Request};
+    use tower::ServiceExt;
+    use ipfs_node_wrapper_structs::public::{dtos::GetFileArgs, signature};
+    use crate::file_decision::decision_makers::LatencyFileDownloadDecisionMaker;
+    use crate::repository::Repository;
+    use crate::repository::memory::MemoryRepository;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn test_download_file_advice() {
+        let repository = Arc::new(MemoryRepository::new());
+        repository.insert_node(node::Model {
+            wrapper_public_address: Some("1.1.1.1:80".to_string()),
+            ..test_node("node", "1.1.1.1:5001")
+        });
+        repository.insert_pin(test_pin("pin", "cid")).await.unwrap();
+        repository.add_replicas("pin", vec!["node".to_string()]).await.unwrap();
+        let state = test_state(repository);
+
+        let args = dtos::DownloadFileAdviceArgs { cid: "cid".to_string() };
+        let res = super::download_file_advice(State(state.clone()), None, Query(args)).await.unwrap();
+        assert_eq!(res.data.url, "1.1.1.1:80/api/cid");
+        assert_eq!(res.data.node_id, "node");
+
+        let args = dtos::DownloadFileAdviceArgs { cid: "other cid".to_string() };
+        let res = super::download_file_advice(State(state.clone()), None, Query(args)).await;
+        assert!(res.is_err());
+
+        // signed only for users with a pin request of the file
+        state.repository.insert_pin_request(daos::new_pin_request("alice", "pin", None, None, None)).await.unwrap();
+        let state = AppState {
+            download_url_config: Some(Arc::new(services::file::DownloadUrlConfig {
+                secret: "secret".to_string(),
+                ttl_secs: 60,
+            })),
+            ..state
+        };
+        let args = || Query(dtos::DownloadFileAdviceArgs { cid: "cid".to_string() });
+        let err = super::download_file_advice(State(state.clone()), None, args()).await.unwrap_err();
+        assert_eq!(err.status_code, Some(StatusCode::UNAUTHORIZED));
+        let err = super::download_file_advice(State(state.clone()), Some(test_user("bob")), args()).await.unwrap_err();
+        assert_eq!(err.status_code, Some(StatusCode::FORBIDDEN));
+        let res = super::download_file_advice(State(state), Some(test_user("alice")), args()).await.unwrap();
+        let (url, query) = res.data.url.split_once('?').unwrap();
+        assert_eq!(url, "1.1.1.1:80/api/cid");
+        let query: std::collections::HashMap<_, _> = query.split('&')
+            .filter_map(|v| v.split_once('='))
+            .collect();
+        let args = GetFileArgs {
+            filename: None,
+            expires: query["expires"].parse().ok(),
+            user: Some(query["user"].to_string()),
+            signature: Some(query["signature"].to_string()),
+        };
+        let now = chrono::Utc::now().timestamp();
+        assert_eq!(signature::verify(b"secret", "cid", &args, now), Ok(()));
+        assert!(signature::verify(b"other secret", "cid", &args, now).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_report_download() {
+        let repository = Arc::new(MemoryRepository::new());
+        repository.insert_node(node::Model {
+            wrapper_public_address: Some("1.1.1.1:80".to_string()),
+            ..test_node("node", "1.1.1.1:5001")
+        });
+        let state = AppState {
+            file_download_decision_maker: Arc::new(LatencyFileDownloadDecisionMaker::new(0.0)),
+            ..test_state(repository)
+        };
+
+        // anonymous
+        let app = crate::app::handlers::generate_router(&state).with_state(state.clone());
+        let req = Request::builder().method("POST").uri("/advice/feedback")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"nodeId":"node","size":100,"durationMs":10}"#))
+            .unwrap();
+        assert_eq!(app.oneshot(req).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        for node_id in ["node", "unknown node"] {
+            let args = dtos::ReportDownloadArgs { node_id: node_id.to_string(), size: Some(100), duration_ms: 10 };
+            super::report_download(State(state.clone()), test_user("user"), axum::Json(args)).await.unwrap();
+        }
+        let estimates = state.file_download_decision_maker.list_node_estimates().await.unwrap();
+        let node_ids: Vec<_> = estimates.iter().map(|v| v.node_id.as_str()).collect();
+        assert_eq!(node_ids, ["node"]);
+
+        state.file_download_decision_maker.forget_node("node").await;
+        assert!(state.file_download_decision_maker.list_node_estimates().await.unwrap().is_empty());
+    }
+}
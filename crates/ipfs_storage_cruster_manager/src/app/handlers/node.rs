@@ -0,0 +1,123 @@
+//! APIs for Wrappers to register themselves.
+
+#[allow(unused_imports)]
+use tracing::{trace, debug, info, warn};
+use axum::extract::{State, Json};
+use axum::http::StatusCode;
+use ipfs_node_wrapper_client::ipfs_node_wrapper_structs::manager::dtos as wrapper_dtos;
+use crate::imports::dao_imports::*;
+use crate::app::AppState;
+use crate::app::common::{ApiResult, StandardApiResult};
+use crate::app::{errors, services};
+use crate::utils::constant_time_eq;
+
+/// Register a node by its Wrapper.
+/// Upsert the node and bootstrap it like `add_ipfs_node`.
+///
+/// Respond 400 without changing anything if the peer id mismatches the IPFS node.
+// #[axum_macros::debug_handler]
+pub async fn register_node(State(state): State<AppState>, Json(args): Json<wrapper_dtos::RegisterNodeArgs>) -> StandardApiResult<wrapper_dtos::RegisterNodeResponse> {
+    info!("Register node. peer_id: {}, rpc: {}, public: {}, admin: {}",
+        args.peer_id, args.rpc_address, args.wrapper_public_address, args.wrapper_admin_address);
+    check_join_secret(&state, &args.join_secret)?;
+
+    let res = services::ipfs::add_node_to_cluster(
+        &state,
+        args.rpc_address,
+        args.wrapper_public_address,
+        args.wrapper_admin_address,
+        Some(&args.peer_id),
+    ).await;
+    let actor = services::audit::Actor::Node(args.peer_id.clone());
+    let audit_entry = match &res {
+        Ok(node_model) => services::audit::AuditEntry::new(&actor, services::audit::AuditAction::AddNode)
+            .node(node_model.id.clone()),
+        Err(e) => services::audit::AuditEntry::new(&actor, services::audit::AuditAction::AddNode)
+            .failed(e),
+    };
+    services::audit::record(&state, audit_entry).await;
+    let node_model = res?;
+    services::health::record_heartbeat(&state, &node_model.id).await;
+
+    let res = wrapper_dtos::RegisterNodeResponse {
+        node_id: node_model.id,
+    };
+    Ok(res.into())
+}
+
+/// Receive a heartbeat from a registered node.
+///
+/// Respond 404 if the node is unknown, so that the Wrapper would register again.
+// #[axum_macros::debug_handler]
+pub async fn node_heartbeat(State(state): State<AppState>, Json(args): Json<wrapper_dtos::HeartbeatArgs>) -> StandardApiResult<()> {
+    trace!("Heartbeat from peer {}", args.peer_id);
+    check_join_secret(&state, &args.join_secret)?;
+
+    let node_model = Node::find()
+        .filter(node::Column::PeerId.eq(args.peer_id.clone()))
+        .one(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?
+        .ok_or_else(|| errors::DB_TARGET_DATA_NOT_EXIST.clone_to_error()
+            .modify_status_code(StatusCode::NOT_FOUND))?;
+    services::health::record_heartbeat(&state, &node_model.id).await;
+
+    Ok(().into())
+}
+
+fn check_join_secret(state: &AppState, join_secret: &str) -> ApiResult<()> {
+    match &state.join_secret {
+        Some(expected) if constant_time_eq(expected.as_bytes(), join_secret.as_bytes()) => Ok(()),
+        Some(_) => {
+            warn!("Receive a request with wrong join secret");
+            Err(errors::PERMISSION_DENIED.clone_to_error()
+                .modify_status_code(StatusCode::FORBIDDEN))
+        }
+        None => {
+            warn!("Receive a request to register, but `join_secret` is not configured");
+            Err(errors::PERMISSION_DENIED.clone_to_error()
+                .modify_msg("Registration is disabled")
+                .modify_status_code(StatusCode::FORBIDDEN))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn test_register_node_with_wrong_peer_id() {
+        // fake IPFS RPC, counting calls other than `id`
+        let other_calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let ipfs_app = axum::Router::new()
+            .route("/api/v0/id", axum::routing::post(|| async {
+                axum::Json(serde_json::json!({
+                    "ID": "actual peer", "PublicKey": "", "Addresses": [], "AgentVersion": "kubo/0.26.0/", "Protocols": [],
+                }))
+            }))
+            .fallback({
+                let other_calls = other_calls.clone();
+                move || async move { other_calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst); }
+            });
+        let ipfs_address = serve_fake(ipfs_app).await;
+
+        let conn = connect_test_db().await;
+        let state = AppState {
+            join_secret: Some(Arc::new("secret".to_string())),
+            ..test_db_state(&conn)
+        };
+        let args = wrapper_dtos::RegisterNodeArgs {
+            join_secret: "secret".to_string(),
+            peer_id: "claimed peer".to_string(),
+            rpc_address: ipfs_address,
+            wrapper_public_address: "1.1.1.1:80".to_string(),
+            wrapper_admin_address: "1.1.1.1:8080".to_string(),
+        };
+        let err = super::register_node(State(state), axum::Json(args)).await.unwrap_err();
+        assert_eq!(err.status_code, Some(StatusCode::BAD_REQUEST));
+        assert_eq!(Node::find().count(&conn).await.unwrap(), 0);
+        assert_eq!(other_calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+}
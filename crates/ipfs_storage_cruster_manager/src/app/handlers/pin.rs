@@ -0,0 +1,21 @@
+//! APIs for owners of pins.
+
+#[allow(unused_imports)]
+use tracing::{trace, debug, info, warn};
+use axum::extract::{Json, Path, State};
+use crate::imports::dao_imports::*;
+use crate::app::AppState;
+use crate::app::common::StandardApiResult;
+use crate::app::{dtos, services};
+use crate::app::services::auth::AuthUser;
+
+/// Extend the expiry time of a pin request of the user.
+// #[axum_macros::debug_handler]
+pub async fn extend_pin_expiry(State(state): State<AppState>,
+                               user: AuthUser,
+                               Path(request_id): Path<String>,
+                               Json(args): Json<dtos::PinExpiryArgs>) -> StandardApiResult<pin::Model> {
+    info!("Extend expiry of pin request {} of user {}. {:?}", request_id, user.id(), args);
+    let pin_model = services::expiry::extend_pin_expiry(&state, user.id(), &request_id, &args).await?;
+    Ok(pin_model.into())
+}
@@ -0,0 +1,96 @@
+//! [IPFS Pinning Service API](https://ipfs.github.io/pinning-services-api-spec).
+//!
+//! Mounted at `/pins` without `/api` prefix, so that `ipfs pin remote service add` works.
+
+#[allow(unused_imports)]
+use tracing::{trace, debug, info, error};
+use axum::{async_trait, Json};
+use axum::extract::{Path, State};
+use axum::Router;
+use ipfs_pin_service_axum_api_framework::{dto, models, EnhancedQuery};
+use ipfs_pin_service_axum_api_framework::api::{ApiResponse, AuthContext, IpfsPinServiceApi};
+use ipfs_pin_service_axum_api_framework::errors::{ResponseError as PinServiceError, ResponseErrorType};
+use ipfs_storage_cruster_manager_entity::users;
+use crate::app::{AppState, errors, services};
+
+/// Generate the router of pin service with the app state.
+pub fn generate_pin_service_router(state: AppState) -> Router {
+    ipfs_pin_service_axum_api_framework::api::generate_router::<ManagerPinServiceApi>()
+        .with_state(state)
+}
+
+pub struct ManagerPinServiceApi;
+
+#[async_trait]
+impl IpfsPinServiceApi for ManagerPinServiceApi {
+    type State = AppState;
+
+    async fn get_pins(State(state): State<AppState>, token: AuthContext, EnhancedQuery(args): EnhancedQuery<dto::GetPinsArgs>) -> ApiResponse<dto::GetPinsResponse> {
+        let user_id = prepare(&state, &token).await?;
+        debug!("Get pins of user {}. {:?}", user_id, args);
+        let pin_results = services::pin_service::list_pin_requests(&state, &user_id, args)
+            .await.map_err(convert_error)?;
+        Ok(pin_results.into())
+    }
+
+    async fn add_pin(State(state): State<AppState>, token: AuthContext, Json(pin): Json<models::Pin>) -> ApiResponse<dto::AddPinResponse> {
+        let user_model = prepare_user(&state, &token).await?;
+        info!("Add pin of user {}. cid: {}", user_model.id, pin.cid);
+        let permit = services::quota::check_upload(&state, &user_model)
+            .await.map_err(convert_error)?;
+        let pin_status = services::pin_service::add_pin_request(&state, &user_model.id, pin)
+            .await.map_err(convert_error)?;
+        drop(permit);
+        Ok(pin_status.into())
+    }
+
+    async fn get_pin_by_request_id(State(state): State<AppState>, token: AuthContext, Path(requestid): Path<String>) -> ApiResponse<dto::GetPinByRequestIdResponse> {
+        let user_id = prepare(&state, &token).await?;
+        debug!("Get pin {} of user {}", requestid, user_id);
+        let pin_status = services::pin_service::get_pin_request(&state, &user_id, &requestid)
+            .await.map_err(convert_error)?;
+        Ok(pin_status.into())
+    }
+
+    async fn replace_pin_by_request_id(State(state): State<AppState>, token: AuthContext, Path(requestid): Path<String>, Json(pin): Json<models::Pin>) -> ApiResponse<dto::AddPinResponse> {
+        let user_id = prepare(&state, &token).await?;
+        info!("Replace pin {} of user {}. cid: {}", requestid, user_id, pin.cid);
+        let pin_status = services::pin_service::replace_pin_request(&state, &user_id, &requestid, pin)
+            .await.map_err(convert_error)?;
+        Ok(pin_status.into())
+    }
+
+    async fn delete_pin_by_request_id(State(state): State<AppState>, token: AuthContext, Path(requestid): Path<String>) -> ApiResponse<dto::DeletePinByRequestIdResponse> {
+        let user_id = prepare(&state, &token).await?;
+        info!("Delete pin {} of user {}", requestid, user_id);
+        services::pin_service::delete_pin_request(&state, &user_id, &requestid)
+            .await.map_err(convert_error)?;
+        Ok(dto::DeletePinByRequestIdResponse::new())
+    }
+}
+
+/// Get the id of the user owning the token.
+async fn prepare(state: &AppState, token: &AuthContext) -> Result<String, PinServiceError> {
+    let user_model = prepare_user(state, token).await?;
+    Ok(user_model.id)
+}
+
+/// Get the user owning the token.
+async fn prepare_user(state: &AppState, token: &AuthContext) -> Result<users::Model, PinServiceError> {
+    let token = services::auth::bearer_token(token.token())
+        .ok_or_else(|| PinServiceError::new(ResponseErrorType::Unauthorized))?;
+    let user_model = services::auth::find_user_by_token(state, token)
+        .await.map_err(convert_error)?
+        .ok_or_else(|| PinServiceError::new(ResponseErrorType::Unauthorized))?;
+    Ok(user_model)
+}
+
+/// Convert the error into the format of pin service.
+fn convert_error(e: errors::ResponseError) -> PinServiceError {
+    let status_code = e.status_code.map(|v| v.as_u16()).unwrap_or(500);
+    match status_code {
+        400 => PinServiceError::new(ResponseErrorType::BadRequest).detail(&e.message),
+        404 => PinServiceError::new(ResponseErrorType::NotFound),
+        _ => PinServiceError::from((status_code, e.message)),
+    }
+}
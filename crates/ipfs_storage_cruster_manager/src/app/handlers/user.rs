@@ -0,0 +1,21 @@
+//! APIs about the user self.
+
+#[allow(unused_imports)]
+use tracing::{trace, debug, info, warn};
+use axum::extract::State;
+use crate::app::AppState;
+use crate::app::common::StandardApiResult;
+use crate::app::{dtos, services};
+use crate::app::services::auth::AuthUser;
+
+/// Get usage and quota of the user.
+// #[axum_macros::debug_handler]
+pub async fn get_usage(State(state): State<AppState>, user: AuthUser) -> StandardApiResult<dtos::UsageResponse> {
+    debug!("Get usage of user {}", user.id());
+    let res = dtos::UsageResponse {
+        usage: services::quota::get_usage(&state, user.id()).await?,
+        quota: services::quota::Quota::of_user(&user.0, &state.default_quota),
+    };
+
+    Ok(res.into())
+}
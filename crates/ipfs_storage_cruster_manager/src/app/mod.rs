@@ -1,14 +1,15 @@
 use std::sync::Arc;
-use tracing::error;
+use tracing::{error, info};
 use axum::body::Body;
-use axum::http::{StatusCode, Uri};
+use axum::http::{HeaderValue, StatusCode, Uri};
 use axum::Router;
 use tower_http::cors;
 use hyper_util::{client::legacy::connect::HttpConnector, rt::TokioExecutor};
 use sea_orm::prelude::DatabaseConnection;
 use tiny_ipfs_client::ReqwestIpfsClient;
-use crate::app_builder::AppConfig;
+use crate::app_builder::{AppConfig, DownloadDecisionMakerKind};
 use crate::file_decision;
+use crate::repository::{self, Repository};
 
 pub mod handlers;
 pub mod errors;
@@ -21,6 +22,7 @@ pub type RawHyperClient = hyper_util::client::legacy::Client<HttpConnector, Body
 
 static IPFS_CONN_RETRY_INTERVAL_TIME_MS: u64 = 500;
 static DATABASE_CONN_RETRY_INTERVAL_TIME_MS: u64 = 3000;
+static IPFS_RPC_TIMEOUT_MS: u64 = 5000;
 
 #[derive(Debug, Clone)]
 pub struct IpfsMetadata {
@@ -42,9 +44,26 @@ pub struct AppState {
     pub raw_hyper_client: RawHyperClient,
     /// MySql connection.
     pub db_conn: DatabaseConnection,
+    /// Nodes, pins and replicas. Backed by `db_conn`.
+    pub repository: Arc<dyn Repository>,
     /// Make decisions to define file storage strategy.
     pub file_storage_decision_maker: Arc<dyn file_decision::FileStorageDecisionMaker>,
     pub file_download_decision_maker: Arc<dyn file_decision::FileDownloadDecisionMaker>,
+    /// Health records of nodes. `node_id -> record`.
+    pub node_health_records: Arc<scc::HashMap<String, services::health::NodeHealthRecord>>,
+    /// Swarm connectivity of nodes. `node_id -> connectivity`.
+    pub node_connectivity: Arc<scc::HashMap<String, services::peering::NodeConnectivity>>,
+    /// How to pin files to storage nodes.
+    pub pin_config: Arc<services::file::PinConfig>,
+    /// Secret shared with Wrappers to register themselves.
+    pub join_secret: Option<Arc<String>>,
+    /// Rate limits of routes.
+    pub rate_limits: Arc<services::rate_limit::RateLimits>,
+    /// Quota of users without their own quota.
+    pub default_quota: Arc<services::quota::Quota>,
+    pub quota_locks: Arc<services::quota::QuotaLocks>,
+    /// How to sign download URLs. URLs are not signed if `None`.
+    pub download_url_config: Option<Arc<services::file::DownloadUrlConfig>>,
 }
 
 impl AppState {
@@ -57,7 +76,13 @@ impl AppState {
             &app_config.database_url,
             DATABASE_CONN_RETRY_INTERVAL_TIME_MS,
         ).await;
-
+        services::db::prepare_schema(&db_conn, app_config.database_auto_migrate).await;
+        if let Some(admin_token) = &app_config.admin_token {
+            services::auth::ensure_bootstrap_admin(&db_conn, admin_token)
+                .await
+                .expect("Failed to prepare the admin of `admin_token`");
+        }
+        let repository: Arc<dyn Repository> = Arc::new(repository::seaorm::SeaOrmRepository::new(db_conn.clone()));
 
         let ipfs_client = ReqwestIpfsClient::new_with_reqwest_client(
             app_config.ipfs_rpc_address.to_string(),
@@ -74,6 +99,29 @@ impl AppState {
             ipfs_swarm_multi_address: app_config.ipfs_swarm_multi_address.to_string(),
         };
 
+        let file_download_decision_maker: Arc<dyn file_decision::FileDownloadDecisionMaker> =
+            match app_config.file_download_decision_maker {
+                DownloadDecisionMakerKind::Random => Arc::new(file_decision::decision_makers::RandomFileDownloadDecisionMaker::new()),
+                DownloadDecisionMakerKind::Latency => {
+                    let maker = file_decision::decision_makers::LatencyFileDownloadDecisionMaker::new(
+                        app_config.latency_exploration_rate,
+                    );
+                    maker.spawn_probe_task(
+                        repository.clone(),
+                        reqwest_client.clone(),
+                        app_config.latency_probe_interval_ms,
+                    );
+                    Arc::new(maker)
+                }
+            };
+        info!("File download decision maker: {:?}", file_download_decision_maker);
+
+        let min_kubo_version = app_config.min_kubo_version.as_ref()
+            .map(|v| v.parse::<file_decision::KuboVersion>()
+                .unwrap_or_else(|_| panic!("Invalid min_kubo_version: {v}")));
+        let file_storage_decision_maker = file_decision::decision_makers::RandomFileStorageDecisionMaker::new()
+            .with_min_kubo_version(min_kubo_version);
+
         AppState {
             reqwest_client: reqwest_client.clone(),
             ipfs_client: ipfs_client.into(),
@@ -81,9 +129,39 @@ impl AppState {
             raw_hyper_client: hyper_util::client::legacy::Client::<(), ()>::builder(TokioExecutor::new())
                 .build(HttpConnector::new()),
             db_conn,
+            repository,
             // TODO 自定义决策
-            file_storage_decision_maker: Arc::new(file_decision::decision_makers::RandomFileStorageDecisionMaker::new()),
-            file_download_decision_maker: Arc::new(file_decision::decision_makers::RandomFileDownloadDecisionMaker::new()),
+            file_storage_decision_maker: Arc::new(file_storage_decision_maker),
+            file_download_decision_maker,
+            node_health_records: Arc::new(scc::HashMap::new()),
+            node_connectivity: Arc::new(scc::HashMap::new()),
+            pin_config: Arc::new(services::file::PinConfig {
+                mode: app_config.pin_mode,
+                poll_interval_ms: app_config.wrapper_pin_poll_interval_ms,
+                timeout_ms: app_config.wrapper_pin_timeout_ms,
+            }),
+            join_secret: app_config.join_secret.clone().map(Arc::new),
+            rate_limits: Arc::new(services::rate_limit::RateLimits {
+                upload: services::rate_limit::RateBudget::new(
+                    app_config.rate_limit_upload_per_ip,
+                    app_config.rate_limit_upload_per_user,
+                ),
+                advice: services::rate_limit::RateBudget::new(
+                    app_config.rate_limit_advice_per_ip,
+                    app_config.rate_limit_advice_per_user,
+                ),
+            }),
+            default_quota: Arc::new(services::quota::Quota {
+                max_total_bytes: app_config.quota_max_total_bytes,
+                max_pin_count: app_config.quota_max_pin_count,
+                max_file_bytes: app_config.quota_max_file_bytes,
+            }),
+            quota_locks: Arc::new(scc::HashMap::new()),
+            download_url_config: app_config.download_url_secret.clone()
+                .map(|secret| Arc::new(services::file::DownloadUrlConfig {
+                    secret,
+                    ttl_secs: app_config.download_url_ttl_secs,
+                })),
         }
     }
 
@@ -99,17 +177,70 @@ impl AppState {
 pub async fn generate_app_from_config(app_config: &AppConfig) -> Router {
     let app_state = AppState::from_app_config(app_config).await;
 
-    // TODO pin没有api前缀。要分开生成路由
-    let app = handlers::generate_router();
+    if app_config.health_check_enabled {
+        services::health::spawn_health_monitor(
+            app_state.clone(),
+            services::health::HealthCheckConfig {
+                interval_time_ms: app_config.health_check_interval_ms,
+                timeout_ms: app_config.health_check_timeout_ms,
+                failure_threshold: app_config.health_check_failure_threshold,
+                success_threshold: app_config.health_check_success_threshold,
+                offline_threshold: app_config.health_check_offline_threshold,
+                heartbeat_timeout_ms: app_config.heartbeat_timeout_ms,
+            },
+        );
+    }
+
+    if app_config.swarm_peering_enabled {
+        services::peering::spawn_peering_maintainer(
+            app_state.clone(),
+            services::peering::PeeringConfig {
+                interval_time_ms: app_config.swarm_peering_interval_ms,
+                timeout_ms: IPFS_RPC_TIMEOUT_MS,
+                mesh: app_config.swarm_peering_mesh,
+            },
+        );
+    }
+
+    if app_config.pin_expiry_enabled {
+        services::expiry::spawn_expiry_reaper(
+            app_state.clone(),
+            services::expiry::ExpiryConfig {
+                interval_time_ms: app_config.pin_expiry_interval_ms,
+                timeout_ms: IPFS_RPC_TIMEOUT_MS,
+            },
+        );
+    }
+
+    services::replication::spawn_replication_worker(
+        app_state.clone(),
+        services::replication::ReplicationConfig {
+            interval_time_ms: app_config.replication_interval_ms,
+            max_attempts: app_config.replication_max_attempts,
+        },
+    );
+
+    services::rate_limit::spawn_rate_limit_purger(app_state.clone());
+
+    let app = handlers::generate_router(&app_state)
+        .layer(axum::middleware::from_fn_with_state(app_state.clone(), services::auth::resolve_user));
 
     let app = Router::new()
         .nest("/api", app)
-        .with_state(app_state);
+        .with_state(app_state.clone());
+
+    // pin service has no `/api` prefix
+    let app = if app_config.pin_service_enabled {
+        app.merge(handlers::generate_pin_service_router(app_state))
+    } else {
+        app
+    };
 
-    decorate_router(app)
+    decorate_router(app, &app_config.cors_allowed_origins)
 }
 
-fn decorate_router(router: Router) -> Router {
+/// Allow any origin if `cors_allowed_origins` is empty.
+fn decorate_router(router: Router, cors_allowed_origins: &[String]) -> Router {
     let tracing_layer = tower_http::trace::TraceLayer::new_for_http()
         // Create our own span for the request and include the matched path. The matched
         // path is useful for figuring out which handler the request was routed to.
@@ -126,8 +257,16 @@ fn decorate_router(router: Router) -> Router {
             tracing::debug_span!("request", %method, %uri, matched_path)
         });
 
+    let allow_origin = if cors_allowed_origins.is_empty() {
+        cors::AllowOrigin::any()
+    } else {
+        let origins = cors_allowed_origins.iter()
+            .map(|v| v.parse::<HeaderValue>()
+                .unwrap_or_else(|_| panic!("Invalid origin in cors_allowed_origins: {v}")));
+        cors::AllowOrigin::list(origins)
+    };
     let cors_layer = cors::CorsLayer::new()
-        .allow_origin(cors::Any)
+        .allow_origin(allow_origin)
         .allow_methods(cors::Any)
         .allow_headers(cors::Any);
 
@@ -0,0 +1,118 @@
+//! Append-only audit log about where the data goes.
+//!
+//! Writing audit log never fails the audited action. Errors are only logged.
+
+use std::fmt;
+#[allow(unused_imports)]
+use tracing::{error, debug, warn, info, trace};
+use crate::imports::dao_imports::*;
+use crate::app::AppState;
+
+pub(crate) use sea_orm_active_enums::AuditAction;
+
+const MAX_DETAIL_LEN: usize = 1000;
+
+/// Who does the action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Actor {
+    /// Background tasks of manager.
+    System,
+    /// Admin with the user id, calling admin APIs.
+    Admin(String),
+    /// Wrapper of the node with the peer id.
+    Node(String),
+    /// User with the user id.
+    User(String),
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Actor::System => write!(f, "system"),
+            Actor::Admin(user_id) => write!(f, "admin:{user_id}"),
+            Actor::Node(peer_id) => write!(f, "node:{peer_id}"),
+            Actor::User(user_id) => write!(f, "user:{user_id}"),
+        }
+    }
+}
+
+/// An entry to append.
+#[derive(Debug, Clone)]
+pub(crate) struct AuditEntry {
+    actor: String,
+    action: AuditAction,
+    cid: Option<String>,
+    node_id: Option<String>,
+    outcome: sea_orm_active_enums::AuditOutcome,
+    detail: Option<String>,
+}
+
+impl AuditEntry {
+    pub(crate) fn new(actor: &Actor, action: AuditAction) -> Self {
+        Self {
+            actor: actor.to_string(),
+            action,
+            cid: None,
+            node_id: None,
+            outcome: sea_orm_active_enums::AuditOutcome::Success,
+            detail: None,
+        }
+    }
+
+    pub(crate) fn cid(mut self, cid: impl Into<String>) -> Self {
+        self.cid = Some(cid.into());
+        self
+    }
+
+    pub(crate) fn node(mut self, node_id: impl Into<String>) -> Self {
+        self.node_id = Some(node_id.into());
+        self
+    }
+
+    pub(crate) fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Mark the action failed, with the error as detail.
+    pub(crate) fn failed(mut self, e: impl fmt::Debug) -> Self {
+        self.outcome = sea_orm_active_enums::AuditOutcome::Failure;
+        self.detail = Some(format!("{e:?}"));
+        self
+    }
+
+    /// Set the outcome by the result of the action.
+    pub(crate) fn result<T, E: fmt::Debug>(self, res: &Result<T, E>) -> Self {
+        match res {
+            Ok(_) => self,
+            Err(e) => self.failed(e),
+        }
+    }
+}
+
+/// Append an entry to audit log.
+pub(crate) async fn record(state: &AppState, entry: AuditEntry) {
+    let mut detail = entry.detail;
+    if let Some(detail) = detail.as_mut() {
+        if detail.len() > MAX_DETAIL_LEN {
+            let mut end = MAX_DETAIL_LEN;
+            while !detail.is_char_boundary(end) {
+                end -= 1;
+            }
+            detail.truncate(end);
+        }
+    }
+    let res = audit_log::ActiveModel {
+        created: Set(chrono::Utc::now()),
+        actor: Set(entry.actor),
+        action: Set(entry.action),
+        cid: Set(entry.cid),
+        node_id: Set(entry.node_id),
+        outcome: Set(entry.outcome),
+        detail: Set(detail),
+        ..Default::default()
+    }.insert(&state.db_conn).await;
+    if let Err(e) = res {
+        error!("Failed to write audit log. msg: {e:?}");
+    }
+}
@@ -0,0 +1,318 @@
+//! Users and API tokens.
+//!
+//! A request is authenticated by `Authorization: Bearer <token>`.
+//! Only the SHA-256 hash of a token is stored, so a token is only shown once when it's created.
+
+#[allow(unused_imports)]
+use tracing::{error, debug, warn, info, trace};
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Request, State};
+use axum::http::{header, request::Parts, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+use crate::imports::dao_imports::*;
+use crate::app::{AppState, errors, services};
+use crate::app::common::ApiResult;
+use crate::app::services::db::DbResult;
+use crate::app::services::audit::Actor;
+
+const MAX_NAME_LEN: usize = 100;
+/// Name of the admin created by `admin_token` in config.
+const BOOTSTRAP_ADMIN_NAME: &str = "admin";
+
+/// The user of a request, resolved by `resolve_user`.
+///
+/// As an extractor, it rejects requests without a valid token.
+#[derive(Debug, Clone)]
+pub(crate) struct AuthUser(pub users::Model);
+
+impl AuthUser {
+    pub fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.0.role == sea_orm_active_enums::UserRole::Admin
+    }
+
+    pub fn actor(&self) -> Actor {
+        if self.is_admin() {
+            Actor::Admin(self.0.id.clone())
+        } else {
+            Actor::User(self.0.id.clone())
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = errors::ResponseError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<AuthUser>()
+            .cloned()
+            .ok_or_else(unauthorized)
+    }
+}
+
+pub(crate) fn unauthorized() -> errors::ResponseError {
+    errors::PERMISSION_DENIED.clone_to_error()
+        .modify_msg("Access token is required")
+        .modify_status_code(StatusCode::UNAUTHORIZED)
+}
+
+/// Get the token from the value of `Authorization` header.
+///
+/// The scheme must be `Bearer`, matched case-insensitively as RFC 6750 says.
+/// Return `None` for other schemes or an empty token.
+pub(crate) fn bearer_token(value: &str) -> Option<&str> {
+    let (scheme, token) = value.trim_start().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Bearer") {
+        return None;
+    }
+    let token = token.trim();
+    if token.is_empty() {
+        return None;
+    }
+    Some(token)
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// A random token of 64 hex characters.
+fn generate_token() -> String {
+    let mut bytes = Vec::with_capacity(32);
+    bytes.extend_from_slice(Uuid::new_v4().as_bytes());
+    bytes.extend_from_slice(Uuid::new_v4().as_bytes());
+    hex::encode(bytes)
+}
+
+/// Find the user owning the token.
+pub(crate) async fn find_user_by_token(state: &AppState, token: &str) -> ApiResult<Option<users::Model>> {
+    Users::find()
+        .inner_join(ApiToken)
+        .filter(api_token::Column::TokenHash.eq(hash_token(token)))
+        .one(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)
+}
+
+/// Middleware to resolve `Authorization` header to `AuthUser` in extensions.
+///
+/// Requests without the header pass through anonymously, but an unknown token is rejected.
+pub(crate) async fn resolve_user(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let token = req.headers().get(header::AUTHORIZATION)
+        .map(|v| v.to_str().ok().and_then(bearer_token));
+    let Some(token) = token else {
+        return next.run(req).await;
+    };
+    let user_model = match token {
+        Some(token) => find_user_by_token(&state, token).await,
+        None => Ok(None),
+    };
+    match user_model {
+        Ok(Some(user_model)) => {
+            trace!("Request from user {}", user_model.id);
+            req.extensions_mut().insert(AuthUser(user_model));
+            next.run(req).await
+        }
+        Ok(None) => {
+            debug!("Receive a request with unknown token");
+            errors::PERMISSION_DENIED.clone_to_error()
+                .modify_msg("Invalid access token")
+                .modify_status_code(StatusCode::UNAUTHORIZED)
+                .into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Middleware to allow only admins. Must run after `resolve_user`.
+pub(crate) async fn require_admin(user: AuthUser, req: Request, next: Next) -> Response {
+    if !user.is_admin() {
+        warn!("User {} is denied to call admin API {}", user.id(), req.uri());
+        return errors::PERMISSION_DENIED.clone_to_error()
+            .modify_status_code(StatusCode::FORBIDDEN)
+            .into_response();
+    }
+    next.run(req).await
+}
+
+/// Create a user.
+pub(crate) async fn create_user(state: &AppState, name: &str, role: sea_orm_active_enums::UserRole) -> ApiResult<users::Model> {
+    check_name(name)?;
+    let res = users::ActiveModel {
+        id: Set(Uuid::new_v4().to_string()),
+        name: Set(name.to_owned()),
+        role: Set(role),
+        created: Set(chrono::Utc::now()),
+        ..Default::default()
+    }.insert(&state.db_conn).await;
+    match res {
+        Ok(user_model) => {
+            info!("Create user {} named {}", user_model.id, name);
+            Ok(user_model)
+        }
+        Err(e) => match services::db::check_duplicate_key_error(e) {
+            Ok(_) => Err(errors::REQUEST_ARGS_ERROR.clone_to_error()
+                .modify_msg("User name exists")
+                .modify_status_code(StatusCode::CONFLICT)),
+            Err(e) => Err(services::db::handle_db_error(e)),
+        },
+    }
+}
+
+/// Create a token of the user. Return the model and the token.
+pub(crate) async fn create_token(state: &AppState, user_id: &str, name: Option<String>) -> ApiResult<(api_token::Model, String)> {
+    if let Some(name) = &name {
+        check_name(name)?;
+    }
+    Users::find_by_id(user_id)
+        .one(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?
+        .ok_or_else(|| errors::DB_TARGET_DATA_NOT_EXIST.clone_to_error()
+            .modify_status_code(StatusCode::NOT_FOUND))?;
+    let token = generate_token();
+    let token_model = insert_token(&state.db_conn, user_id, &hash_token(&token), name).await
+        .map_err(services::db::handle_db_error)?;
+    info!("Create token {} of user {}", token_model.id, user_id);
+    Ok((token_model, token))
+}
+
+/// Revoke a token of the user.
+pub(crate) async fn revoke_token(state: &AppState, user_id: &str, token_id: &str) -> ApiResult<()> {
+    let res = ApiToken::delete_many()
+        .filter(api_token::Column::Id.eq(token_id))
+        .filter(api_token::Column::UserId.eq(user_id))
+        .exec(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+    if res.rows_affected == 0 {
+        return Err(errors::DB_TARGET_DATA_NOT_EXIST.clone_to_error()
+            .modify_status_code(StatusCode::NOT_FOUND));
+    }
+    info!("Revoke token {} of user {}", token_id, user_id);
+    Ok(())
+}
+
+/// Make sure the admin token in config belongs to an admin, so that the first admin could log in.
+pub(crate) async fn ensure_bootstrap_admin(db_conn: &DatabaseConnection, token: &str) -> DbResult<()> {
+    let token_hash = hash_token(token);
+    let exists = ApiToken::find()
+        .filter(api_token::Column::TokenHash.eq(token_hash.clone()))
+        .one(db_conn).await?
+        .is_some();
+    if exists {
+        return Ok(());
+    }
+    let user_model = match Users::find().filter(users::Column::Name.eq(BOOTSTRAP_ADMIN_NAME)).one(db_conn).await? {
+        Some(user_model) => user_model,
+        None => users::ActiveModel {
+            id: Set(Uuid::new_v4().to_string()),
+            name: Set(BOOTSTRAP_ADMIN_NAME.to_owned()),
+            role: Set(sea_orm_active_enums::UserRole::Admin),
+            created: Set(chrono::Utc::now()),
+            ..Default::default()
+        }.insert(db_conn).await?,
+    };
+    if user_model.role != sea_orm_active_enums::UserRole::Admin {
+        error!("User {} exists but is not an admin. Ignore `admin_token`", BOOTSTRAP_ADMIN_NAME);
+        return Ok(());
+    }
+    insert_token(db_conn, &user_model.id, &token_hash, Some("bootstrap".to_owned())).await?;
+    info!("Add `admin_token` to user {}", BOOTSTRAP_ADMIN_NAME);
+    Ok(())
+}
+
+async fn insert_token(db_conn: &DatabaseConnection, user_id: &str, token_hash: &str, name: Option<String>) -> DbResult<api_token::Model> {
+    api_token::ActiveModel {
+        id: Set(Uuid::new_v4().to_string()),
+        user_id: Set(user_id.to_owned()),
+        token_hash: Set(token_hash.to_owned()),
+        name: Set(name),
+        created: Set(chrono::Utc::now()),
+    }.insert(db_conn).await
+}
+
+fn check_name(name: &str) -> ApiResult<()> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(errors::REQUEST_ARGS_ERROR.clone_to_error()
+            .modify_msg("name should have 1 to 100 bytes")
+            .modify_status_code(StatusCode::BAD_REQUEST));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token("Bearer token"), Some("token"));
+        assert_eq!(bearer_token("bearer  token "), Some("token"));
+        assert_eq!(bearer_token("BEARER token"), Some("token"));
+        assert_eq!(bearer_token("token"), None);
+        assert_eq!(bearer_token("Basic dXNlcjpwYXNz"), None);
+        assert_eq!(bearer_token("Bearertoken"), None);
+        assert_eq!(bearer_token("Bearer "), None);
+    }
+
+    #[tokio::test]
+    async fn try_api_token_auth() {
+        let conn = connect_test_db().await;
+        let state = test_db_state(&conn);
+        // idempotent on restart
+        services::auth::ensure_bootstrap_admin(&conn, "admin token").await.unwrap();
+        services::auth::ensure_bootstrap_admin(&conn, "admin token").await.unwrap();
+        let user_model = services::auth::create_user(&state, "alice", sea_orm_active_enums::UserRole::User).await.unwrap();
+        assert!(services::auth::create_user(&state, "alice", sea_orm_active_enums::UserRole::User).await.is_err());
+        let (token_model, token) = services::auth::create_token(&state, &user_model.id, None).await.unwrap();
+        assert_ne!(token_model.token_hash, token);
+
+        let app = crate::app::handlers::generate_router(&state)
+            .layer(axum::middleware::from_fn_with_state(state.clone(), services::auth::resolve_user))
+            .with_state(state.clone());
+        let call = |uri: &'static str, token: Option<&str>| {
+            let mut builder = Request::builder().uri(uri);
+            if let Some(token) = token {
+                builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+            }
+            let req = builder.body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+        assert_eq!(call("/admin/user", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(call("/admin/user", Some("unknown token")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(call("/admin/user", Some(&token)).await, StatusCode::FORBIDDEN);
+        assert_eq!(call("/admin/user", Some("admin token")).await, StatusCode::OK);
+        // the token without the scheme is rejected
+        let req = Request::builder()
+            .uri("/admin/user")
+            .header(header::AUTHORIZATION, "admin token")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(app.clone().oneshot(req).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        // admin actions are audited with the admin
+        let req = Request::builder()
+            .method("PUT")
+            .uri("/admin/ipfs/unknown/maintenance")
+            .header(header::AUTHORIZATION, "Bearer admin token")
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(req).await.unwrap();
+        let admin_model = services::auth::find_user_by_token(&state, "admin token").await.unwrap().unwrap();
+        let audit_model = AuditLog::find().one(&conn).await.unwrap().unwrap();
+        assert_eq!(audit_model.actor, format!("admin:{}", admin_model.id));
+        // public APIs need no token
+        assert_ne!(call("/advice?cid=cid", None).await, StatusCode::UNAUTHORIZED);
+
+        services::auth::revoke_token(&state, &user_model.id, &token_model.id).await.unwrap();
+        assert!(services::auth::find_user_by_token(&state, &token).await.unwrap().is_none());
+        assert_eq!(call("/admin/user", Some(&token)).await, StatusCode::UNAUTHORIZED);
+    }
+}
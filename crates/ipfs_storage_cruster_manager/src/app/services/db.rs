@@ -1,5 +1,7 @@
+use std::collections::HashSet;
 #[allow(unused_imports)]
-use tracing::{trace, debug, info, error};
+use tracing::{trace, debug, info, warn, error};
+use ipfs_storage_cruster_manager_migration::{Migrator, MigratorTrait};
 use crate::imports::dao_imports::*;
 use crate::app::errors;
 
@@ -20,6 +22,41 @@ pub async fn connect_db_until_success(db_url: &str, interval_time_ms: u64) -> Da
     }
 }
 
+/// Make sure the schema of database is understood, and run pending migrations if `auto_migrate`.
+///
+/// Panic if the schema is newer than this manager, i.e. some applied migrations are unknown.
+#[tracing::instrument(skip_all)]
+pub async fn prepare_schema(db_conn: &DatabaseConnection, auto_migrate: bool) {
+    let known_migrations: HashSet<String> = Migrator::migrations().iter()
+        .map(|v| v.name().to_owned())
+        .collect();
+    let applied_migrations = Migrator::get_migration_models(db_conn).await
+        .unwrap_or_else(|e| panic!("Failed to get applied migrations: {e:?}"));
+    let unknown_migrations: Vec<_> = applied_migrations.iter()
+        .map(|v| v.version.as_str())
+        .filter(|v| !known_migrations.contains(*v))
+        .collect();
+    if !unknown_migrations.is_empty() {
+        error!("Schema of database is newer than this manager. Unknown migrations: {:?}", unknown_migrations);
+        panic!("Schema of database is newer than this manager. Please upgrade the manager");
+    }
+
+    let pending_migrations = Migrator::get_pending_migrations(db_conn).await
+        .unwrap_or_else(|e| panic!("Failed to get pending migrations: {e:?}"));
+    if pending_migrations.is_empty() {
+        info!("Schema of database is up to date");
+        return;
+    }
+    let pending_names: Vec<_> = pending_migrations.iter().map(|v| v.name().to_owned()).collect();
+    if !auto_migrate {
+        warn!("Schema of database is out of date. Pending migrations: {:?}", pending_names);
+        return;
+    }
+    info!("Run pending migrations: {:?}", pending_names);
+    Migrator::up(db_conn, None).await
+        .unwrap_or_else(|e| panic!("Failed to run migrations: {e:?}"));
+}
+
 /// Convert and log database error.
 pub fn handle_db_error(e: DbErr) -> errors::ResponseError {
     error!("Database error: {:?}", e);
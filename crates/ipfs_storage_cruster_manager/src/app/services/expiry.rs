@@ -0,0 +1,259 @@
+//! Expiry of pins.
+//!
+//! A pin with `expires_at` is marked as `Deleted` after that time, then unpinned from all its nodes.
+//! It's marked by a conditional update, so a pin extended by an upload meanwhile is kept.
+
+#[allow(unused_imports)]
+use tracing::{error, debug, warn, info, trace};
+use axum::http::StatusCode;
+use crate::imports::dao_imports::*;
+use crate::app::{AppState, daos, dtos, errors, services};
+use crate::app::common::ApiResult;
+use crate::app::services::audit::{Actor, AuditAction, AuditEntry};
+
+/// About 100 years.
+const MAX_TTL_SECS: u64 = 100 * 365 * 24 * 3600;
+/// Max number of pins to reap in one round.
+const REAP_BATCH_SIZE: u64 = 100;
+
+/// Config of the expiry reaper.
+#[derive(Debug, Clone)]
+pub struct ExpiryConfig {
+    pub interval_time_ms: u64,
+    /// Timeout of unpinning in each node.
+    pub timeout_ms: u64,
+}
+
+/// Get the expiry time from `expiresAt` or `ttlSecs`. `None` means never expire.
+pub(crate) fn resolve_expires_at(args: &dtos::PinExpiryArgs) -> ApiResult<Option<chrono::DateTime<chrono::Utc>>> {
+    let args_error = |msg: &'static str| errors::REQUEST_ARGS_ERROR.clone_to_error()
+        .modify_msg(msg)
+        .modify_status_code(StatusCode::BAD_REQUEST);
+    let now = chrono::Utc::now();
+    let expires_at = match (args.expires_at, args.ttl_secs) {
+        (None, None) => return Ok(None),
+        (Some(expires_at), None) => expires_at,
+        (None, Some(ttl_secs)) => {
+            if ttl_secs > MAX_TTL_SECS {
+                return Err(args_error("ttlSecs is too large"));
+            }
+            now + chrono::Duration::seconds(ttl_secs as i64)
+        }
+        (Some(_), Some(_)) => return Err(args_error("expiresAt and ttlSecs should not be used together")),
+    };
+    if expires_at <= now {
+        return Err(args_error("Expiry time should be in the future"));
+    }
+    Ok(Some(expires_at))
+}
+
+/// Get the expiry time of a pin service request from `expiresAt` or `ttlSecs` in its meta.
+pub(crate) fn resolve_expires_at_from_meta(meta: Option<&std::collections::HashMap<String, String>>) -> ApiResult<Option<chrono::DateTime<chrono::Utc>>> {
+    let args_error = |msg: &'static str| errors::REQUEST_ARGS_ERROR.clone_to_error()
+        .modify_msg(msg)
+        .modify_status_code(StatusCode::BAD_REQUEST);
+    let Some(meta) = meta else {
+        return Ok(None);
+    };
+    let expires_at = match meta.get("expiresAt") {
+        Some(v) => Some(chrono::DateTime::parse_from_rfc3339(v)
+            .map_err(|_| args_error("expiresAt in meta should be RFC 3339 time"))?
+            .with_timezone(&chrono::Utc)),
+        None => None,
+    };
+    let ttl_secs = match meta.get("ttlSecs") {
+        Some(v) => Some(v.parse()
+            .map_err(|_| args_error("ttlSecs in meta should be a number"))?),
+        None => None,
+    };
+    resolve_expires_at(&dtos::PinExpiryArgs { expires_at, ttl_secs })
+}
+
+/// Extend the expiry time of a pin owned by the user.
+///
+/// The pin might be shared with others, so the expiry time is never brought forward.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn extend_pin_expiry(state: &AppState, user_id: &str, request_id: &str, args: &dtos::PinExpiryArgs) -> ApiResult<pin::Model> {
+    let expires_at = resolve_expires_at(args)?
+        .ok_or_else(|| errors::REQUEST_ARGS_ERROR.clone_to_error()
+            .modify_msg("expiresAt or ttlSecs is required")
+            .modify_status_code(StatusCode::BAD_REQUEST))?;
+    let (_, pin_model) = services::pin_service::find_pin_request(state, user_id, request_id).await?;
+    if pin_model.status == sea_orm_active_enums::Status::Deleted {
+        return Err(errors::PIN_EXPIRED.clone_to_error()
+            .modify_status_code(StatusCode::GONE));
+    }
+    match pin_model.expires_at {
+        Some(old) if expires_at < old => Err(errors::REQUEST_ARGS_ERROR.clone_to_error()
+            .modify_msg("Expiry time can only be extended")
+            .modify_status_code(StatusCode::BAD_REQUEST)),
+        Some(_) => {
+            let kept = daos::keep_pin_until(&pin_model.id, Some(expires_at), &state.db_conn)
+                .await.map_err(services::db::handle_db_error)?;
+            if !kept {
+                return Err(errors::PIN_EXPIRED.clone_to_error()
+                    .modify_status_code(StatusCode::GONE));
+            }
+            info!("Extend expiry of pin {} to {}", pin_model.id, expires_at);
+            Ok(pin::Model { expires_at: Some(expires_at), ..pin_model })
+        }
+        None => {
+            debug!("Pin {} never expires, no need to extend", pin_model.id);
+            Ok(pin_model)
+        }
+    }
+}
+
+/// Spawn a task to unpin expired pins regularly.
+pub fn spawn_expiry_reaper(state: AppState, config: ExpiryConfig) -> tokio::task::JoinHandle<()> {
+    info!("Expiry reaper starts. {:?}", config);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(config.interval_time_ms));
+        loop {
+            interval.tick().await;
+            reap_expired_pins(&state, &config).await;
+        }
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn reap_expired_pins(state: &AppState, config: &ExpiryConfig) {
+    let now = chrono::Utc::now();
+    let pin_vec = match state.repository.find_expired_pins(now, REAP_BATCH_SIZE).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to find expired pins. msg: {e:?}");
+            return;
+        }
+    };
+    for pin_model in pin_vec {
+        // claim it first, so that it's not unpinned after being extended by an upload
+        let res = state.repository.claim_expired_pin(&pin_model.id, now).await;
+        let audit_entry = AuditEntry::new(&Actor::System, AuditAction::Expire)
+            .cid(pin_model.cid.clone())
+            .result(&res);
+        match res {
+            Ok(true) => services::audit::record(state, audit_entry).await,
+            Ok(false) => {
+                debug!("Expired pin {} is changed before claimed. Skip it", pin_model.id);
+                continue;
+            }
+            Err(e) => {
+                services::audit::record(state, audit_entry).await;
+                error!("Failed to claim expired pin {}. msg: {:?}", pin_model.id, e);
+                continue;
+            }
+        }
+        if let Err(e) = reap_pin(state, &pin_model, config.timeout_ms).await {
+            error!("Failed to reap expired pin {}. msg: {:?}", pin_model.id, e);
+        }
+    }
+
+    // retry nodes failed to unpin in previous rounds
+    let pin_vec = match state.repository.find_deleted_pins_with_replicas(now, REAP_BATCH_SIZE).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to find expired pins not unpinned. msg: {e:?}");
+            return;
+        }
+    };
+    for pin_model in pin_vec {
+        if let Err(e) = reap_pin(state, &pin_model, config.timeout_ms).await {
+            error!("Failed to reap expired pin {}. msg: {:?}", pin_model.id, e);
+        }
+    }
+}
+
+/// Unpin the claimed pin from all its nodes.
+///
+/// Nodes failed to unpin are kept in `pins_stored_nodes`, and would be retried in next round.
+async fn reap_pin(state: &AppState, pin_model: &pin::Model, timeout_ms: u64) -> ApiResult<()> {
+    let stored_nodes = state.repository.find_replicas(&pin_model.id)
+        .await.map_err(services::db::handle_db_error)?;
+
+    let mut unpinned_node_ids = vec![];
+    let mut all_unpinned = true;
+    for (stored_node, node_model) in stored_nodes {
+        let unpinned = match node_model {
+            Some(node_model) => {
+                let unpinned = services::file::rm_pin_from_node(
+                    state, &node_model.rpc_address, node_model.wrapper_admin_address.as_deref(), &pin_model.cid, timeout_ms).await;
+                let mut audit_entry = AuditEntry::new(&Actor::System, AuditAction::Unpin)
+                    .cid(pin_model.cid.clone())
+                    .node(stored_node.node_id.clone());
+                if !unpinned {
+                    audit_entry = audit_entry.failed("Failed to unpin");
+                }
+                services::audit::record(state, audit_entry).await;
+                unpinned
+            }
+            // the node has been removed
+            None => true,
+        };
+        if !unpinned {
+            warn!("Failed to unpin expired pin {} in node {}", pin_model.cid, stored_node.node_id);
+            all_unpinned = false;
+            continue;
+        }
+        unpinned_node_ids.push(stored_node.node_id);
+    }
+    state.repository.remove_replicas(&pin_model.id, unpinned_node_ids)
+        .await.map_err(services::db::handle_db_error)?;
+    if !all_unpinned {
+        return Ok(());
+    }
+
+    // uploaded files are also pinned in master IPFS node
+    if !services::file::rm_pin_by_rpc(&state.ipfs_client, &pin_model.cid, timeout_ms).await {
+        warn!("Failed to unpin expired pin {} in master node", pin_model.cid);
+    }
+    info!("Expired pin {} of cid {} is deleted", pin_model.id, pin_model.cid);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn test_pin_expiry_is_compared_in_db() {
+        let conn = connect_test_db().await;
+        let state = test_db_state(&conn);
+        let now = chrono::Utc::now();
+        let expired = now - chrono::Duration::seconds(1);
+        for id in ["kept", "claimed"] {
+            state.repository.insert_pin(pin::Model {
+                expires_at: Some(expired),
+                ..test_pin(id, &format!("cid {id}"))
+            }).await.unwrap();
+        }
+
+        // extended by an upload before claimed, then never brought forward
+        let later = now + chrono::Duration::hours(1);
+        assert!(state.repository.keep_pin_until("kept", Some(later)).await.unwrap());
+        assert!(state.repository.keep_pin_until("kept", Some(now + chrono::Duration::minutes(1))).await.unwrap());
+        assert!(!daos::claim_expired_pin("kept", now, &conn).await.unwrap());
+        let pin_model = state.repository.find_pin_by_id("kept").await.unwrap().unwrap();
+        assert_eq!(pin_model.expires_at.unwrap().timestamp(), later.timestamp());
+
+        // claimed once, then not revived
+        assert!(daos::claim_expired_pin("claimed", now, &conn).await.unwrap());
+        assert!(!daos::claim_expired_pin("claimed", now, &conn).await.unwrap());
+        assert!(!state.repository.keep_pin_until("claimed", Some(later)).await.unwrap());
+        let pin_model = state.repository.find_pin_by_id("claimed").await.unwrap().unwrap();
+        assert_eq!(pin_model.status, sea_orm_active_enums::Status::Deleted);
+        assert_eq!(pin_model.expires_at.unwrap().timestamp(), expired.timestamp());
+
+        // pin service requests take expiry from meta
+        let pin_request = |ttl_secs: &str| ipfs_pin_service_axum_api_framework::models::Pin::new(
+            "cid kept".to_string(), None, None,
+            Some(std::collections::HashMap::from([("ttlSecs".to_string(), ttl_secs.to_string())])),
+        );
+        let err = services::pin_service::add_pin_request(&state, "alice", pin_request("soon")).await.unwrap_err();
+        assert_eq!(err.status_code, Some(StatusCode::BAD_REQUEST));
+        services::pin_service::add_pin_request(&state, "alice", pin_request("7200")).await.unwrap();
+        let pin_model = state.repository.find_pin_by_id("kept").await.unwrap().unwrap();
+        assert!(pin_model.expires_at.unwrap() > later);
+    }
+}
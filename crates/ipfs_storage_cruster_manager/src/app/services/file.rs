@@ -3,14 +3,47 @@ use tracing::{debug, error, info, trace, warn};
 use axum::http;
 use http_body_util::BodyExt;
 use tiny_ipfs_client::ReqwestIpfsClient;
-use crate::app::{AppState, dtos, errors};
+use ipfs_node_wrapper_client::admin::IpfsNodeWrapperAdminClient;
+use ipfs_node_wrapper_client::ipfs_node_wrapper_structs::admin::models::PinStatus as WrapperPinStatus;
+use ipfs_node_wrapper_structs::public::signature;
+use crate::imports::dao_imports::*;
+use crate::app::{AppState, dtos, errors, services};
 use crate::app::common::ApiResult;
 use crate::app::errors::ResponseError;
+use crate::app::services::audit::{Actor, AuditAction, AuditEntry};
 use crate::file_decision::TargetAdminIpfsNodeMessage;
+use crate::repository::NodeFilter;
+use crate::app_builder::PinMode;
 use crate::utils::move_entry_between_header_map;
 
+/// Config about how to pin files to storage nodes.
+#[derive(Debug, Clone)]
+pub struct PinConfig {
+    pub mode: PinMode,
+    /// Interval of polling the status of pin. Only used by `Wrapper` mode.
+    pub poll_interval_ms: u64,
+    /// Give up waiting for a pin after this time. Only used by `Wrapper` mode.
+    pub timeout_ms: u64,
+}
+
+/// Config to sign download URLs, verified by Wrappers with the same secret.
+#[derive(Debug, Clone)]
+pub struct DownloadUrlConfig {
+    pub secret: String,
+    pub ttl_secs: u64,
+}
+
+/// Append the signature of downloading `cid` by the user to the download URL.
+pub(crate) fn sign_download_url(config: &DownloadUrlConfig, url: &str, cid: &str, user_id: Option<&str>) -> String {
+    let expires = chrono::Utc::now().timestamp() + config.ttl_secs as i64;
+    let query = signature::signed_query(config.secret.as_bytes(), cid, expires, user_id);
+    format!("{url}?{query}")
+}
+
 /// Add a file to ipfs by stream, return the message of the added file.
-pub(crate) async fn add_file_to_ipfs(state: &AppState, mut req: axum::extract::Request) -> ApiResult<dtos::IpfsAddFileResponse> {
+///
+/// Abort if the request body is larger than `max_bytes`.
+pub(crate) async fn add_file_to_ipfs(state: &AppState, mut req: axum::extract::Request, max_bytes: Option<u64>) -> ApiResult<dtos::IpfsAddFileResponse> {
     // log
     let file_size = req.headers().get(http::header::CONTENT_LENGTH);
     if file_size.is_none() {
@@ -19,6 +52,17 @@ pub(crate) async fn add_file_to_ipfs(state: &AppState, mut req: axum::extract::R
         info!("Add file. Content size: {:?}", file_size);
     }
 
+    // check size before and during streaming
+    if let Some(max_bytes) = max_bytes {
+        let file_size = file_size
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if file_size.is_some_and(|v| v > max_bytes) {
+            return Err(services::quota::file_too_large());
+        }
+        req = req.map(|body| axum::body::Body::new(http_body_util::Limited::new(body, max_bytes as usize)));
+    }
+
     // handle url
     let url = format!("http://{}/api/v0/add", state.ipfs_client.rpc_address);
     *req.uri_mut() = http::uri::Uri::try_from(url).expect("Impossible fail to parse url");
@@ -39,9 +83,11 @@ pub(crate) async fn add_file_to_ipfs(state: &AppState, mut req: axum::extract::R
     let res = state.raw_hyper_client
         .request(req)
         .await
-        .map_err(|_e|
-            errors::IPFS_REQUEST_ERROR.clone_to_error_with_log_with_content(_e)
-        )?;
+        .map_err(|e| if is_length_limit_error(&e) {
+            services::quota::file_too_large()
+        } else {
+            errors::IPFS_REQUEST_ERROR.clone_to_error_with_log_with_content(e)
+        })?;
     if !res.status().is_success() {
         error!("Failed to add file to IPFS. Status code: {}", res.status());
         return Err(errors::IPFS_RESPOND_ERROR.clone_to_error());
@@ -63,25 +109,63 @@ pub(crate) async fn add_file_to_ipfs(state: &AppState, mut req: axum::extract::R
     Ok(body)
 }
 
-/// Make decision and store file with certain CID to cluster.
+/// Whether the error is caused by the body limited by `http_body_util::Limited`.
+fn is_length_limit_error(e: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(e);
+    while let Some(e) = source {
+        if e.is::<http_body_util::LengthLimitError>() {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// Make decision which nodes to store file with certain CID firstly.
 ///
-/// Return the list of nodes that stores the file.
+/// Must be followed by `store_file_to_decided_nodes` to finish the storage.
 #[tracing::instrument(skip_all)]
-pub(crate) async fn store_file_to_cluster(state: &AppState, cid: String) -> ApiResult<Vec<TargetAdminIpfsNodeMessage>> {
+pub(crate) async fn decide_store_nodes(state: &AppState, cid: &str) -> ApiResult<Vec<TargetAdminIpfsNodeMessage>> {
     let target_node_list = state.file_storage_decision_maker
-        .decide_store_node(&cid, &state.db_conn, &state.reqwest_client)
+        .decide_store_node(cid, state.repository.as_ref(), &state.reqwest_client)
         .await?;
     // error when empty nodes
     if target_node_list.is_empty() {
+        let _ = state.file_storage_decision_maker.finish_storage(cid).await;
         return Err(errors::IPFS_NODE_CLUSTER_UNHEALTHY.clone_to_error());
     }
+    Ok(target_node_list)
+}
+
+/// Store file with certain CID to the nodes decided by `decide_store_nodes`.
+/// Retry on other nodes when a store failure occurs.
+///
+/// The storage is finished even if failed, so that the CID could be stored again.
+/// Return the list of nodes that stores the file.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn store_file_to_decided_nodes(state: &AppState,
+                                                cid: String,
+                                                target_node_list: Vec<TargetAdminIpfsNodeMessage>)
+                                                -> ApiResult<Vec<TargetAdminIpfsNodeMessage>> {
+    let res = store_file_with_retry(state, &cid, target_node_list).await;
+    let finish_res = state.file_storage_decision_maker
+        .finish_storage(&cid)
+        .await;
+    let final_stored_nodes = res?;
+    finish_res?;
+    Ok(final_stored_nodes)
+}
+
+async fn store_file_with_retry(state: &AppState,
+                               cid: &str,
+                               target_node_list: Vec<TargetAdminIpfsNodeMessage>)
+                               -> ApiResult<Vec<TargetAdminIpfsNodeMessage>> {
+    let cid = cid.to_owned();
     debug!("Firstly store pin {cid} in nodes: {target_node_list:?}");
     // send file to nodes
     let mut join_set = tokio::task::JoinSet::new();
     for node in target_node_list.into_iter() {
-        let client = state.reqwest_client.clone();
-        let task = add_pin_to_node(client, node, cid.clone());
-        join_set.spawn(task);
+        join_set.spawn(add_pin_to_node_with_audit(state.clone(), node, cid.clone()));
     }
 
     let mut final_stored_nodes = Vec::new();
@@ -105,27 +189,79 @@ pub(crate) async fn store_file_to_cluster(state: &AppState, cid: String) -> ApiR
 
         // Failed to add pin, retry
         let retry_target_node_list = state.file_storage_decision_maker
-            .decide_store_node_fail_one(&cid, &state.db_conn, &state.reqwest_client)
+            .decide_store_node_fail_one(&cid, state.repository.as_ref(), &state.reqwest_client)
             .await?;
         debug!("Retry to add pin {cid} to nodes: {retry_target_node_list:?}");
         for node in retry_target_node_list.into_iter() {
-            let client = state.reqwest_client.clone();
-            let task = add_pin_to_node(client, node, cid.clone());
-            join_set.spawn(task);
+            join_set.spawn(add_pin_to_node_with_audit(state.clone(), node, cid.clone()));
         }
     }
 
     info!("Finally store pin {cid} in nodes: {final_stored_nodes:?}");
-    state.file_storage_decision_maker
-        .finish_storage(&cid)
-        .await?;
     Ok(final_stored_nodes)
 }
 
+/// Store the pin to one available node other than `exclude_node_id`, and record it to database.
+///
+/// Try candidate nodes in random order until one succeeds.
+/// Return the node that stores the pin.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn rehome_pin(state: &AppState, pin_model: &pin::Model, exclude_node_id: &str, actor: &Actor) -> ApiResult<TargetAdminIpfsNodeMessage> {
+    let filter = NodeFilter {
+        status_in: vec![sea_orm_active_enums::NodeStatus::Online],
+        id_not_in: vec![exclude_node_id.to_owned()],
+        ..Default::default()
+    };
+    let mut candidates = state.repository.find_admin_nodes(&filter)
+        .await.map_err(services::db::handle_db_error)?;
+    fastrand::shuffle(&mut candidates);
+
+    for node in candidates {
+        let node_id = node.id.clone();
+        let res = add_pin_to_node(state.clone(), node, pin_model.cid.clone()).await;
+        let audit_entry = AuditEntry::new(actor, AuditAction::Rehome)
+            .cid(pin_model.cid.clone())
+            .node(node_id)
+            .detail(format!("from node {exclude_node_id}"))
+            .result(&res);
+        services::audit::record(state, audit_entry).await;
+        let Ok(node) = res else {
+            continue;
+        };
+        state.repository.add_replicas(&pin_model.id, vec![node.id.clone()])
+            .await.map_err(services::db::handle_db_error)?;
+        info!("Re-home pin {} to node {}", pin_model.cid, node.id);
+        return Ok(node);
+    }
+
+    error!("No node could store pin {}", pin_model.cid);
+    Err(errors::IPFS_NODE_CLUSTER_UNHEALTHY.clone_to_error())
+}
+
+/// Add pin to a storage node like `add_pin_to_node`, and write the audit log.
+async fn add_pin_to_node_with_audit(state: AppState, node_message: TargetAdminIpfsNodeMessage, cid: String) -> ApiResult<TargetAdminIpfsNodeMessage> {
+    let audit_entry = AuditEntry::new(&Actor::System, AuditAction::Pin)
+        .cid(cid.clone())
+        .node(node_message.id.clone());
+    let res = add_pin_to_node(state.clone(), node_message, cid).await;
+    services::audit::record(&state, audit_entry.result(&res)).await;
+    res
+}
+
+/// Add pin to a storage node in the way of `PinMode`.
+///
+/// Return `TargetIPFSNodeMessage` when success.
+async fn add_pin_to_node(state: AppState, node_message: TargetAdminIpfsNodeMessage, cid: String) -> ApiResult<TargetAdminIpfsNodeMessage> {
+    match state.pin_config.mode {
+        PinMode::Rpc => add_pin_to_node_by_rpc(state.reqwest_client.clone(), node_message, cid).await,
+        PinMode::Wrapper => add_pin_to_node_by_wrapper(&state, node_message, cid).await,
+    }
+}
+
 /// Send add pin RPC to an IPFS node.
 ///
 /// Return `TargetIPFSNodeMessage` when success.
-async fn add_pin_to_node(client: reqwest::Client, node_message: TargetAdminIpfsNodeMessage, cid: String) -> ApiResult<TargetAdminIpfsNodeMessage> {
+async fn add_pin_to_node_by_rpc(client: reqwest::Client, node_message: TargetAdminIpfsNodeMessage, cid: String) -> ApiResult<TargetAdminIpfsNodeMessage> {
     trace!("Begin storing cid {cid} to {node_message:?}");
     let client = ReqwestIpfsClient::new_with_reqwest_client(node_message.rpc_address.clone(), client);
     let res = client.add_pin_recursive(&cid, None).await
@@ -137,3 +273,201 @@ async fn add_pin_to_node(client: reqwest::Client, node_message: TargetAdminIpfsN
     }
     Ok(node_message)
 }
+
+/// Launch a background pin by the admin API of Wrapper,
+/// then poll the status until it's `Pinned` or `Failed`.
+///
+/// Return `TargetIPFSNodeMessage` when success.
+async fn add_pin_to_node_by_wrapper(state: &AppState, node_message: TargetAdminIpfsNodeMessage, cid: String) -> ApiResult<TargetAdminIpfsNodeMessage> {
+    trace!("Begin storing cid {cid} to {node_message:?} by Wrapper");
+    let Some(admin_address) = node_message.wrapper_admin_address.clone() else {
+        error!("Failed to add pin of {cid} to node {}, because it has no Wrapper admin address", node_message.id);
+        return Err(errors::WRAPPER_FAIL.clone_to_error());
+    };
+    let client = IpfsNodeWrapperAdminClient::new_with_reqwest_client(admin_address, state.reqwest_client.clone());
+    client.add_pin_background(cid.clone(), None).await
+        .map_err(|e| {
+            error!("Failed to add pin of {cid} to Wrapper {}, because: {e:?}", client.base_url);
+            Into::<ResponseError>::into(e)
+        })?;
+
+    let poll_task = async {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(state.pin_config.poll_interval_ms));
+        loop {
+            interval.tick().await;
+            let res = client.check_pin(&cid).await;
+            match res {
+                Ok(res) => match res.data.status {
+                    WrapperPinStatus::Pinned => return Ok(()),
+                    WrapperPinStatus::Pinning => continue,
+                    status => {
+                        error!("Wrapper {} failed to pin {cid}. Status: {status:?}", client.base_url);
+                        return Err(errors::WRAPPER_PIN_FAILED.clone_to_error());
+                    }
+                },
+                // might be a temporary error, keep polling until timeout
+                Err(e) => warn!("Failed to check pin {cid} in Wrapper {}. msg: {e:?}", client.base_url),
+            }
+        }
+    };
+    let res = tokio::time::timeout(
+        tokio::time::Duration::from_millis(state.pin_config.timeout_ms),
+        poll_task,
+    ).await;
+    match res {
+        Ok(Ok(())) => Ok(node_message),
+        Ok(Err(e)) => Err(e),
+        Err(_) => {
+            error!("Wait for Wrapper {} to pin {cid} timeout", client.base_url);
+            Err(errors::WRAPPER_PIN_TIMEOUT.clone_to_error())
+        }
+    }
+}
+
+/// Check whether a storage node has pinned the CID, in the way of `PinMode`.
+///
+/// Return false if failed to check.
+pub(crate) async fn is_pinned_in_node(state: &AppState,
+                                      rpc_address: &str,
+                                      wrapper_admin_address: Option<&str>,
+                                      cid: &str,
+                                      timeout_ms: u64)
+                                      -> bool {
+    let timeout = tokio::time::Duration::from_millis(timeout_ms);
+    match (state.pin_config.mode, wrapper_admin_address) {
+        (PinMode::Rpc, _) => {
+            let ipfs_client = state.get_ipfs_client_with_rpc_addr(rpc_address.to_owned());
+            let res = tokio::time::timeout(timeout, ipfs_client.get_one_pin(cid, false)).await;
+            matches!(res, Ok(Ok(Some(_))))
+        }
+        (PinMode::Wrapper, Some(admin_address)) => {
+            let client = IpfsNodeWrapperAdminClient::new_with_reqwest_client(
+                admin_address.to_owned(), state.reqwest_client.clone());
+            let res = tokio::time::timeout(timeout, client.check_pin(cid)).await;
+            matches!(res, Ok(Ok(res)) if matches!(res.data.status, WrapperPinStatus::Pinned))
+        }
+        (PinMode::Wrapper, None) => false,
+    }
+}
+
+/// Remove the pin from a storage node, in the way of `PinMode`.
+///
+/// Return true if it's not pinned anymore.
+pub(crate) async fn rm_pin_from_node(state: &AppState,
+                                     rpc_address: &str,
+                                     wrapper_admin_address: Option<&str>,
+                                     cid: &str,
+                                     timeout_ms: u64)
+                                     -> bool {
+    match (state.pin_config.mode, wrapper_admin_address) {
+        (PinMode::Rpc, _) => {
+            let ipfs_client = state.get_ipfs_client_with_rpc_addr(rpc_address.to_owned());
+            rm_pin_by_rpc(&ipfs_client, cid, timeout_ms).await
+        }
+        (PinMode::Wrapper, Some(admin_address)) => rm_pin_by_wrapper(state, admin_address, cid, timeout_ms).await,
+        (PinMode::Wrapper, None) => {
+            error!("Failed to remove pin of {cid} from node {rpc_address}, because it has no Wrapper admin address");
+            false
+        }
+    }
+}
+
+/// Remove the pin by IPFS RPC. Return true if it's not pinned anymore.
+pub(crate) async fn rm_pin_by_rpc(ipfs_client: &ReqwestIpfsClient, cid: &str, timeout_ms: u64) -> bool {
+    let timeout = tokio::time::Duration::from_millis(timeout_ms);
+    match tokio::time::timeout(timeout, ipfs_client.remove_pin_recursive(cid)).await {
+        Ok(Ok(())) => true,
+        // Kubo fails when the CID is not pinned
+        _ => matches!(
+            tokio::time::timeout(timeout, ipfs_client.get_one_pin(cid, false)).await,
+            Ok(Ok(None))
+        ),
+    }
+}
+
+/// Launch a background unpin by the admin API of Wrapper,
+/// then poll the status until it's not `Pinned` or `Pinning`.
+///
+/// Return true if it's not pinned anymore.
+async fn rm_pin_by_wrapper(state: &AppState, admin_address: &str, cid: &str, timeout_ms: u64) -> bool {
+    let client = IpfsNodeWrapperAdminClient::new_with_reqwest_client(
+        admin_address.to_owned(), state.reqwest_client.clone());
+    let task = async {
+        if let Err(e) = client.rm_pin(cid.to_owned()).await {
+            warn!("Failed to remove pin of {cid} from Wrapper {}, because: {e:?}", client.base_url);
+            return false;
+        }
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(state.pin_config.poll_interval_ms));
+        loop {
+            interval.tick().await;
+            match client.check_pin(cid).await {
+                Ok(res) => match res.data.status {
+                    WrapperPinStatus::Pinned | WrapperPinStatus::Pinning => continue,
+                    WrapperPinStatus::Failed | WrapperPinStatus::NotFound => return true,
+                },
+                // might be a temporary error, keep polling until timeout
+                Err(e) => warn!("Failed to check pin {cid} in Wrapper {}. msg: {e:?}", client.base_url),
+            }
+        }
+    };
+    tokio::time::timeout(tokio::time::Duration::from_millis(timeout_ms), task).await
+        .unwrap_or_else(|_| {
+            error!("Wait for Wrapper {} to remove pin {cid} timeout", client.base_url);
+            false
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use axum::http::StatusCode;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn test_wrapper_pin_mode_without_rpc() {
+        // fake Wrapper admin service, with a pinned CID
+        let pinned = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let wrapper_app = axum::Router::new()
+            .route("/api/info", axum::routing::get(|| async {
+                axum::Json(serde_json::json!({ "code": "S0000", "message": "", "data": { "id": "wrapper peer" } }))
+            }))
+            .route("/api/pin/:cid", axum::routing::get({
+                let pinned = pinned.clone();
+                move || async move {
+                    let status = if pinned.load(std::sync::atomic::Ordering::SeqCst) { "pinned" } else { "not_found" };
+                    axum::Json(serde_json::json!({ "code": "S0000", "message": "", "data": { "status": status } }))
+                }
+            }))
+            .route("/api/pin", axum::routing::delete({
+                let pinned = pinned.clone();
+                move || async move {
+                    pinned.store(false, std::sync::atomic::Ordering::SeqCst);
+                    (StatusCode::ACCEPTED, axum::Json(serde_json::json!({ "code": "S0000", "message": "", "data": null })))
+                }
+            }));
+        let wrapper_address = serve_fake(wrapper_app).await;
+        // nothing listens
+        let rpc_address = "127.0.0.1:1";
+
+        let conn = connect_test_db().await;
+        let state = AppState {
+            pin_config: Arc::new(services::file::PinConfig {
+                mode: PinMode::Wrapper,
+                poll_interval_ms: 10,
+                timeout_ms: 1000,
+            }),
+            ..test_db_state(&conn)
+        };
+
+        let node_model = services::ipfs::add_node_to_cluster(
+            &state, rpc_address.to_string(), "1.1.1.1:80".to_string(), wrapper_address.clone(), Some("wrapper peer"),
+        ).await.unwrap();
+        assert_eq!(node_model.peer_id, "wrapper peer");
+        assert_eq!(node_model.node_status, sea_orm_active_enums::NodeStatus::Online);
+
+        assert!(services::file::is_pinned_in_node(&state, rpc_address, Some(&wrapper_address), "cid", 1000).await);
+        assert!(services::file::rm_pin_from_node(&state, rpc_address, Some(&wrapper_address), "cid", 1000).await);
+        assert!(!services::file::is_pinned_in_node(&state, rpc_address, Some(&wrapper_address), "cid", 1000).await);
+    }
+}
@@ -0,0 +1,236 @@
+//! Monitor the health of IPFS nodes and their Wrappers.
+
+#[allow(unused_imports)]
+use tracing::{error, debug, warn, info, trace};
+use serde::Serialize;
+use tiny_ipfs_client::IdResponse;
+use ipfs_node_wrapper_client::admin::IpfsNodeWrapperAdminClient;
+use ipfs_node_wrapper_client::public::IpfsNodeWrapperPublicClient;
+use crate::imports::dao_imports::*;
+use crate::app::{AppState, daos, services};
+use crate::app_builder::PinMode;
+use crate::repository::NodeFilter;
+use crate::utils::now_ms;
+
+/// Config of the health monitor.
+#[derive(Debug, Clone)]
+pub struct HealthCheckConfig {
+    pub interval_time_ms: u64,
+    /// Timeout of each check.
+    pub timeout_ms: u64,
+    /// Consecutive failures before an `Online` node becomes `Unhealthy`.
+    pub failure_threshold: u32,
+    /// Consecutive successes before a not `Online` node is re-bootstrapped.
+    pub success_threshold: u32,
+    /// Consecutive failures before a node becomes `Offline`.
+    pub offline_threshold: u32,
+    /// Fail the check if a registered node sends no heartbeat in this time.
+    pub heartbeat_timeout_ms: u64,
+}
+
+/// Health record of a node. Only kept in memory.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeHealthRecord {
+    pub consecutive_failures: u32,
+    pub consecutive_successes: u32,
+    /// Unix timestamp (ms) of the last successful check.
+    pub last_seen_ms: Option<u64>,
+    /// Error of the last failed check.
+    pub last_error: Option<String>,
+    /// Unix timestamp (ms) of the last heartbeat. `None` if the node never sends heartbeat.
+    pub last_heartbeat_ms: Option<u64>,
+}
+
+/// Spawn a task to check the health of all nodes regularly.
+pub fn spawn_health_monitor(state: AppState, config: HealthCheckConfig) -> tokio::task::JoinHandle<()> {
+    info!("Health monitor starts. {:?}", config);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(config.interval_time_ms));
+        loop {
+            interval.tick().await;
+            check_all_nodes(&state, &config).await;
+        }
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn check_all_nodes(state: &AppState, config: &HealthCheckConfig) {
+    let node_vec = match state.repository.find_nodes(&NodeFilter::default()).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to find nodes to check health. msg: {e:?}");
+            return;
+        }
+    };
+
+    let mut join_set = tokio::task::JoinSet::new();
+    for node_model in node_vec {
+        let state = state.clone();
+        let config = config.clone();
+        join_set.spawn(async move {
+            let res = match check_heartbeat(&state, &node_model, config.heartbeat_timeout_ms).await {
+                Ok(()) => check_node(&state, &node_model, config.timeout_ms).await,
+                Err(e) => Err(e),
+            };
+            let res = match res {
+                Ok(id_info) => {
+                    let ipfs_client = state.get_ipfs_client_with_rpc_addr(node_model.rpc_address.clone());
+                    let record_res = services::ipfs::record_node_capabilities(
+                        &state, &node_model.id, &ipfs_client, id_info, config.timeout_ms).await;
+                    if let Err(e) = record_res {
+                        error!("Failed to record capabilities of node {}. msg: {:?}", node_model.id, e);
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            };
+            apply_check_result(&state, &config, node_model, res).await;
+        });
+    }
+
+    while let Some(join_res) = join_set.join_next().await {
+        // a panic of one check should not stop the monitor
+        if let Err(join_err) = join_res {
+            if join_err.is_panic() {
+                error!("Health check of a node panicked. msg: {:?}", join_err);
+            }
+        }
+    }
+}
+
+/// Record a heartbeat from a registered node.
+pub async fn record_heartbeat(state: &AppState, node_id: &str) {
+    let mut entry = state.node_health_records
+        .entry_async(node_id.to_owned()).await
+        .or_default();
+    entry.get_mut().last_heartbeat_ms = Some(now_ms());
+}
+
+/// Fail if the node sent heartbeats before but stops now.
+async fn check_heartbeat(state: &AppState, node_model: &node::Model, heartbeat_timeout_ms: u64) -> Result<(), String> {
+    let last_heartbeat_ms = state.node_health_records
+        .read_async(&node_model.id, |_, v| v.last_heartbeat_ms).await
+        .flatten();
+    match last_heartbeat_ms {
+        Some(last_heartbeat_ms) if now_ms().saturating_sub(last_heartbeat_ms) > heartbeat_timeout_ms => {
+            Err(format!("No heartbeat in {} ms", heartbeat_timeout_ms))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check IPFS RPC, Wrapper's admin and public service of a node.
+///
+/// IPFS RPC is optional in `Wrapper` pin mode, in which the Wrapper's admin service is required instead.
+///
+/// Return the id info of IPFS node when success, or `None` if IPFS RPC is unavailable in `Wrapper` pin mode.
+async fn check_node(state: &AppState, node_model: &node::Model, timeout_ms: u64) -> Result<Option<IdResponse>, String> {
+    let timeout = tokio::time::Duration::from_millis(timeout_ms);
+
+    let ipfs_client = state.get_ipfs_client_with_rpc_addr(node_model.rpc_address.clone());
+    let id_info = tokio::time::timeout(timeout, ipfs_client.get_id_info()).await
+        .map_err(|_| "IPFS RPC timeout".to_string())
+        .and_then(|res| res.map_err(|e| format!("IPFS RPC error: {e:?}")));
+    let id_info = match (id_info, state.pin_config.mode) {
+        (Ok(id_info), _) => Some(id_info),
+        (Err(e), PinMode::Rpc) => return Err(e),
+        (Err(e), PinMode::Wrapper) => {
+            debug!("IPFS RPC of node {} is unavailable: {}", node_model.id, e);
+            None
+        }
+    };
+    if let Some(id_info) = &id_info {
+        if id_info.id != node_model.peer_id {
+            return Err(format!("Peer id mismatch. Expected {}, found {}", node_model.peer_id, id_info.id));
+        }
+    }
+
+    match &node_model.wrapper_admin_address {
+        Some(admin_address) => {
+            let client = IpfsNodeWrapperAdminClient::new_with_reqwest_client(
+                admin_address.clone(), state.reqwest_client.clone());
+            let node_info = tokio::time::timeout(timeout, client.get_ipfs_node_info()).await
+                .map_err(|_| "Wrapper admin timeout".to_string())?
+                .map_err(|e| format!("Wrapper admin error: {e:?}"))?;
+            if node_info.data.id != node_model.peer_id {
+                return Err(format!("Peer id mismatch. Expected {}, found {} by Wrapper", node_model.peer_id, node_info.data.id));
+            }
+        }
+        None if state.pin_config.mode == PinMode::Wrapper => {
+            return Err("No Wrapper admin address".to_string());
+        }
+        None => {}
+    }
+
+    if let Some(public_address) = &node_model.wrapper_public_address {
+        let client = IpfsNodeWrapperPublicClient::new_with_reqwest_client(
+            public_address.clone(), state.reqwest_client.clone());
+        tokio::time::timeout(timeout, client.check_health()).await
+            .map_err(|_| "Wrapper public timeout".to_string())?
+            .map_err(|e| format!("Wrapper public error: {e:?}"))?;
+    }
+
+    Ok(id_info)
+}
+
+/// Record the result and change node status when a threshold is reached.
+async fn apply_check_result(state: &AppState, config: &HealthCheckConfig, node_model: node::Model, res: Result<(), String>) {
+    let mut entry = state.node_health_records
+        .entry_async(node_model.id.clone()).await
+        .or_default();
+    let record = entry.get_mut();
+    let current_status = node_model.node_status.clone();
+
+    match res {
+        Ok(()) => {
+            record.consecutive_failures = 0;
+            record.consecutive_successes = record.consecutive_successes.saturating_add(1);
+            record.last_seen_ms = Some(now_ms());
+            let successes = record.consecutive_successes;
+            drop(entry);
+
+            // `Maintenance` is only cleared by admin
+            let should_recover = match current_status {
+                sea_orm_active_enums::NodeStatus::Online | sea_orm_active_enums::NodeStatus::Maintenance => false,
+                sea_orm_active_enums::NodeStatus::Unhealthy | sea_orm_active_enums::NodeStatus::Offline => true,
+            };
+            if should_recover && successes >= config.success_threshold {
+                info!("Node {} recovered after {} successful checks. Re-bootstrap it", node_model.id, successes);
+                let _ = services::ipfs::bootstrap_and_check_health(
+                    state.clone(), node_model, services::audit::Actor::System).await;
+            }
+        }
+        Err(e) => {
+            warn!("Health check of node {} failed: {}", node_model.id, e);
+            record.consecutive_successes = 0;
+            record.consecutive_failures = record.consecutive_failures.saturating_add(1);
+            record.last_error = Some(e);
+            let failures = record.consecutive_failures;
+            drop(entry);
+
+            let new_status = if failures >= config.offline_threshold {
+                sea_orm_active_enums::NodeStatus::Offline
+            } else if failures >= config.failure_threshold {
+                sea_orm_active_enums::NodeStatus::Unhealthy
+            } else {
+                return;
+            };
+            // only degrade
+            let should_change = match current_status {
+                sea_orm_active_enums::NodeStatus::Online => true,
+                sea_orm_active_enums::NodeStatus::Unhealthy => new_status == sea_orm_active_enums::NodeStatus::Offline,
+                sea_orm_active_enums::NodeStatus::Offline | sea_orm_active_enums::NodeStatus::Maintenance => false,
+            };
+            if should_change {
+                warn!("Node {} failed {} checks in a row. Set status to {:?}", node_model.id, failures, new_status);
+                let res = daos::update_node_status_from(&node_model.id, current_status, new_status, &state.db_conn).await;
+                match res {
+                    Ok(true) => {}
+                    Ok(false) => info!("Status of node {} is changed during the check. Keep it", node_model.id),
+                    Err(e) => error!("Failed to set status of node in database. node_id {:?}. msg: {:?}", node_model.id, e),
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,12 @@
 #[allow(unused_imports)]
 use tracing::{error, debug, warn, info, trace};
-use tiny_ipfs_client::ReqwestIpfsClient;
+use tiny_ipfs_client::{IdResponse, ReqwestIpfsClient};
+use ipfs_node_wrapper_client::admin::IpfsNodeWrapperAdminClient;
 use crate::imports::dao_imports::*;
-use crate::app::AppState;
+use crate::app::{AppState, daos, errors, services};
+use crate::app::common::ApiResult;
+use crate::app::services::audit::{Actor, AuditAction, AuditEntry};
+use crate::app_builder::PinMode;
 
 static RE_BOOTSTRAP_TIMEOUT_MS: u64 = 2000;
 
@@ -28,7 +32,7 @@ pub(crate) async fn get_peer_id_until_success(ipfs_client: &ReqwestIpfsClient, i
 ///
 /// Return the result of database update.
 #[tracing::instrument(skip_all)]
-pub(crate) async fn bootstrap_and_check_health(state: AppState, node_model: node::Model) -> Result<node::Model, ()> {
+pub(crate) async fn bootstrap_and_check_health(state: AppState, node_model: node::Model, actor: Actor) -> Result<node::Model, ()> {
     let _target_peer_id = node_model.peer_id.clone();
     let target_ipfs_client = state.get_ipfs_client_with_rpc_addr(node_model.rpc_address.clone());
     let task = target_ipfs_client.bootstrap_add(
@@ -44,7 +48,16 @@ pub(crate) async fn bootstrap_and_check_health(state: AppState, node_model: node
     let status = match res {
         Ok(res) => {
             match res {
-                Ok(_) => sea_orm_active_enums::NodeStatus::Online,
+                Ok(_) => {
+                    let res = tokio::time::timeout(
+                        tokio::time::Duration::from_millis(RE_BOOTSTRAP_TIMEOUT_MS),
+                        services::peering::peer_with_master(&state, &target_ipfs_client),
+                    ).await;
+                    if !matches!(res, Ok(Ok(()))) {
+                        warn!("Failed to peer node with master. peer_id: {}. msg: {:?}", _target_peer_id, res);
+                    }
+                    sea_orm_active_enums::NodeStatus::Online
+                }
                 Err(_) => sea_orm_active_enums::NodeStatus::Unhealthy,
             }
         }
@@ -54,20 +67,352 @@ pub(crate) async fn bootstrap_and_check_health(state: AppState, node_model: node
         }
     };
 
-    let mut node_model: node::ActiveModel = node_model.into();
-    node_model.node_status = Set(status);
+    // IPFS RPC is optional in `Wrapper` pin mode, so the node works as long as its Wrapper works
+    let status = match (status, state.pin_config.mode, &node_model.wrapper_admin_address) {
+        (sea_orm_active_enums::NodeStatus::Unhealthy, PinMode::Wrapper, Some(admin_address)) => {
+            match get_peer_id_by_wrapper(&state, admin_address).await {
+                Ok(peer_id) if peer_id == node_model.peer_id => sea_orm_active_enums::NodeStatus::Online,
+                _ => sea_orm_active_enums::NodeStatus::Unhealthy,
+            }
+        }
+        (status, _, _) => status,
+    };
+
+    let mut audit_entry = AuditEntry::new(&actor, AuditAction::ReBootstrap).node(node_model.id.clone());
+    if status != sea_orm_active_enums::NodeStatus::Online {
+        audit_entry = audit_entry.failed("Failed to bootstrap");
+    }
+    services::audit::record(&state, audit_entry).await;
+
+    // the status might be changed during bootstrap, e.g. set to `Maintenance` by admin
+    let res = daos::update_node_status_from(
+        &node_model.id, node_model.node_status.clone(), status.clone(), &state.db_conn).await;
+    let res = match res {
+        Ok(true) => return Ok(node::Model { node_status: status, ..node_model }),
+        Ok(false) => {
+            info!("Status of node {} is changed during bootstrap. Keep it", node_model.id);
+            Node::find_by_id(node_model.id.clone())
+                .one(&state.db_conn)
+                .await
+                .and_then(|v| v.ok_or_else(|| DbErr::RecordNotFound(node_model.id.clone())))
+        }
+        Err(e) => Err(e),
+    };
+
+    res.map_err(|e| {
+        error!("Failed to set status of node in database. peer_id {:?}. msg: {:?}", _target_peer_id, e);
+    })
+}
+
+/// Let target IPFS node bootstrap self.
+/// Would set the status of node to `Online`, unless it's in `Maintenance`.
+/// Upsert the database entry by peer id.
+///
+/// If `expected_peer_id` is set, nothing is changed when the node has another peer id.
+///
+/// Return the upserted node.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn add_node_to_cluster(state: &AppState,
+                                        rpc_address: String,
+                                        wrapper_public_address: String,
+                                        wrapper_admin_address: String,
+                                        expected_peer_id: Option<&str>)
+                                        -> ApiResult<node::Model> {
+    let target_ipfs_client = state.get_ipfs_client_with_rpc_addr(rpc_address.clone());
+    let (aim_peer_id, id_info) = match state.pin_config.mode {
+        PinMode::Rpc => {
+            let id_info = target_ipfs_client.get_id_info()
+                .await?;
+            (id_info.id.clone(), Some(id_info))
+        }
+        // IPFS RPC is optional, only used to record capabilities
+        PinMode::Wrapper => {
+            let aim_peer_id = get_peer_id_by_wrapper(state, &wrapper_admin_address).await?;
+            let id_info = tokio::time::timeout(
+                tokio::time::Duration::from_millis(RE_BOOTSTRAP_TIMEOUT_MS),
+                target_ipfs_client.get_id_info(),
+            ).await;
+            let id_info = match id_info {
+                Ok(Ok(v)) if v.id == aim_peer_id => Some(v),
+                _ => {
+                    warn!("Failed to get id info of node {} by IPFS RPC", aim_peer_id);
+                    None
+                }
+            };
+            (aim_peer_id, id_info)
+        }
+    };
+    debug!("Add IPFS node target peer id: {}", aim_peer_id);
+    if let Some(expected_peer_id) = expected_peer_id.filter(|v| *v != aim_peer_id) {
+        warn!("Expected peer id {} mismatches the actual one {}", expected_peer_id, aim_peer_id);
+        return Err(errors::REQUEST_ARGS_ERROR.clone_to_error()
+            .modify_msg("Peer id mismatches the IPFS node")
+            .modify_status_code(axum::http::StatusCode::BAD_REQUEST));
+    }
+
+    let res = target_ipfs_client.bootstrap_add(
+        &state.ipfs_metadata.ipfs_swarm_multi_address,
+        &state.ipfs_metadata.ipfs_peer_id,
+    ).await;
+    match (res, state.pin_config.mode) {
+        (Ok(_), _) => {}
+        (Err(e), PinMode::Rpc) => return Err(e.into()),
+        (Err(e), PinMode::Wrapper) => warn!("Failed to bootstrap node {}. msg: {:?}", aim_peer_id, e),
+    }
+    let repo_stat = tokio::time::timeout(
+        tokio::time::Duration::from_millis(RE_BOOTSTRAP_TIMEOUT_MS),
+        target_ipfs_client.repo_stat(),
+    ).await;
+    let repo_stat = match repo_stat {
+        Ok(Ok(v)) => Some(v),
+        _ => {
+            warn!("Failed to get repo stat of node {}", aim_peer_id);
+            None
+        }
+    };
+
+    // bootstrap only works when IPFS node starts
+    if let Err(e) = services::peering::peer_with_master(state, &target_ipfs_client).await {
+        warn!("Failed to peer node {} with master: {}", aim_peer_id, e);
+    }
+
+    let new_node = node::ActiveModel {
+        id: Set(Uuid::new_v4().to_string()),
+        peer_id: Set(aim_peer_id.clone()),
+        rpc_address: Set(rpc_address),
+        wrapper_public_address: Set(Some(wrapper_public_address)),
+        wrapper_admin_address: Set(Some(wrapper_admin_address)),
+        node_status: Set(sea_orm_active_enums::NodeStatus::Online),
+        agent_version: Set(id_info.as_ref().map(|v| v.agent_version.clone())),
+        multiaddrs: Set(id_info.as_ref().map(|v| v.addresses.clone().into())),
+        repo_size: Set(repo_stat.as_ref().map(|v| v.repo_size as i64)),
+        storage_max: Set(repo_stat.as_ref().map(|v| v.storage_max as i64)),
+        last_seen: Set(Some(chrono::Utc::now())),
+    };
+    // upsert
+    let mut update_columns = vec![
+        node::Column::RpcAddress,
+        node::Column::WrapperPublicAddress,
+        node::Column::WrapperAdminAddress,
+        node::Column::LastSeen,
+    ];
+    // keep the old capabilities when IPFS RPC is unavailable
+    if id_info.is_some() {
+        update_columns.extend([node::Column::AgentVersion, node::Column::Multiaddrs]);
+    }
+    if repo_stat.is_some() {
+        update_columns.extend([node::Column::RepoSize, node::Column::StorageMax]);
+    }
+    // keep `Maintenance` when the Wrapper registers again
+    let in_maintenance = Node::find()
+        .filter(node::Column::PeerId.eq(aim_peer_id.clone()))
+        .filter(node::Column::NodeStatus.eq(sea_orm_active_enums::NodeStatus::Maintenance))
+        .count(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)? > 0;
+    if !in_maintenance {
+        update_columns.push(node::Column::NodeStatus);
+    }
+    let dup_conflict = sea_query::OnConflict::column(node::Column::PeerId)
+        .update_columns(update_columns)
+        .to_owned();
+    Node::insert(new_node)
+        .on_conflict(dup_conflict)
+        .exec(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+
+    // id is not changed when conflict
+    Node::find()
+        .filter(node::Column::PeerId.eq(aim_peer_id))
+        .one(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?
+        .ok_or_else(|| errors::DB_DATA_FAIL.clone_to_error_with_log())
+}
+
+/// Get the peer id of the IPFS node behind a Wrapper by its admin API.
+async fn get_peer_id_by_wrapper(state: &AppState, wrapper_admin_address: &str) -> ApiResult<String> {
+    let client = IpfsNodeWrapperAdminClient::new_with_reqwest_client(
+        wrapper_admin_address.to_owned(), state.reqwest_client.clone());
+    let res = tokio::time::timeout(
+        tokio::time::Duration::from_millis(RE_BOOTSTRAP_TIMEOUT_MS),
+        client.get_ipfs_node_info(),
+    ).await.map_err(|_| {
+        error!("Get IPFS node info from Wrapper {} timeout", client.base_url);
+        errors::WRAPPER_FAIL.clone_to_error()
+    })?;
+    Ok(res?.data.id)
+}
 
-    let res: Result<node::Model, DbErr> = node_model
-        .update(&state.db_conn)
-        .await;
+/// Record the capabilities of a node which just passed the check, and update its last seen time.
+///
+/// Repo stat is got in best effort. The old value is kept if failed.
+/// Only the last seen time is updated if `id_info` is `None`, i.e. IPFS RPC is unavailable.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn record_node_capabilities(state: &AppState,
+                                             node_id: &str,
+                                             ipfs_client: &ReqwestIpfsClient,
+                                             id_info: Option<IdResponse>,
+                                             timeout_ms: u64)
+                                             -> ApiResult<()> {
+    let mut node_model = node::ActiveModel {
+        id: Set(node_id.to_owned()),
+        last_seen: Set(Some(chrono::Utc::now())),
+        ..Default::default()
+    };
+    let Some(id_info) = id_info else {
+        Node::update(node_model)
+            .exec(&state.db_conn)
+            .await.map_err(services::db::handle_db_error)?;
+        return Ok(());
+    };
+    node_model.agent_version = Set(Some(id_info.agent_version));
+    node_model.multiaddrs = Set(Some(id_info.addresses.into()));
 
-    match res {
-        Ok(m) => {
-            Ok(m)
+    let repo_stat = tokio::time::timeout(
+        tokio::time::Duration::from_millis(timeout_ms),
+        ipfs_client.repo_stat(),
+    ).await;
+    match repo_stat {
+        Ok(Ok(repo_stat)) => {
+            node_model.repo_size = Set(Some(repo_stat.repo_size as i64));
+            node_model.storage_max = Set(Some(repo_stat.storage_max as i64));
         }
-        Err(e) => {
-            error!("Failed to set status of node in database. peer_id {:?}. msg: {:?}", _target_peer_id, e);
-            Err(())
+        _ => warn!("Failed to get repo stat of node {}. msg: {:?}", node_id, repo_stat),
+    }
+
+    Node::update(node_model)
+        .exec(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+    Ok(())
+}
+
+/// Set the status of a node to `Maintenance`.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn set_node_maintenance(state: &AppState, node_id: &str) -> ApiResult<()> {
+    let res = Node::update_many()
+        .col_expr(node::Column::NodeStatus, Expr::value(sea_orm_active_enums::NodeStatus::Maintenance))
+        .filter(node::Column::Id.eq(node_id))
+        .exec(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+    if res.rows_affected == 0 {
+        return Err(errors::DB_TARGET_DATA_NOT_EXIST.clone_to_error()
+            .modify_status_code(axum::http::StatusCode::NOT_FOUND));
+    }
+    Ok(())
+}
+
+/// Take a node out of `Maintenance` by bootstrapping it again.
+///
+/// Return the node with new status. Do nothing if the node is not in `Maintenance`.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn clear_node_maintenance(state: &AppState, node_id: &str, actor: &Actor) -> ApiResult<node::Model> {
+    let node_model = Node::find_by_id(node_id)
+        .one(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?
+        .ok_or_else(|| errors::DB_TARGET_DATA_NOT_EXIST.clone_to_error()
+            .modify_status_code(axum::http::StatusCode::NOT_FOUND))?;
+    if node_model.node_status != sea_orm_active_enums::NodeStatus::Maintenance {
+        return Ok(node_model);
+    }
+    bootstrap_and_check_health(state.clone(), node_model, actor.clone()).await
+        .map_err(|_| errors::DB_FAIL.clone_to_error())
+}
+
+/// Remove a node from the cluster.
+///
+/// Refuse when the node holds the only replica of some pins (replicas on nodes not `Online` are not counted),
+/// unless `force` is `true`,
+/// in which case these pins are re-homed to other nodes first.
+/// Then remove self from the node's bootstrap list, delete the node in database,
+/// and remove the peering between the node and the cluster.
+///
+/// Return the CIDs of re-homed pins.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn remove_node_from_cluster(state: &AppState, node_id: &str, force: bool, actor: &Actor) -> ApiResult<Vec<String>> {
+    let node_model = Node::find_by_id(node_id)
+        .one(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?
+        .ok_or_else(|| errors::DB_TARGET_DATA_NOT_EXIST.clone_to_error())?;
+
+    let sole_pins = daos::find_pins_only_stored_in_node(node_id, &state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+    if !sole_pins.is_empty() && !force {
+        warn!("Refuse to remove node {}. It holds the only replica of {} pins", node_id, sole_pins.len());
+        return Err(errors::IPFS_NODE_HOLDS_SOLE_REPLICA.clone_to_error()
+            .modify_status_code(axum::http::StatusCode::CONFLICT));
+    }
+
+    let mut rehomed_cids = Vec::with_capacity(sole_pins.len());
+    for pin_model in sole_pins {
+        services::file::rehome_pin(state, &pin_model, node_id, actor).await?;
+        rehomed_cids.push(pin_model.cid);
+    }
+
+    // The node might be unreachable, so it's ok to fail.
+    let target_ipfs_client = state.get_ipfs_client_with_rpc_addr(node_model.rpc_address.clone());
+    let res = tokio::time::timeout(
+        tokio::time::Duration::from_millis(RE_BOOTSTRAP_TIMEOUT_MS),
+        target_ipfs_client.bootstrap_rm(
+            &state.ipfs_metadata.ipfs_swarm_multi_address,
+            &state.ipfs_metadata.ipfs_peer_id,
+        ),
+    ).await;
+    if !matches!(res, Ok(Ok(()))) {
+        warn!("Failed to remove self from bootstrap list of node {}. Continue removing", node_id);
+    }
+
+    let txn = state.db_conn.begin()
+        .await.map_err(services::db::handle_db_error)?;
+    PinsStoredNodes::delete_many()
+        .filter(pins_stored_nodes::Column::NodeId.eq(node_id))
+        .exec(&txn)
+        .await.map_err(services::db::handle_db_error)?;
+    Node::delete_by_id(node_id)
+        .exec(&txn)
+        .await.map_err(services::db::handle_db_error)?;
+    txn.commit()
+        .await.map_err(services::db::handle_db_error)?;
+    state.node_health_records.remove_async(node_id).await;
+    state.node_connectivity.remove_async(node_id).await;
+    state.file_download_decision_maker.forget_node(node_id).await;
+    services::peering::unpeer_removed_node(state, &node_model, RE_BOOTSTRAP_TIMEOUT_MS).await;
+
+    info!("Node {} removed. {} pins re-homed", node_id, rehomed_cids.len());
+    Ok(rehomed_cids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use axum::extract::Query;
+    use axum::http::StatusCode;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn test_remove_node_removes_peering() {
+        // fake IPFS RPC of all nodes, recording the peers removed from peering
+        let removed_peers = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let ipfs_app = axum::Router::new()
+            .route("/api/v0/swarm/peering/rm", axum::routing::post({
+                let removed_peers = removed_peers.clone();
+                move |Query(args): Query<std::collections::HashMap<String, String>>| async move {
+                    removed_peers.lock().unwrap().push(args["arg"].clone());
+                    axum::Json(serde_json::json!({ "ID": args["arg"], "Status": "success" }))
+                }
+            }))
+            .fallback(|| async { StatusCode::INTERNAL_SERVER_ERROR });
+        let ipfs_address = serve_fake(ipfs_app).await;
+
+        let conn = connect_test_db().await;
+        let state = test_db_state(&conn);
+        for id in ["a", "b"] {
+            insert_test_node(test_node(id, &ipfs_address), &conn).await;
         }
+
+        services::ipfs::remove_node_from_cluster(&state, "a", false, &services::audit::Actor::Admin("admin".to_string())).await.unwrap();
+        let mut removed_peers = removed_peers.lock().unwrap().clone();
+        removed_peers.sort();
+        assert_eq!(removed_peers, ["peer a", "test peer id"]);
+        assert_eq!(Node::find().count(&conn).await.unwrap(), 1);
     }
 }
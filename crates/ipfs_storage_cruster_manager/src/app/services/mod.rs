@@ -1,4 +1,13 @@
 
 pub mod ipfs;
 pub mod db;
-pub mod file;
\ No newline at end of file
+pub mod file;
+pub mod health;
+pub mod peering;
+pub mod expiry;
+pub mod audit;
+pub mod auth;
+pub mod quota;
+pub mod rate_limit;
+pub mod pin_service;
+pub mod replication;
@@ -0,0 +1,262 @@
+//! Keep storage nodes peered with the master IPFS node, and optionally with each other.
+//!
+//! Bootstrap list is only used when IPFS node starts,
+//! so the swarm connection might be lost and never come back.
+//! The peering subsystem of IPFS protects the connections and re-dials when they drop.
+
+use std::collections::HashSet;
+#[allow(unused_imports)]
+use tracing::{error, debug, warn, info, trace};
+use serde::Serialize;
+use tiny_ipfs_client::ReqwestIpfsClient;
+use crate::imports::dao_imports::*;
+use crate::app::AppState;
+use crate::utils::now_ms;
+
+/// Config of the peering maintainer.
+#[derive(Debug, Clone)]
+pub struct PeeringConfig {
+    pub interval_time_ms: u64,
+    /// Timeout of each RPC.
+    pub timeout_ms: u64,
+    /// Whether to peer storage nodes with each other.
+    pub mesh: bool,
+}
+
+/// Swarm connectivity of a node. Only kept in memory.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeConnectivity {
+    pub connected_to_master: bool,
+    /// The number of other storage nodes connected with. Only checked when mesh peering is enabled.
+    pub connected_node_num: usize,
+    /// The number of other storage nodes expected to connect with.
+    pub expected_node_num: usize,
+    /// The number of all swarm peers.
+    pub swarm_peer_num: usize,
+    /// Unix timestamp (ms) of the last check.
+    pub last_checked_ms: Option<u64>,
+    /// Error of the last check.
+    pub last_error: Option<String>,
+}
+
+/// Storage node to peer with.
+#[derive(Clone, Debug)]
+struct PeeringTarget {
+    node_id: String,
+    peer_id: String,
+    multi_addr: String,
+}
+
+/// Spawn a task to keep peering regularly.
+pub fn spawn_peering_maintainer(state: AppState, config: PeeringConfig) -> tokio::task::JoinHandle<()> {
+    info!("Peering maintainer starts. {:?}", config);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(config.interval_time_ms));
+        loop {
+            interval.tick().await;
+            maintain_all_peering(&state, &config).await;
+        }
+    })
+}
+
+/// The multi address of the master IPFS node with peer id.
+pub fn master_multi_addr(state: &AppState) -> String {
+    format!("{}/p2p/{}",
+            state.ipfs_metadata.ipfs_swarm_multi_address,
+            state.ipfs_metadata.ipfs_peer_id)
+}
+
+/// Let the node peer with the master IPFS node, and connect to it if not connected.
+pub async fn peer_with_master(state: &AppState, ipfs_client: &ReqwestIpfsClient) -> Result<(), String> {
+    let master_multi_addr = master_multi_addr(state);
+    ipfs_client.swarm_peering_add(&master_multi_addr).await
+        .map_err(|e| format!("Failed to add peering of master: {e:?}"))?;
+    ipfs_client.swarm_connect(&master_multi_addr).await
+        .map_err(|e| format!("Failed to connect to master: {e:?}"))?;
+    Ok(())
+}
+
+/// Undo the peering of a node removed from the cluster, in best effort since nodes might be unreachable.
+///
+/// The node stops protecting its connection to the master, and other nodes stop re-dialing it.
+/// Should be called after the node is deleted in database, so that it's not peered again.
+#[tracing::instrument(skip_all)]
+pub async fn unpeer_removed_node(state: &AppState, removed_node: &node::Model, timeout_ms: u64) {
+    let timeout = tokio::time::Duration::from_millis(timeout_ms);
+    let ipfs_client = state.get_ipfs_client_with_rpc_addr(removed_node.rpc_address.clone());
+    let res = tokio::time::timeout(timeout, ipfs_client.swarm_peering_rm(&state.ipfs_metadata.ipfs_peer_id)).await;
+    if !matches!(res, Ok(Ok(()))) {
+        warn!("Failed to remove master from peering of node {}. msg: {:?}", removed_node.id, res);
+    }
+
+    let node_vec = match Node::find()
+        .filter(node::Column::NodeStatus.ne(sea_orm_active_enums::NodeStatus::Offline))
+        .filter(node::Column::Id.ne(removed_node.id.clone()))
+        .all(&state.db_conn).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to find nodes to remove peering. msg: {e:?}");
+            return;
+        }
+    };
+    let mut join_set = tokio::task::JoinSet::new();
+    for node_model in node_vec {
+        let ipfs_client = state.get_ipfs_client_with_rpc_addr(node_model.rpc_address.clone());
+        let removed_peer_id = removed_node.peer_id.clone();
+        join_set.spawn(async move {
+            let res = tokio::time::timeout(timeout, ipfs_client.swarm_peering_rm(&removed_peer_id)).await;
+            if !matches!(res, Ok(Ok(()))) {
+                warn!("Failed to remove peering of {} from node {}. msg: {:?}", removed_peer_id, node_model.id, res);
+            }
+        });
+    }
+    while join_set.join_next().await.is_some() {}
+}
+
+#[tracing::instrument(skip_all)]
+async fn maintain_all_peering(state: &AppState, config: &PeeringConfig) {
+    let node_vec = match Node::find()
+        .filter(node::Column::NodeStatus.ne(sea_orm_active_enums::NodeStatus::Offline))
+        .all(&state.db_conn).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to find nodes to maintain peering. msg: {e:?}");
+            return;
+        }
+    };
+
+    let mesh_targets = if config.mesh {
+        find_mesh_targets(state, &node_vec, config.timeout_ms).await
+    } else {
+        vec![]
+    };
+
+    let mut join_set = tokio::task::JoinSet::new();
+    for node_model in node_vec {
+        let state = state.clone();
+        let config = config.clone();
+        let mesh_targets = mesh_targets.clone();
+        join_set.spawn(async move {
+            let connectivity = maintain_node_peering(&state, &config, &node_model, &mesh_targets).await;
+            if let Some(e) = &connectivity.last_error {
+                warn!("Failed to maintain peering of node {}: {}", node_model.id, e);
+            }
+            state.node_connectivity.upsert_async(node_model.id, connectivity).await;
+        });
+    }
+
+    while let Some(join_res) = join_set.join_next().await {
+        if let Err(join_err) = join_res {
+            if join_err.is_panic() {
+                std::panic::resume_unwind(join_err.into_panic());
+            }
+        }
+    }
+}
+
+/// Find a reachable multi address of each node.
+async fn find_mesh_targets(state: &AppState, node_vec: &[node::Model], timeout_ms: u64) -> Vec<PeeringTarget> {
+    let mut join_set = tokio::task::JoinSet::new();
+    for node_model in node_vec {
+        let ipfs_client = state.get_ipfs_client_with_rpc_addr(node_model.rpc_address.clone());
+        let node_id = node_model.id.clone();
+        join_set.spawn(async move {
+            let res = tokio::time::timeout(
+                tokio::time::Duration::from_millis(timeout_ms),
+                ipfs_client.get_id_info(),
+            ).await;
+            let Ok(Ok(id_info)) = res else {
+                return None;
+            };
+            let multi_addr = id_info.addresses.into_iter()
+                .find(|v| !is_loopback_multi_addr(v))?;
+            let multi_addr = if multi_addr.contains("/p2p/") {
+                multi_addr
+            } else {
+                format!("{}/p2p/{}", multi_addr, id_info.id)
+            };
+            Some(PeeringTarget {
+                node_id,
+                peer_id: id_info.id,
+                multi_addr,
+            })
+        });
+    }
+
+    let mut targets = Vec::new();
+    while let Some(join_res) = join_set.join_next().await {
+        if let Ok(Some(target)) = join_res {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+pub(crate) fn is_loopback_multi_addr(multi_addr: &str) -> bool {
+    multi_addr.starts_with("/ip4/127.") || multi_addr.starts_with("/ip6/::1/")
+}
+
+/// Add peering and re-dial dropped connections of a node.
+async fn maintain_node_peering(state: &AppState,
+                               config: &PeeringConfig,
+                               node_model: &node::Model,
+                               mesh_targets: &[PeeringTarget])
+                               -> NodeConnectivity {
+    let timeout = tokio::time::Duration::from_millis(config.timeout_ms);
+    let ipfs_client = state.get_ipfs_client_with_rpc_addr(node_model.rpc_address.clone());
+    let mut connectivity = NodeConnectivity {
+        last_checked_ms: Some(now_ms()),
+        ..Default::default()
+    };
+
+    let peers = match tokio::time::timeout(timeout, ipfs_client.swarm_peers()).await {
+        Ok(Ok(res)) => res.peers.unwrap_or_default(),
+        Ok(Err(e)) => {
+            connectivity.last_error = Some(format!("Failed to list swarm peers: {e:?}"));
+            return connectivity;
+        }
+        Err(_) => {
+            connectivity.last_error = Some("List swarm peers timeout".to_string());
+            return connectivity;
+        }
+    };
+    let connected_peers: HashSet<String> = peers.into_iter().map(|v| v.peer).collect();
+    connectivity.swarm_peer_num = connected_peers.len();
+
+    // master
+    if connected_peers.contains(&state.ipfs_metadata.ipfs_peer_id) {
+        connectivity.connected_to_master = true;
+        // make sure it's protected
+        let _ = tokio::time::timeout(timeout, ipfs_client.swarm_peering_add(&master_multi_addr(state))).await;
+    } else {
+        debug!("Node {} lost connection to master. Re-dial", node_model.id);
+        match tokio::time::timeout(timeout, peer_with_master(state, &ipfs_client)).await {
+            Ok(Ok(())) => connectivity.connected_to_master = true,
+            Ok(Err(e)) => connectivity.last_error = Some(e),
+            Err(_) => connectivity.last_error = Some("Connect to master timeout".to_string()),
+        }
+    }
+
+    // mesh
+    for target in mesh_targets.iter().filter(|v| v.node_id != node_model.id) {
+        connectivity.expected_node_num += 1;
+        if connected_peers.contains(&target.peer_id) {
+            connectivity.connected_node_num += 1;
+            let _ = tokio::time::timeout(timeout, ipfs_client.swarm_peering_add(&target.multi_addr)).await;
+            continue;
+        }
+        debug!("Node {} is not connected to node {}. Dial", node_model.id, target.node_id);
+        let res = tokio::time::timeout(timeout, async {
+            ipfs_client.swarm_peering_add(&target.multi_addr).await?;
+            ipfs_client.swarm_connect(&target.multi_addr).await
+        }).await;
+        match res {
+            Ok(Ok(())) => connectivity.connected_node_num += 1,
+            Ok(Err(e)) => connectivity.last_error = Some(format!("Failed to connect to node {}: {e:?}", target.node_id)),
+            Err(_) => connectivity.last_error = Some(format!("Connect to node {} timeout", target.node_id)),
+        }
+    }
+
+    connectivity
+}
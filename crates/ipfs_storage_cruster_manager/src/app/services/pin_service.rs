@@ -0,0 +1,424 @@
+//! Logic of [IPFS Pinning Service API](https://ipfs.github.io/pinning-services-api-spec).
+//!
+//! A pin request is a `users_pins` entry, whose id is the `requestid`.
+//! Requests with the same CID share one `pin` entry, so the data is only stored once in cluster.
+//! Name, meta and origins are kept in each request, and only the request's own ones are returned.
+
+use std::collections::HashMap;
+#[allow(unused_imports)]
+use tracing::{error, debug, warn, info, trace};
+use axum::http::StatusCode;
+use ipfs_pin_service_axum_api_framework::{dto, models};
+use crate::imports::dao_imports::*;
+use crate::app::{AppState, daos, errors, services};
+use crate::app::common::ApiResult;
+use crate::app::services::audit::{Actor, AuditAction, AuditEntry};
+
+const DEFAULT_LIST_LIMIT: i32 = 10;
+const MAX_LIST_LIMIT: i32 = 1000;
+const MAX_PIN_NAME_LEN: usize = 100;
+
+/// List pin requests of a user.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn list_pin_requests(state: &AppState, user_id: &str, args: dto::GetPinsArgs) -> ApiResult<models::PinResults> {
+    let limit = args.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if !(1..=MAX_LIST_LIMIT).contains(&limit) {
+        return Err(errors::REQUEST_ARGS_ERROR.clone_to_error()
+            .modify_msg("limit should be in [1, 1000]")
+            .modify_status_code(StatusCode::BAD_REQUEST));
+    }
+
+    let mut query = UsersPins::find()
+        .inner_join(Pin)
+        .filter(users_pins::Column::UserId.eq(user_id));
+    if let Some(cid) = args.cid {
+        query = query.filter(pin::Column::Cid.is_in(cid));
+    }
+    if let Some(name) = args.name {
+        let lower_name_col = Expr::expr(sea_query::Func::lower(Expr::col((users_pins::Entity, users_pins::Column::PinName))));
+        query = match args.r#match.unwrap_or(models::TextMatchingStrategy::Exact) {
+            models::TextMatchingStrategy::Exact => query.filter(users_pins::Column::PinName.eq(name)),
+            models::TextMatchingStrategy::Iexact => query.filter(lower_name_col.eq(name.to_lowercase())),
+            // only case-sensitive in PostgreSQL, since `LIKE` ignores ASCII case in SQLite and MySQL by default
+            models::TextMatchingStrategy::Partial => query.filter(Expr::col((users_pins::Entity, users_pins::Column::PinName)).like(daos::contains_like_pattern(&name))),
+            models::TextMatchingStrategy::Ipartial => query.filter(lower_name_col.like(daos::contains_like_pattern(&name.to_lowercase()))),
+        };
+    }
+    if let Some(status) = args.status {
+        let status: Vec<_> = status.into_iter()
+            .flat_map(db_status_from_spec)
+            .collect();
+        query = query.filter(pin::Column::Status.is_in(status));
+    }
+    if let Some(meta) = args.meta {
+        let backend = state.db_conn.get_database_backend();
+        for (key, value) in meta.map_parameter.unwrap_or_default() {
+            query = query.filter(daos::meta_eq_condition(backend, "users_pins", &key, &value));
+        }
+    }
+    if let Some(before) = args.before {
+        query = query.filter(users_pins::Column::Created.lt(before));
+    }
+    if let Some(after) = args.after {
+        query = query.filter(users_pins::Column::Created.gt(after));
+    }
+
+    let count = query.clone()
+        .count(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+    let requests = query
+        .order_by_desc(users_pins::Column::Created)
+        .limit(limit as u64)
+        .select_also(Pin)
+        .all(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+
+    let pin_ids = requests.iter().map(|v| v.0.pin_id.clone()).collect();
+    let mut delegates_map = find_delegates(state, pin_ids).await?;
+    let results = requests.into_iter()
+        .filter_map(|(request, pin_model)| {
+            let pin_model = pin_model?;
+            let delegates = delegates_map.remove(&pin_model.id)
+                .unwrap_or_else(|| vec![services::peering::master_multi_addr(state)]);
+            Some(generate_pin_status(request, &pin_model, delegates, None))
+        })
+        .collect();
+
+    Ok(models::PinResults::new(count as u32, results))
+}
+
+/// Add a pin request. Store the data to cluster in background if it's not stored.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn add_pin_request(state: &AppState, user_id: &str, pin: models::Pin) -> ApiResult<models::PinStatus> {
+    add_or_replace_pin_request(state, user_id, pin, None).await
+}
+
+/// Add a pin request, and remove `old_request` in the same transaction if it's `Some`.
+async fn add_or_replace_pin_request(state: &AppState,
+                                    user_id: &str,
+                                    pin: models::Pin,
+                                    old_request: Option<users_pins::Model>) -> ApiResult<models::PinStatus> {
+    if pin.name.as_ref().is_some_and(|v| v.len() > MAX_PIN_NAME_LEN) {
+        return Err(errors::REQUEST_ARGS_ERROR.clone_to_error()
+            .modify_msg("name is too long")
+            .modify_status_code(StatusCode::BAD_REQUEST));
+    }
+
+    let expires_at = services::expiry::resolve_expires_at_from_meta(pin.meta.as_ref())?;
+    let (pin_model, delegates) = ensure_pin_stored(state, &pin, expires_at).await?;
+    let txn = state.db_conn.begin()
+        .await.map_err(services::db::handle_db_error)?;
+    let request = daos::insert_pin_request(
+        user_id,
+        &pin_model.id,
+        pin.name.clone(),
+        pin.meta.as_ref().map(|v| serde_json::json!(v)),
+        pin.origins.as_ref().map(|v| serde_json::json!(v)),
+        &txn,
+    )
+        .await.map_err(services::db::handle_db_error)?;
+    if let Some(old_request) = old_request {
+        old_request.delete(&txn)
+            .await.map_err(services::db::handle_db_error)?;
+    }
+    txn.commit()
+        .await.map_err(services::db::handle_db_error)?;
+    info!("Add pin request {} of cid {}", request.id, pin.cid);
+    let audit_entry = AuditEntry::new(&Actor::User(user_id.to_owned()), AuditAction::AddPinRequest)
+        .cid(pin.cid.clone())
+        .detail(format!("request: {}", request.id));
+    services::audit::record(state, audit_entry).await;
+
+    Ok(generate_pin_status(request, &pin_model, delegates, Some(pin)))
+}
+
+/// Get a pin request of a user.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_pin_request(state: &AppState, user_id: &str, request_id: &str) -> ApiResult<models::PinStatus> {
+    let (request, pin_model) = find_pin_request(state, user_id, request_id).await?;
+    let delegates = find_delegates(state, vec![pin_model.id.clone()]).await?
+        .remove(&pin_model.id)
+        .unwrap_or_else(|| vec![services::peering::master_multi_addr(state)]);
+    Ok(generate_pin_status(request, &pin_model, delegates, None))
+}
+
+/// Add a new pin request, and remove the old one at the same time.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn replace_pin_request(state: &AppState, user_id: &str, request_id: &str, pin: models::Pin) -> ApiResult<models::PinStatus> {
+    let (old_request, _) = find_pin_request(state, user_id, request_id).await?;
+    let pin_status = add_or_replace_pin_request(state, user_id, pin, Some(old_request)).await?;
+    info!("Replace pin request {} with {}", request_id, pin_status.requestid);
+    Ok(pin_status)
+}
+
+/// Remove a pin request.
+///
+/// The data is kept in cluster, because it might be shared by other requests or uploads.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn delete_pin_request(state: &AppState, user_id: &str, request_id: &str) -> ApiResult<()> {
+    let (request, pin_model) = find_pin_request(state, user_id, request_id).await?;
+    request.delete(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+    info!("Delete pin request {}", request_id);
+    let audit_entry = AuditEntry::new(&Actor::User(user_id.to_owned()), AuditAction::DeletePinRequest)
+        .cid(pin_model.cid)
+        .detail(format!("request: {request_id}"));
+    services::audit::record(state, audit_entry).await;
+    Ok(())
+}
+
+/// Find a pin request of a user with its pin.
+pub(crate) async fn find_pin_request(state: &AppState, user_id: &str, request_id: &str) -> ApiResult<(users_pins::Model, pin::Model)> {
+    let res = UsersPins::find_by_id(request_id)
+        .filter(users_pins::Column::UserId.eq(user_id))
+        .inner_join(Pin)
+        .select_also(Pin)
+        .one(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+    match res {
+        Some((request, Some(pin_model))) => Ok((request, pin_model)),
+        _ => Err(errors::DB_TARGET_DATA_NOT_EXIST.clone_to_error()
+            .modify_status_code(StatusCode::NOT_FOUND)),
+    }
+}
+
+/// Make sure the pin of the CID exists and is (being) stored in cluster.
+/// Failed and deleted pins are retried.
+///
+/// The expiry of an existing pin is extended to `expires_at` if needed (`None` means never expire).
+///
+/// Name, meta and origins are only recorded by the first request of the CID.
+/// Return the pin and its delegates.
+async fn ensure_pin_stored(state: &AppState,
+                           pin: &models::Pin,
+                           expires_at: Option<DateTimeUtc>) -> ApiResult<(pin::Model, Vec<String>)> {
+    let cid = pin.cid.as_str();
+    let origins = pin.origins.clone().unwrap_or_default();
+    let now = chrono::Utc::now();
+    let new_pin = pin::Model {
+        id: Uuid::new_v4().to_string(),
+        status: sea_orm_active_enums::Status::Queued,
+        cid: cid.to_owned(),
+        created: now,
+        updated: now,
+        size: None,
+        name: pin.name.clone(),
+        meta: pin.meta.as_ref().map(|v| serde_json::json!(v)),
+        origins: pin.origins.as_ref().map(|v| serde_json::json!(v)),
+        expires_at,
+    };
+    let intent = state.repository
+        .insert_pin_to_replicate(new_pin.clone(), services::replication::new_replication(state, origins.clone()))
+        .await.map_err(services::db::handle_db_error)?;
+    let (pin_model, intent) = match intent {
+        Some(intent) => (new_pin, intent),
+        None => {
+            let pin_model = state.repository.find_pin_by_cid(cid)
+                .await.map_err(services::db::handle_db_error)?
+                .ok_or_else(|| errors::DB_DATA_FAIL.clone_to_error_with_log())?;
+            let retry = match pin_model.status {
+                sea_orm_active_enums::Status::Failed
+                | sea_orm_active_enums::Status::NotFound
+                | sea_orm_active_enums::Status::Deleted => true,
+                // it might be claimed by the expiry reaper meanwhile
+                _ => !state.repository.keep_pin_until(&pin_model.id, expires_at)
+                    .await.map_err(services::db::handle_db_error)?,
+            };
+            if !retry {
+                debug!("cid {} has been stored, skip it", cid);
+                let delegates = find_delegates(state, vec![pin_model.id.clone()]).await?
+                    .remove(&pin_model.id)
+                    .unwrap_or_else(|| vec![services::peering::master_multi_addr(state)]);
+                return Ok((pin_model, delegates));
+            }
+            info!("Retry to store {:?} pin of cid {}", pin_model.status, cid);
+            let pin_model = pin::Model {
+                status: sea_orm_active_enums::Status::Queued,
+                expires_at,
+                updated: chrono::Utc::now(),
+                ..pin_model
+            };
+            let intent = state.repository
+                .update_pin_to_replicate(pin_model.clone(), services::replication::new_replication(state, origins))
+                .await.map_err(services::db::handle_db_error)?;
+            (pin_model, intent)
+        }
+    };
+
+    let target_node_list = match services::file::decide_store_nodes(state, cid).await {
+        Ok(v) => v,
+        Err(e) => {
+            if let Err(e) = state.repository.abandon_replication(&intent).await {
+                error!("Failed to abandon replication of pin {}. msg: {:?}", pin_model.id, e);
+            }
+            return Err(e);
+        }
+    };
+    let target_node_ids: Vec<_> = target_node_list.iter().map(|v| v.id.clone()).collect();
+    let delegates = Node::find()
+        .filter(node::Column::Id.is_in(target_node_ids))
+        .all(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?
+        .iter()
+        .filter_map(delegate_multi_addr)
+        .collect::<Vec<_>>();
+    let delegates = if delegates.is_empty() {
+        vec![services::peering::master_multi_addr(state)]
+    } else {
+        delegates
+    };
+
+    // retried by the replication worker if failed
+    let state = state.clone();
+    tokio::spawn(async move {
+        let _ = services::replication::process_replication(&state, intent, Some(target_node_list)).await;
+    });
+    Ok((pin_model, delegates))
+}
+
+/// Find the delegates of pins. `pin_id -> delegates`.
+///
+/// Pins without any known delegate are not in the result.
+async fn find_delegates(state: &AppState, pin_ids: Vec<String>) -> ApiResult<HashMap<String, Vec<String>>> {
+    let res = PinsStoredNodes::find()
+        .filter(pins_stored_nodes::Column::PinId.is_in(pin_ids))
+        .inner_join(Node)
+        .select_also(Node)
+        .all(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+
+    let mut delegates_map: HashMap<String, Vec<String>> = HashMap::new();
+    for (stored_node, node_model) in res {
+        if let Some(delegate) = node_model.as_ref().and_then(delegate_multi_addr) {
+            delegates_map.entry(stored_node.pin_id).or_default().push(delegate);
+        }
+    }
+    Ok(delegates_map)
+}
+
+/// A reachable multi address (with `/p2p/{peer_id}`) of the node.
+fn delegate_multi_addr(node_model: &node::Model) -> Option<String> {
+    let multi_addr = node_model.multiaddrs.as_ref()?
+        .as_array()?
+        .iter()
+        .filter_map(|v| v.as_str())
+        .find(|v| !services::peering::is_loopback_multi_addr(v))?;
+    if multi_addr.contains("/p2p/") {
+        Some(multi_addr.to_owned())
+    } else {
+        Some(format!("{}/p2p/{}", multi_addr, node_model.peer_id))
+    }
+}
+
+/// Generate `PinStatus`. Use `pin` from request if exists, or generate it from the database.
+fn generate_pin_status(request: users_pins::Model,
+                       pin_model: &pin::Model,
+                       delegates: Vec<String>,
+                       pin: Option<models::Pin>)
+                       -> models::PinStatus {
+    // only the values of the request, since the pin might be shared
+    let pin = pin.unwrap_or_else(|| models::Pin::new(
+        pin_model.cid.clone(),
+        request.pin_name,
+        request.origins.and_then(|v| serde_json::from_value(v).ok()),
+        request.meta.and_then(|v| serde_json::from_value(v).ok()),
+    ));
+    models::PinStatus::new(
+        request.id,
+        spec_status_from_db(&pin_model.status),
+        request.created,
+        pin,
+        delegates,
+        None,
+    )
+}
+
+fn spec_status_from_db(status: &sea_orm_active_enums::Status) -> models::Status {
+    match status {
+        sea_orm_active_enums::Status::Queued => models::Status::Queued,
+        sea_orm_active_enums::Status::Pinning => models::Status::Pinning,
+        sea_orm_active_enums::Status::Pinned => models::Status::Pinned,
+        sea_orm_active_enums::Status::Failed
+        | sea_orm_active_enums::Status::NotFound
+        | sea_orm_active_enums::Status::Deleted => models::Status::Failed,
+    }
+}
+
+fn db_status_from_spec(status: models::Status) -> Vec<sea_orm_active_enums::Status> {
+    match status {
+        models::Status::Queued => vec![sea_orm_active_enums::Status::Queued],
+        models::Status::Pinning => vec![sea_orm_active_enums::Status::Pinning],
+        models::Status::Pinned => vec![sea_orm_active_enums::Status::Pinned],
+        models::Status::Failed => vec![
+            sea_orm_active_enums::Status::Failed,
+            sea_orm_active_enums::Status::NotFound,
+            sea_orm_active_enums::Status::Deleted,
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Request};
+    use tower::ServiceExt;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn test_list_pin_requests_by_meta() {
+        let conn = connect_test_db().await;
+        let state = test_db_state(&conn);
+        let user_model = services::auth::create_user(&state, "alice", sea_orm_active_enums::UserRole::User).await.unwrap();
+        let (_, token) = services::auth::create_token(&state, &user_model.id, None).await.unwrap();
+        for (id, app) in [("a", "x"), ("b", "y")] {
+            state.repository.insert_pin(test_pin(id, &format!("cid {id}"))).await.unwrap();
+            let meta = Some(serde_json::json!({ "app": app }));
+            daos::insert_pin_request(&user_model.id, id, None, meta, None, &conn).await.unwrap();
+        }
+
+        let app = crate::app::handlers::generate_pin_service_router(state.clone());
+        let req = Request::builder()
+            .uri("/pins?meta=%7B%22app%22%3A%22x%22%7D")
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let pin_results: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(pin_results["count"], 1);
+        assert_eq!(pin_results["results"][0]["pin"]["cid"], "cid a");
+    }
+
+    #[tokio::test]
+    async fn test_pin_requests_keep_own_meta() {
+        let conn = connect_test_db().await;
+        let state = test_db_state(&conn);
+        // the shared pin is created by the first request
+        state.repository.insert_pin(pin::Model {
+            name: Some("alice file".to_string()),
+            meta: Some(serde_json::json!({ "owner": "alice" })),
+            origins: Some(serde_json::json!(["/ip4/1.1.1.1/tcp/4001/p2p/alice"])),
+            ..test_pin("pin", "cid")
+        }).await.unwrap();
+        let alice_request = daos::insert_pin_request(
+            "alice", "pin", Some("alice file".to_string()),
+            Some(serde_json::json!({ "owner": "alice" })),
+            Some(serde_json::json!(["/ip4/1.1.1.1/tcp/4001/p2p/alice"])),
+            &conn,
+        ).await.unwrap();
+        let bob_request = daos::insert_pin_request("bob", "pin", None, None, None, &conn).await.unwrap();
+
+        let alice_pin = services::pin_service::get_pin_request(&state, "alice", &alice_request.id).await.unwrap().pin;
+        assert_eq!(alice_pin.name.as_deref(), Some("alice file"));
+        assert_eq!(alice_pin.meta.unwrap()["owner"], "alice");
+        assert_eq!(alice_pin.origins.unwrap(), ["/ip4/1.1.1.1/tcp/4001/p2p/alice"]);
+
+        let bob_pin = services::pin_service::get_pin_request(&state, "bob", &bob_request.id).await.unwrap().pin;
+        assert_eq!(bob_pin.cid, "cid");
+        assert!(bob_pin.name.is_none());
+        assert!(bob_pin.meta.is_none());
+        assert!(bob_pin.origins.is_none());
+    }
+}
@@ -0,0 +1,253 @@
+//! Storage quotas of users.
+//!
+//! Usage is computed from the pins requested by the user, and a pin is counted once
+//! even if it's requested many times. Deleted pins are not counted.
+//! Quotas of a user default to the ones in config.
+//!
+//! Uploads and pin requests of a user with quota are checked one by one in a Manager,
+//! so that concurrent ones could not exceed the quota together.
+//! The size of a pin requested by pin service is unknown until it's stored,
+//! so it's charged after that. Pin requests are rejected once no bytes remain,
+//! but a single request could exceed the remaining bytes.
+
+#[allow(unused_imports)]
+use tracing::{error, debug, warn, info, trace};
+use std::sync::Arc;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use crate::imports::dao_imports::*;
+use crate::app::{AppState, errors, services};
+use crate::app::common::ApiResult;
+
+/// Limits of a user. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quota {
+    /// Max total bytes of pins.
+    pub max_total_bytes: Option<u64>,
+    pub max_pin_count: Option<u64>,
+    /// Max bytes of a single uploaded file.
+    pub max_file_bytes: Option<u64>,
+}
+
+impl Quota {
+    /// Quota of the user, falling back to `default` for the limits not set.
+    pub fn of_user(user_model: &users::Model, default: &Quota) -> Quota {
+        let to_u64 = |v: Option<i64>| v.map(|v| v.max(0) as u64);
+        Quota {
+            max_total_bytes: to_u64(user_model.max_total_bytes).or(default.max_total_bytes),
+            max_pin_count: to_u64(user_model.max_pin_count).or(default.max_pin_count),
+            max_file_bytes: to_u64(user_model.max_file_bytes).or(default.max_file_bytes),
+        }
+    }
+}
+
+/// Locks of users to check quota one by one. `user_id -> lock`.
+///
+/// A lock is removed when no upload of the user holds or waits for it.
+pub type QuotaLocks = scc::HashMap<String, Arc<tokio::sync::Mutex<()>>>;
+
+/// Permission to upload, returned by `check_upload`.
+///
+/// Keep it until the pin request is recorded.
+#[derive(Debug)]
+pub(crate) struct UploadPermit {
+    /// Max bytes of the file to upload, or `None` if unlimited.
+    pub max_bytes: Option<u64>,
+    _lock: Option<UserLock>,
+}
+
+/// The lock of a user held by an upload.
+#[derive(Debug)]
+struct UserLock {
+    locks: Arc<QuotaLocks>,
+    user_id: String,
+    guard: Option<tokio::sync::OwnedMutexGuard<()>>,
+}
+
+impl Drop for UserLock {
+    fn drop(&mut self) {
+        // the guard keeps a reference to the lock
+        drop(self.guard.take());
+        // references are cloned only with the entry locked, so nobody takes it meanwhile
+        self.locks.remove_if(&self.user_id, |lock| Arc::strong_count(lock) == 1);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    /// Pins of unknown size are not counted.
+    pub total_bytes: u64,
+    pub pin_count: u64,
+}
+
+/// Get the usage of the user.
+pub(crate) async fn get_usage(state: &AppState, user_id: &str) -> ApiResult<Usage> {
+    let pins = state.repository.find_requested_pins(user_id)
+        .await.map_err(services::db::handle_db_error)?;
+    Ok(Usage {
+        total_bytes: pins.iter().map(|v| v.size.unwrap_or_default().max(0) as u64).sum(),
+        pin_count: pins.len() as u64,
+    })
+}
+
+/// Check quota before uploading a file or adding a pin request.
+///
+/// Wait for other uploads of the user with quota until their permits are dropped.
+pub(crate) async fn check_upload(state: &AppState, user_model: &users::Model) -> ApiResult<UploadPermit> {
+    let quota = Quota::of_user(user_model, &state.default_quota);
+    if quota == Quota::default() {
+        return Ok(UploadPermit { max_bytes: None, _lock: None });
+    }
+    let lock = state.quota_locks
+        .entry_async(user_model.id.clone()).await
+        .or_default()
+        .get()
+        .clone();
+    let user_lock = UserLock {
+        locks: state.quota_locks.clone(),
+        user_id: user_model.id.clone(),
+        guard: Some(lock.lock_owned().await),
+    };
+    let usage = get_usage(state, &user_model.id).await?;
+    if quota.max_pin_count.is_some_and(|max| usage.pin_count >= max) {
+        info!("User {} exceeds pin count quota. {:?}", user_model.id, usage);
+        return Err(quota_exceeded("Pin count quota exceeded", StatusCode::FORBIDDEN));
+    }
+    let remaining_bytes = quota.max_total_bytes.map(|max| max.saturating_sub(usage.total_bytes));
+    if remaining_bytes == Some(0) {
+        info!("User {} exceeds storage quota. {:?}", user_model.id, usage);
+        return Err(quota_exceeded("Storage quota exceeded", StatusCode::PAYLOAD_TOO_LARGE));
+    }
+    let max_bytes = match (remaining_bytes, quota.max_file_bytes) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    Ok(UploadPermit { max_bytes, _lock: Some(user_lock) })
+}
+
+/// Error when the file to upload is larger than the max bytes returned by `check_upload`.
+pub(crate) fn file_too_large() -> errors::ResponseError {
+    quota_exceeded("File is larger than the quota", StatusCode::PAYLOAD_TOO_LARGE)
+}
+
+fn quota_exceeded(msg: &str, status_code: StatusCode) -> errors::ResponseError {
+    errors::QUOTA_EXCEEDED.clone_to_error()
+        .modify_msg(msg)
+        .modify_status_code(status_code)
+}
+
+/// Set quota of the user. `None` means the default in config.
+pub(crate) async fn set_quota(state: &AppState, user_id: &str, quota: Quota) -> ApiResult<users::Model> {
+    let user_model = Users::find_by_id(user_id)
+        .one(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?
+        .ok_or_else(|| errors::DB_TARGET_DATA_NOT_EXIST.clone_to_error()
+            .modify_status_code(StatusCode::NOT_FOUND))?;
+    let to_i64 = |v: Option<u64>| v.map(|v| v.min(i64::MAX as u64) as i64);
+    let mut user_model: users::ActiveModel = user_model.into();
+    user_model.max_total_bytes = Set(to_i64(quota.max_total_bytes));
+    user_model.max_pin_count = Set(to_i64(quota.max_pin_count));
+    user_model.max_file_bytes = Set(to_i64(quota.max_file_bytes));
+    let user_model = user_model.update(&state.db_conn)
+        .await.map_err(services::db::handle_db_error)?;
+    info!("Set quota of user {}. {:?}", user_id, quota);
+    Ok(user_model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use axum::body::Body;
+    use axum::http::{header, Request};
+    use crate::app::daos;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn try_user_quota() {
+        // fake IPFS RPC to add files
+        let ipfs_app = axum::Router::new().route("/api/v0/add", axum::routing::post(|body: axum::body::Bytes| async move {
+            axum::Json(serde_json::json!({ "Name": "file", "Hash": "cid", "Size": body.len().to_string() }))
+        }));
+        let ipfs_address = serve_fake(ipfs_app).await;
+
+        let conn = connect_test_db().await;
+        let state = AppState {
+            ipfs_client: Arc::new(tiny_ipfs_client::ReqwestIpfsClient::new_with_reqwest_client(
+                ipfs_address,
+                reqwest::Client::new(),
+            )),
+            default_quota: Arc::new(services::quota::Quota {
+                max_file_bytes: Some(10),
+                ..Default::default()
+            }),
+            ..test_db_state(&conn)
+        };
+        let user_model = services::auth::create_user(&state, "alice", sea_orm_active_enums::UserRole::User).await.unwrap();
+        let upload = |body: &'static str, content_length: bool| {
+            let mut builder = Request::builder().method("POST").uri("/api/file");
+            if content_length {
+                builder = builder.header(header::CONTENT_LENGTH, body.len());
+            }
+            let req = builder.body(Body::from(body)).unwrap();
+            let state = state.clone();
+            async move { services::file::add_file_to_ipfs(&state, req, Some(10)).await }
+        };
+        assert_eq!(upload("small", false).await.unwrap().size, "5");
+        let err = upload("larger than max bytes", true).await.unwrap_err();
+        assert_eq!(err, errors::QUOTA_EXCEEDED);
+        assert_eq!(err.status_code, Some(StatusCode::PAYLOAD_TOO_LARGE));
+        // aborted during streaming
+        let err = upload("larger than max bytes", false).await.unwrap_err();
+        assert_eq!(err, errors::QUOTA_EXCEEDED);
+
+        // usage counts a pin once, and skips deleted pins
+        assert_eq!(services::quota::check_upload(&state, &user_model).await.unwrap().max_bytes, Some(10));
+        for (pin_id, size, status) in [
+            ("pin1", 60, sea_orm_active_enums::Status::Pinned),
+            ("pin2", 1000, sea_orm_active_enums::Status::Deleted),
+        ] {
+            state.repository.insert_pin(pin::Model {
+                size: Some(size),
+                status,
+                ..test_pin(pin_id, pin_id)
+            }).await.unwrap();
+        }
+        for pin_id in ["pin1", "pin1", "pin2"] {
+            daos::insert_pin_request(&user_model.id, pin_id, None, None, None, &conn).await.unwrap();
+        }
+        let usage = services::quota::get_usage(&state, &user_model.id).await.unwrap();
+        assert_eq!(usage, services::quota::Usage { total_bytes: 60, pin_count: 1 });
+
+        // quota of the user overrides the default
+        let user_model = services::quota::set_quota(&state, &user_model.id, services::quota::Quota {
+            max_total_bytes: Some(65),
+            ..Default::default()
+        }).await.unwrap();
+        let permit = services::quota::check_upload(&state, &user_model).await.unwrap();
+        assert_eq!(permit.max_bytes, Some(5));
+        // another upload of the user waits until the permit is dropped
+        let other_upload = services::quota::check_upload(&state, &user_model);
+        let timeout = std::time::Duration::from_millis(100);
+        assert!(tokio::time::timeout(timeout, other_upload).await.is_err());
+        drop(permit);
+        let other_upload = services::quota::check_upload(&state, &user_model);
+        assert!(tokio::time::timeout(timeout, other_upload).await.unwrap().is_ok());
+        // the lock goes away with the last permit
+        assert!(state.quota_locks.is_empty());
+        let user_model = services::quota::set_quota(&state, &user_model.id, services::quota::Quota {
+            max_total_bytes: Some(60),
+            ..Default::default()
+        }).await.unwrap();
+        let err = services::quota::check_upload(&state, &user_model).await.unwrap_err();
+        assert_eq!(err.status_code, Some(StatusCode::PAYLOAD_TOO_LARGE));
+        let user_model = services::quota::set_quota(&state, &user_model.id, services::quota::Quota {
+            max_pin_count: Some(1),
+            ..Default::default()
+        }).await.unwrap();
+        let err = services::quota::check_upload(&state, &user_model).await.unwrap_err();
+        assert_eq!(err.status_code, Some(StatusCode::FORBIDDEN));
+    }
+}
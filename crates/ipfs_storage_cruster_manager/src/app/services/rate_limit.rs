@@ -0,0 +1,173 @@
+//! Rate limits of requests, by client IP and by user of API token.
+//!
+//! Each kind of route has its own budget, so that flooding one doesn't block others.
+
+#[allow(unused_imports)]
+use tracing::{error, debug, warn, info, trace};
+use std::net::SocketAddr;
+use std::time::Duration;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use ipfs_node_wrapper_structs::rate_limit::{self, BucketState, Rate, RateLimiter};
+use crate::app::{AppState, errors};
+use crate::app::services::auth::AuthUser;
+
+const PURGE_INTERVAL_MS: u64 = 60 * 1000;
+
+/// Limits of a kind of routes. No limit if `None`.
+#[derive(Debug, Default)]
+pub struct RateBudget {
+    pub per_ip: Option<RateLimiter>,
+    pub per_user: Option<RateLimiter>,
+}
+
+impl RateBudget {
+    pub fn new(per_ip: Option<Rate>, per_user: Option<Rate>) -> Self {
+        RateBudget {
+            per_ip: per_ip.map(RateLimiter::new),
+            per_user: per_user.map(RateLimiter::new),
+        }
+    }
+
+    /// Take a token from each limit. Return how long to wait if any is exhausted,
+    /// without taking tokens from the others.
+    fn check(&self, ip: Option<&str>, user_id: Option<&str>) -> Result<(), Duration> {
+        let limits = [(&self.per_ip, ip), (&self.per_user, user_id)];
+        rate_limit::check_all(limits.into_iter()
+            .filter_map(|(limiter, key)| Some((limiter.as_ref()?, key?))))
+    }
+
+    fn limiters(&self) -> impl Iterator<Item = (&'static str, &RateLimiter)> {
+        [("ip", &self.per_ip), ("user", &self.per_user)].into_iter()
+            .filter_map(|(scope, limiter)| limiter.as_ref().map(|v| (scope, v)))
+    }
+}
+
+/// Budgets of all kinds of routes.
+#[derive(Debug, Default)]
+pub struct RateLimits {
+    pub upload: RateBudget,
+    pub advice: RateBudget,
+}
+
+impl RateLimits {
+    fn budgets(&self) -> [(&'static str, &RateBudget); 2] {
+        [("upload", &self.upload), ("advice", &self.advice)]
+    }
+}
+
+/// State of a limiter.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimiterState {
+    /// Kind of routes, like `upload`.
+    pub budget: &'static str,
+    /// What is limited, `ip` or `user`.
+    pub scope: &'static str,
+    pub rate: Rate,
+    /// Keys not listed have full buckets.
+    pub buckets: Vec<BucketState>,
+}
+
+pub(crate) fn list_limiter_states(state: &AppState) -> Vec<RateLimiterState> {
+    state.rate_limits.budgets().into_iter()
+        .flat_map(|(budget, v)| v.limiters().map(move |(scope, limiter)| RateLimiterState {
+            budget,
+            scope,
+            rate: limiter.rate(),
+            buckets: limiter.states(),
+        }))
+        .collect()
+}
+
+/// Middleware to limit upload routes. Must run after `services::auth::resolve_user`.
+pub(crate) async fn limit_upload(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    limit(&state.rate_limits.upload, req, next).await
+}
+
+/// Middleware to limit advice routes. Must run after `services::auth::resolve_user`.
+pub(crate) async fn limit_advice(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    limit(&state.rate_limits.advice, req, next).await
+}
+
+async fn limit(budget: &RateBudget, req: Request, next: Next) -> Response {
+    // no client address if not served with connect info, e.g. in tests
+    let ip = req.extensions().get::<ConnectInfo<SocketAddr>>()
+        .map(|v| v.0.ip().to_string());
+    let user_id = req.extensions().get::<AuthUser>()
+        .map(|v| v.id().to_owned());
+    if let Err(wait) = budget.check(ip.as_deref(), user_id.as_deref()) {
+        debug!("Rate limit {} from ip {:?}, user {:?}", req.uri(), ip, user_id);
+        return too_many_requests(wait);
+    }
+    next.run(req).await
+}
+
+fn too_many_requests(wait: Duration) -> Response {
+    let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+    let mut res = errors::RATE_LIMITED.clone_to_error()
+        .modify_status_code(StatusCode::TOO_MANY_REQUESTS)
+        .into_response();
+    res.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    res
+}
+
+/// Spawn a task to free the memory of idle clients regularly.
+pub fn spawn_rate_limit_purger(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(PURGE_INTERVAL_MS));
+        loop {
+            interval.tick().await;
+            for (_, budget) in state.rate_limits.budgets() {
+                for (_, limiter) in budget.limiters() {
+                    limiter.purge_full();
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use axum::body::Body;
+    use tower::ServiceExt;
+    use crate::app::services;
+    use crate::repository::memory::MemoryRepository;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let state = AppState {
+            rate_limits: Arc::new(services::rate_limit::RateLimits {
+                advice: services::rate_limit::RateBudget::new(Some("1/m".parse().unwrap()), None),
+                ..Default::default()
+            }),
+            ..test_state(Arc::new(MemoryRepository::new()))
+        };
+        let app = crate::app::handlers::generate_router(&state).with_state(state.clone());
+        let call = |uri: &'static str, ip: &str| {
+            let mut req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let addr: std::net::SocketAddr = format!("{ip}:1234").parse().unwrap();
+            req.extensions_mut().insert(axum::extract::ConnectInfo(addr));
+            app.clone().oneshot(req)
+        };
+        assert_ne!(call("/advice?cid=cid", "1.1.1.1").await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+        let res = call("/advice?cid=cid", "1.1.1.1").await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = res.headers()[header::RETRY_AFTER].to_str().unwrap().parse().unwrap();
+        assert!(retry_after > 0 && retry_after <= 60);
+        // budgets are separated by clients and routes
+        assert_ne!(call("/advice?cid=cid", "2.2.2.2").await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_ne!(call("/usage", "1.1.1.1").await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let limiters = services::rate_limit::list_limiter_states(&state);
+        assert_eq!(limiters.len(), 1);
+        assert_eq!((limiters[0].budget, limiters[0].scope), ("advice", "ip"));
+        assert_eq!(limiters[0].buckets.len(), 2);
+    }
+}
@@ -0,0 +1,280 @@
+//! Replication of pins through an outbox.
+//!
+//! An intent to replicate a pin is written to `replication_outbox` in the same transaction as the pin.
+//! Its creator processes it right away, and a worker retries unfinished intents, e.g. after a crash.
+//!
+//! Processing is idempotent. The nodes decided to store the pin are recorded in the intent before pinning,
+//! and checked first in next attempt, so the data pinned there is not unknown to the database.
+
+#[allow(unused_imports)]
+use tracing::{error, debug, warn, info, trace};
+use crate::imports::dao_imports::*;
+use crate::app::{AppState, errors, services};
+use crate::app::common::ApiResult;
+use crate::file_decision::TargetAdminIpfsNodeMessage;
+use crate::repository::{NewReplication, NodeFilter};
+
+/// An intent is claimed longer than the time to pin, to avoid being processed twice.
+const CLAIM_MARGIN_MS: u64 = 10 * 60 * 1000;
+const RETRY_BASE_DELAY_MS: u64 = 10 * 1000;
+const RETRY_MAX_DELAY_MS: u64 = 60 * 60 * 1000;
+const MAX_LAST_ERROR_LEN: usize = 1000;
+/// Max number of intents to process in one round.
+const PROCESS_BATCH_SIZE: u64 = 100;
+const CHECK_PIN_TIMEOUT_MS: u64 = 5000;
+const CONNECT_ORIGIN_TIMEOUT_MS: u64 = 5000;
+
+/// Config of the replication worker.
+#[derive(Debug, Clone)]
+pub struct ReplicationConfig {
+    pub interval_time_ms: u64,
+    /// Give up an intent after this number of attempts, and mark the pin as `Failed`.
+    pub max_attempts: u32,
+}
+
+/// A new intent claimed by the caller, who should process it by `process_replication`.
+pub(crate) fn new_replication(state: &AppState, origins: Vec<String>) -> NewReplication {
+    NewReplication {
+        origins,
+        claimed_until: claim_deadline(state),
+        request: None,
+    }
+}
+
+fn claim_deadline(state: &AppState) -> DateTimeUtc {
+    let claim_ms = state.pin_config.timeout_ms + CLAIM_MARGIN_MS;
+    chrono::Utc::now() + chrono::Duration::milliseconds(claim_ms as i64)
+}
+
+/// Delay before the next attempt, doubled for each failed attempt.
+fn retry_delay_ms(attempts: i32) -> u64 {
+    let exp = attempts.clamp(1, 16) as u32 - 1;
+    RETRY_BASE_DELAY_MS.saturating_mul(1 << exp).min(RETRY_MAX_DELAY_MS)
+}
+
+/// Replicate the pin of a claimed intent.
+///
+/// Use `decided_nodes` if it's just decided by `services::file::decide_store_nodes`.
+/// The intent is finished if succeed, or made available again later if failed.
+///
+/// Return the nodes that store the pin.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn process_replication(state: &AppState,
+                                        intent: replication_outbox::Model,
+                                        decided_nodes: Option<Vec<TargetAdminIpfsNodeMessage>>)
+                                        -> ApiResult<Vec<TargetAdminIpfsNodeMessage>> {
+    let (pin_model, stored_nodes) = match replicate(state, &intent, decided_nodes).await {
+        Ok(v) => v,
+        Err(e) => {
+            let delay_ms = retry_delay_ms(intent.attempts);
+            warn!("Failed to replicate pin {} in attempt {}. Retry in {} ms. msg: {:?}",
+                intent.pin_id, intent.attempts, delay_ms, e);
+            let available_at = chrono::Utc::now() + chrono::Duration::milliseconds(delay_ms as i64);
+            let mut last_error = format!("{e:?}");
+            if last_error.len() > MAX_LAST_ERROR_LEN {
+                let mut end = MAX_LAST_ERROR_LEN;
+                while !last_error.is_char_boundary(end) {
+                    end -= 1;
+                }
+                last_error.truncate(end);
+            }
+            if let Err(e) = state.repository.delay_replication(intent.id, available_at, last_error).await {
+                error!("Failed to delay replication of pin {}. msg: {:?}", intent.pin_id, e);
+            }
+            return Err(e);
+        }
+    };
+
+    let size = match pin_model.size {
+        Some(_) => None,
+        None => get_dag_size(state, &pin_model.cid, &stored_nodes[0]).await,
+    };
+    let node_ids = stored_nodes.iter().map(|v| v.id.clone()).collect();
+    state.repository.finish_replication(&intent, node_ids, size)
+        .await.map_err(services::db::handle_db_error)?;
+    info!("Finish replicating pin {} of cid {}", pin_model.id, pin_model.cid);
+    Ok(stored_nodes)
+}
+
+/// Store the pin to the nodes which store it already, `decided_nodes`, or newly decided nodes.
+///
+/// Return the pin and the nodes that store it. The nodes are never empty.
+async fn replicate(state: &AppState,
+                   intent: &replication_outbox::Model,
+                   decided_nodes: Option<Vec<TargetAdminIpfsNodeMessage>>)
+                   -> ApiResult<(pin::Model, Vec<TargetAdminIpfsNodeMessage>)> {
+    let pin_model = state.repository.find_pin_by_id(&intent.pin_id)
+        .await.map_err(services::db::handle_db_error)?
+        .ok_or_else(|| errors::DB_TARGET_DATA_NOT_EXIST.clone_to_error())?;
+    if let Err(e) = state.repository.update_pin_status(&pin_model.id, sea_orm_active_enums::Status::Pinning).await {
+        warn!("Failed to update status of pin {}. msg: {:?}", pin_model.id, e);
+    }
+
+    let mut target_node_ids = json_strings(&intent.target_node_ids);
+    let target_nodes = match decided_nodes {
+        Some(v) => v,
+        None => {
+            if !target_node_ids.is_empty() {
+                let stored_nodes = find_nodes_storing(state, &pin_model.cid, target_node_ids.clone()).await?;
+                if !stored_nodes.is_empty() {
+                    info!("Pin {} has been stored in {} nodes decided before", pin_model.cid, stored_nodes.len());
+                    return Ok((pin_model, stored_nodes));
+                }
+            }
+            services::file::decide_store_nodes(state, &pin_model.cid).await?
+        }
+    };
+
+    // record before pinning
+    for node in target_nodes.iter() {
+        if !target_node_ids.contains(&node.id) {
+            target_node_ids.push(node.id.clone());
+        }
+    }
+    if let Err(e) = state.repository.record_replication_targets(intent.id, target_node_ids).await {
+        let _ = state.file_storage_decision_maker.finish_storage(&pin_model.cid).await;
+        return Err(services::db::handle_db_error(e));
+    }
+
+    connect_to_origins(state, &target_nodes, &json_strings(&intent.origins)).await;
+    let stored_nodes = services::file::store_file_to_decided_nodes(state, pin_model.cid.clone(), target_nodes).await?;
+    if stored_nodes.is_empty() {
+        return Err(errors::IPFS_NODE_CLUSTER_UNHEALTHY.clone_to_error());
+    }
+    Ok((pin_model, stored_nodes))
+}
+
+/// Find the nodes in `node_ids` which have pinned the CID.
+async fn find_nodes_storing(state: &AppState, cid: &str, node_ids: Vec<String>) -> ApiResult<Vec<TargetAdminIpfsNodeMessage>> {
+    let filter = NodeFilter {
+        id_in: node_ids,
+        ..Default::default()
+    };
+    let nodes = state.repository.find_admin_nodes(&filter)
+        .await.map_err(services::db::handle_db_error)?;
+    let mut stored_nodes = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let pinned = services::file::is_pinned_in_node(
+            state, &node.rpc_address, node.wrapper_admin_address.as_deref(), cid, CHECK_PIN_TIMEOUT_MS).await;
+        if pinned {
+            stored_nodes.push(node);
+        }
+    }
+    Ok(stored_nodes)
+}
+
+/// Connect the nodes to origins in best effort, so that the data could be found faster.
+async fn connect_to_origins(state: &AppState, nodes: &[TargetAdminIpfsNodeMessage], origins: &[String]) {
+    for node in nodes {
+        let ipfs_client = state.get_ipfs_client_with_rpc_addr(node.rpc_address.clone());
+        for origin in origins {
+            let res = tokio::time::timeout(
+                tokio::time::Duration::from_millis(CONNECT_ORIGIN_TIMEOUT_MS),
+                ipfs_client.swarm_connect(origin),
+            ).await;
+            if !matches!(res, Ok(Ok(()))) {
+                debug!("Failed to connect node {} to origin {}", node.id, origin);
+            }
+        }
+    }
+}
+
+/// Get the size of the data, which is in local of the node now.
+async fn get_dag_size(state: &AppState, cid: &str, node: &TargetAdminIpfsNodeMessage) -> Option<i64> {
+    let ipfs_client = state.get_ipfs_client_with_rpc_addr(node.rpc_address.clone());
+    match ipfs_client.dag_stat(cid).await.map(|v| v.dag_size()) {
+        Ok(Some(size)) => Some(size as i64),
+        res => {
+            warn!("Failed to get size of pin {}. msg: {:?}", cid, res);
+            None
+        }
+    }
+}
+
+fn json_strings(value: &Option<Json>) -> Vec<String> {
+    value.as_ref()
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Spawn a task to retry unfinished replication intents regularly.
+pub fn spawn_replication_worker(state: AppState, config: ReplicationConfig) -> tokio::task::JoinHandle<()> {
+    info!("Replication worker starts. {:?}", config);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(config.interval_time_ms));
+        loop {
+            interval.tick().await;
+            process_available_replications(&state, &config).await;
+        }
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn process_available_replications(state: &AppState, config: &ReplicationConfig) {
+    let intents = match state.repository.find_available_replications(PROCESS_BATCH_SIZE).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to find replication intents. msg: {e:?}");
+            return;
+        }
+    };
+    for mut intent in intents {
+        if intent.attempts >= config.max_attempts as i32 {
+            error!("Give up replicating pin {} after {} attempts. Last error: {:?}",
+                intent.pin_id, intent.attempts, intent.last_error);
+            if let Err(e) = state.repository.abandon_replication(&intent).await {
+                error!("Failed to abandon replication of pin {}. msg: {:?}", intent.pin_id, e);
+            }
+            continue;
+        }
+        match state.repository.claim_replication(intent.id, claim_deadline(state)).await {
+            Ok(true) => intent.attempts += 1,
+            // claimed by others
+            Ok(false) => continue,
+            Err(e) => {
+                error!("Failed to claim replication of pin {}. msg: {:?}", intent.pin_id, e);
+                continue;
+            }
+        }
+        let _ = process_replication(state, intent, None).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::repository::Repository;
+    use crate::repository::memory::MemoryRepository;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay_ms(1), RETRY_BASE_DELAY_MS);
+        assert_eq!(retry_delay_ms(3), RETRY_BASE_DELAY_MS * 4);
+        assert_eq!(retry_delay_ms(100), RETRY_MAX_DELAY_MS);
+    }
+
+    #[tokio::test]
+    async fn test_failed_replication_is_delayed() {
+        let repository = Arc::new(MemoryRepository::new());
+        let state = test_state(repository.clone());
+        let new_replication = services::replication::new_replication(&state, vec![]);
+        let intent = repository.insert_pin_to_replicate(test_pin("pin", "cid"), new_replication)
+            .await.unwrap().unwrap();
+
+        // no node to store
+        for _ in 0..2 {
+            let res = services::replication::process_replication(&state, intent.clone(), None).await;
+            assert!(res.unwrap_err() == errors::IPFS_NODE_CLUSTER_UNHEALTHY);
+        }
+        let delayed = repository.find_replication(intent.id).unwrap();
+        assert!(delayed.available_at > chrono::Utc::now());
+        assert!(delayed.last_error.is_some());
+
+        repository.abandon_replication(&delayed).await.unwrap();
+        assert!(repository.find_replication(intent.id).is_none());
+        let pin_model = repository.find_pin_by_id("pin").await.unwrap().unwrap();
+        assert_eq!(pin_model.status, sea_orm_active_enums::Status::Failed);
+    }
+}
@@ -1,7 +1,9 @@
+use std::net::SocketAddr;
 use axum::Router;
 use tokio::net::ToSocketAddrs;
 use tracing::info;
 use serde::Deserialize;
+use ipfs_node_wrapper_structs::rate_limit::Rate;
 use crate::app;
 
 // TODO 日志级别可配置化
@@ -9,8 +11,191 @@ use crate::app;
 #[derive(Deserialize)]
 pub struct AppConfig {
     pub database_url: String,
+    /// Whether to run pending database migrations on startup.
+    #[serde(default)]
+    pub database_auto_migrate: bool,
     pub ipfs_rpc_address: String,
     pub ipfs_swarm_multi_address: String,
+    /// Strategy to give download advice.
+    #[serde(default)]
+    pub file_download_decision_maker: DownloadDecisionMakerKind,
+    /// Interval of probing Wrappers. Only used by `latency` decision maker.
+    #[serde(default = "default_latency_probe_interval_ms")]
+    pub latency_probe_interval_ms: u64,
+    /// Probability of choosing a random replica. Only used by `latency` decision maker.
+    #[serde(default = "default_latency_exploration_rate")]
+    pub latency_exploration_rate: f64,
+    /// Whether to serve IPFS Pinning Service API at `/pins`.
+    #[serde(default = "default_true")]
+    pub pin_service_enabled: bool,
+    /// How to pin files to storage nodes.
+    #[serde(default)]
+    pub pin_mode: PinMode,
+    /// Interval of polling the status of pin. Only used by `wrapper` pin mode.
+    #[serde(default = "default_wrapper_pin_poll_interval_ms")]
+    pub wrapper_pin_poll_interval_ms: u64,
+    /// Give up waiting for a pin after this time. Only used by `wrapper` pin mode.
+    #[serde(default = "default_wrapper_pin_timeout_ms")]
+    pub wrapper_pin_timeout_ms: u64,
+    /// Nodes below this Kubo version (like `0.26.0`) are not chosen to store new pins.
+    #[serde(default)]
+    pub min_kubo_version: Option<String>,
+    /// Whether to check the health of nodes regularly.
+    #[serde(default = "default_true")]
+    pub health_check_enabled: bool,
+    #[serde(default = "default_health_check_interval_ms")]
+    pub health_check_interval_ms: u64,
+    #[serde(default = "default_health_check_timeout_ms")]
+    pub health_check_timeout_ms: u64,
+    /// Consecutive failures before an `Online` node becomes `Unhealthy`.
+    #[serde(default = "default_health_check_failure_threshold")]
+    pub health_check_failure_threshold: u32,
+    /// Consecutive successes before an unhealthy node is re-bootstrapped.
+    #[serde(default = "default_health_check_success_threshold")]
+    pub health_check_success_threshold: u32,
+    /// Consecutive failures before a node becomes `Offline`.
+    #[serde(default = "default_health_check_offline_threshold")]
+    pub health_check_offline_threshold: u32,
+    /// Secret shared with Wrappers to register themselves. Registration is disabled if not set.
+    pub join_secret: Option<String>,
+    /// API token of the user `admin`, created on startup if missing. Used to create the first users.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// Origins allowed by CORS, like `https://example.com`. Any origin is allowed if empty.
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+    /// Secret shared with Wrappers to sign download URLs. URLs are not signed if not set.
+    pub download_url_secret: Option<String>,
+    /// Default quotas of users, unlimited if not set. Could be overridden for each user by admin API.
+    #[serde(default)]
+    pub quota_max_total_bytes: Option<u64>,
+    #[serde(default)]
+    pub quota_max_pin_count: Option<u64>,
+    #[serde(default)]
+    pub quota_max_file_bytes: Option<u64>,
+    /// Rate limits of uploading by client IP and by user, like `10/s`, `600/m` or `1000/h`. Unlimited if not set.
+    #[serde(default)]
+    pub rate_limit_upload_per_ip: Option<Rate>,
+    #[serde(default)]
+    pub rate_limit_upload_per_user: Option<Rate>,
+    /// Rate limits of download advice and its feedback.
+    #[serde(default)]
+    pub rate_limit_advice_per_ip: Option<Rate>,
+    #[serde(default)]
+    pub rate_limit_advice_per_user: Option<Rate>,
+    /// Signed download URLs expire after this time.
+    #[serde(default = "default_download_url_ttl_secs")]
+    pub download_url_ttl_secs: u64,
+    /// A registered node fails health check if no heartbeat is received in this time.
+    #[serde(default = "default_heartbeat_timeout_ms")]
+    pub heartbeat_timeout_ms: u64,
+    /// Whether to keep storage nodes peered with the master IPFS node.
+    #[serde(default = "default_true")]
+    pub swarm_peering_enabled: bool,
+    /// Whether to peer storage nodes with each other.
+    #[serde(default)]
+    pub swarm_peering_mesh: bool,
+    #[serde(default = "default_swarm_peering_interval_ms")]
+    pub swarm_peering_interval_ms: u64,
+    /// Whether to unpin expired pins regularly.
+    #[serde(default = "default_true")]
+    pub pin_expiry_enabled: bool,
+    #[serde(default = "default_pin_expiry_interval_ms")]
+    pub pin_expiry_interval_ms: u64,
+    /// Interval of retrying unfinished replications.
+    #[serde(default = "default_replication_interval_ms")]
+    pub replication_interval_ms: u64,
+    /// Mark the pin `Failed` after this number of replication attempts.
+    #[serde(default = "default_replication_max_attempts")]
+    pub replication_max_attempts: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadDecisionMakerKind {
+    /// Choose a replica randomly.
+    #[default]
+    Random,
+    /// Choose the fastest healthy replica.
+    Latency,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PinMode {
+    /// Pin by IPFS RPC of storage nodes directly.
+    #[default]
+    Rpc,
+    /// Pin in background by the admin API of Wrappers, then poll the status.
+    /// Unpinning, pin checks and node identity checks are also done by Wrappers.
+    ///
+    /// IPFS RPC of storage nodes becomes optional, and is still used in best effort
+    /// to bootstrap, peer, connect to origins and get DAG size and repo stat.
+    Wrapper,
+}
+
+fn default_latency_probe_interval_ms() -> u64 {
+    10000
+}
+
+fn default_latency_exploration_rate() -> f64 {
+    0.05
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_health_check_interval_ms() -> u64 {
+    10000
+}
+
+fn default_health_check_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_health_check_failure_threshold() -> u32 {
+    3
+}
+
+fn default_health_check_success_threshold() -> u32 {
+    2
+}
+
+fn default_health_check_offline_threshold() -> u32 {
+    30
+}
+
+fn default_heartbeat_timeout_ms() -> u64 {
+    30000
+}
+
+fn default_wrapper_pin_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_wrapper_pin_timeout_ms() -> u64 {
+    600000
+}
+
+fn default_download_url_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_swarm_peering_interval_ms() -> u64 {
+    30000
+}
+
+fn default_pin_expiry_interval_ms() -> u64 {
+    60000
+}
+
+fn default_replication_interval_ms() -> u64 {
+    30000
+}
+
+fn default_replication_max_attempts() -> u32 {
+    10
 }
 
 #[tracing::instrument(skip_all)]
@@ -32,5 +217,6 @@ pub async fn serve(app_config: AppConfig) {
 /// Tool to bind server to port
 async fn generate_server(address: impl ToSocketAddrs, app: Router) {
     let listener = tokio::net::TcpListener::bind(address).await.unwrap();
-    axum::serve(listener, app).await.unwrap()
+    // client address is used by rate limits
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
 }
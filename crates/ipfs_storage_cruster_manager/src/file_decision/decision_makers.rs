@@ -1,27 +1,46 @@
 use std::collections::HashSet;
 use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::Instant;
 use axum::async_trait;
 use reqwest::Client;
-use sea_orm::DatabaseConnection;
 #[allow(unused_imports)]
-use tracing::{error, info, warn};
+use tracing::{error, info, warn, debug, trace};
+use ipfs_node_wrapper_client::admin::IpfsNodeWrapperAdminClient;
 use crate::imports::dao_imports::*;
 use crate::app::common::ApiResult;
-use crate::app::{services, errors, daos};
-use crate::file_decision::{FileDownloadDecisionMaker, FileStorageDecisionMaker, TargetAdminIpfsNodeMessage, TargetPublicWrapperMessage};
+use crate::app::{services, errors};
+use crate::file_decision::{FileDownloadDecisionMaker, FileStorageDecisionMaker, KuboVersion, NodeDownloadEstimate, TargetAdminIpfsNodeMessage, TargetPublicWrapperMessage};
+use crate::file_decision::{choose_random_avoiding_maintenance, filter_nodes_by_min_kubo_version};
+use crate::repository::{NodeFilter, Repository};
+
+/// Nodes with these status are not chosen to store new pins.
+const UNAVAILABLE_STORE_NODE_STATUS: [sea_orm_active_enums::NodeStatus; 2] = [
+    sea_orm_active_enums::NodeStatus::Offline,
+    sea_orm_active_enums::NodeStatus::Maintenance,
+];
 
 /// Simple decision maker of `FileStoreDecision`.
 pub struct RandomFileStorageDecisionMaker {
     /// Store the status of tasks. HashSet<String> is the set of stored nodes.
     task_map: scc::HashMap<String, HashSet<String>>,
+    /// Nodes below this version are not chosen.
+    min_kubo_version: Option<KuboVersion>,
 }
 
 impl RandomFileStorageDecisionMaker {
     pub fn new() -> Self {
         RandomFileStorageDecisionMaker {
             task_map: scc::HashMap::new(),
+            min_kubo_version: None,
         }
     }
+
+    /// Exclude nodes below `min_kubo_version`.
+    pub fn with_min_kubo_version(mut self, min_kubo_version: Option<KuboVersion>) -> Self {
+        self.min_kubo_version = min_kubo_version;
+        self
+    }
 }
 
 impl Debug for RandomFileStorageDecisionMaker {
@@ -35,15 +54,17 @@ impl FileStorageDecisionMaker for RandomFileStorageDecisionMaker {
     #[tracing::instrument(skip_all)]
     async fn decide_store_node(&self,
                                cid: &str,
-                               db_conn: &DatabaseConnection,
+                               repository: &dyn Repository,
                                _reqwest_client: &Client)
                                -> ApiResult<Vec<TargetAdminIpfsNodeMessage>> {
         const STORE_NODE_NUM: usize = 2;
-        let available_nodes = Node::find()
-            .filter(node::Column::NodeStatus.ne(sea_orm_active_enums::NodeStatus::Offline))
-            .into_partial_model::<TargetAdminIpfsNodeMessage>()
-            .all(db_conn).await
-            .map_err(services::db::handle_db_error)?;
+        let filter = NodeFilter {
+            status_not_in: UNAVAILABLE_STORE_NODE_STATUS.to_vec(),
+            ..Default::default()
+        };
+        let available_nodes = repository.find_admin_nodes(&filter)
+            .await.map_err(services::db::handle_db_error)?;
+        let available_nodes = filter_nodes_by_min_kubo_version(available_nodes, self.min_kubo_version);
         let available_node_num = available_nodes.len();
 
         // It's ok when `available_node_num` is less than node_num.
@@ -65,7 +86,7 @@ impl FileStorageDecisionMaker for RandomFileStorageDecisionMaker {
     #[tracing::instrument(skip_all)]
     async fn decide_store_node_fail_one(&self,
                                         cid: &str,
-                                        db_conn: &DatabaseConnection,
+                                        repository: &dyn Repository,
                                         _reqwest_client: &Client)
                                         -> ApiResult<Vec<TargetAdminIpfsNodeMessage>> {
         // retry would be executed one by one
@@ -77,12 +98,14 @@ impl FileStorageDecisionMaker for RandomFileStorageDecisionMaker {
             }
             Some(mut pre_decision_entry) => {
                 let pre_decision = pre_decision_entry.get();
-                let available_nodes = Node::find()
-                    .filter(node::Column::NodeStatus.ne(sea_orm_active_enums::NodeStatus::Offline))
-                    .filter(node::Column::Id.is_not_in(pre_decision))
-                    .into_partial_model::<TargetAdminIpfsNodeMessage>()
-                    .all(db_conn).await
-                    .map_err(services::db::handle_db_error)?;
+                let filter = NodeFilter {
+                    status_not_in: UNAVAILABLE_STORE_NODE_STATUS.to_vec(),
+                    id_not_in: pre_decision.iter().cloned().collect(),
+                    ..Default::default()
+                };
+                let available_nodes = repository.find_admin_nodes(&filter)
+                    .await.map_err(services::db::handle_db_error)?;
+                let available_nodes = filter_nodes_by_min_kubo_version(available_nodes, self.min_kubo_version);
                 let available_node_num = available_nodes.len();
 
                 let decide_result = fastrand::choice(available_nodes);
@@ -135,14 +158,14 @@ impl FileDownloadDecisionMaker for RandomFileDownloadDecisionMaker {
     #[tracing::instrument(skip_all)]
     async fn decide_download_node(&self,
                                   cid: &str,
-                                  db_conn: &DatabaseConnection,
+                                  repository: &dyn Repository,
                                   _reqwest_client: &Client
     ) -> ApiResult<TargetPublicWrapperMessage> {
-        let available_nodes = daos::find_nodes_with_pin_cid(cid, db_conn)
+        let available_nodes = repository.find_nodes_with_pin_cid(cid)
             .await.map_err(services::db::handle_db_error)?;
         let available_node_num = available_nodes.len();
 
-        let decide_result = fastrand::choice(available_nodes);
+        let decide_result = choose_random_avoiding_maintenance(available_nodes);
         if let Some(decide_result) = decide_result {
             info!("Find {available_node_num} available IPFS nodes. Result: {decide_result:?}");
             Ok(decide_result)
@@ -152,3 +175,307 @@ impl FileDownloadDecisionMaker for RandomFileDownloadDecisionMaker {
     }
 }
 
+
+/// Decision maker of `FileDownloadDecisionMaker` which chooses the fastest healthy replica.
+///
+/// Keep a rolling RTT/throughput estimate of each Wrapper.
+/// RTT comes from periodic probes to Wrapper's admin `/api/info`,
+/// and throughput comes from the feedback of finished downloads.
+pub struct LatencyFileDownloadDecisionMaker {
+    /// `node_id -> estimate`.
+    estimates: Arc<scc::HashMap<String, NodeEstimateRecord>>,
+    /// Probability of choosing a random replica instead of the fastest one.
+    exploration_rate: f64,
+}
+
+/// Rolling estimate of a node.
+#[derive(Clone, Debug, Default)]
+struct NodeEstimateRecord {
+    rtt_ms: Option<f64>,
+    throughput_bytes_per_sec: Option<f64>,
+    consecutive_probe_failures: u32,
+}
+
+impl LatencyFileDownloadDecisionMaker {
+    /// Weight of the newest sample in rolling estimates.
+    const SMOOTHING_FACTOR: f64 = 0.3;
+    /// Size of a reference file to convert throughput into time cost.
+    const REFERENCE_FILE_SIZE: f64 = 1024.0 * 1024.0;
+    const PROBE_TIMEOUT_MS: u64 = 2000;
+
+    pub fn new(exploration_rate: f64) -> Self {
+        LatencyFileDownloadDecisionMaker {
+            estimates: Arc::new(scc::HashMap::new()),
+            exploration_rate,
+        }
+    }
+
+    /// Spawn a task to probe all available Wrappers regularly.
+    pub fn spawn_probe_task(&self,
+                            repository: Arc<dyn Repository>,
+                            reqwest_client: Client,
+                            interval_time_ms: u64)
+                            -> tokio::task::JoinHandle<()> {
+        let estimates = self.estimates.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(interval_time_ms));
+            loop {
+                interval.tick().await;
+                Self::probe_all(&estimates, repository.as_ref(), &reqwest_client).await;
+            }
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn probe_all(estimates: &Arc<scc::HashMap<String, NodeEstimateRecord>>,
+                       repository: &dyn Repository,
+                       reqwest_client: &Client) {
+        let targets = match repository.find_available_wrapper_admin_addresses().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to find Wrappers to probe. msg: {e:?}");
+                return;
+            }
+        };
+
+        let mut join_set = tokio::task::JoinSet::new();
+        for (node_id, admin_address) in targets {
+            let client = IpfsNodeWrapperAdminClient::new_with_reqwest_client(admin_address, reqwest_client.clone());
+            join_set.spawn(async move {
+                let start = Instant::now();
+                let res = tokio::time::timeout(
+                    tokio::time::Duration::from_millis(Self::PROBE_TIMEOUT_MS),
+                    client.get_ipfs_node_info(),
+                ).await;
+                let rtt_ms = match res {
+                    Ok(Ok(_)) => Some(start.elapsed().as_secs_f64() * 1000.0),
+                    _ => None,
+                };
+                (node_id, rtt_ms)
+            });
+        }
+
+        while let Some(join_res) = join_set.join_next().await {
+            let Ok((node_id, rtt_ms)) = join_res else {
+                continue;
+            };
+            let mut entry = estimates.entry_async(node_id.clone()).await.or_default();
+            let record = entry.get_mut();
+            match rtt_ms {
+                Some(rtt_ms) => {
+                    record.rtt_ms = Some(Self::smooth(record.rtt_ms, rtt_ms));
+                    record.consecutive_probe_failures = 0;
+                    trace!("Probe node {node_id} rtt: {rtt_ms} ms");
+                }
+                None => {
+                    record.consecutive_probe_failures += 1;
+                    warn!("Failed to probe node {node_id}. Failed {} times in a row", record.consecutive_probe_failures);
+                }
+            }
+        }
+    }
+
+    fn smooth(old: Option<f64>, sample: f64) -> f64 {
+        match old {
+            Some(old) => old * (1.0 - Self::SMOOTHING_FACTOR) + sample * Self::SMOOTHING_FACTOR,
+            None => sample,
+        }
+    }
+
+    /// Estimated time cost (ms) to download a reference file. Lower is better.
+    ///
+    /// Return `None` if the node has no estimate or is failing probes.
+    fn estimate_cost(record: &NodeEstimateRecord) -> Option<f64> {
+        if record.consecutive_probe_failures > 0 {
+            return None;
+        }
+        let transfer_ms = record.throughput_bytes_per_sec
+            .filter(|v| *v > 0.0)
+            .map(|v| Self::REFERENCE_FILE_SIZE / v * 1000.0);
+        match (record.rtt_ms, transfer_ms) {
+            (None, None) => None,
+            (rtt_ms, transfer_ms) => Some(rtt_ms.unwrap_or(0.0) + transfer_ms.unwrap_or(0.0)),
+        }
+    }
+}
+
+impl Debug for LatencyFileDownloadDecisionMaker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Latency File Download Decision Maker")
+    }
+}
+
+#[async_trait]
+impl FileDownloadDecisionMaker for LatencyFileDownloadDecisionMaker {
+    #[tracing::instrument(skip_all)]
+    async fn decide_download_node(&self,
+                                  cid: &str,
+                                  repository: &dyn Repository,
+                                  _reqwest_client: &Client
+    ) -> ApiResult<TargetPublicWrapperMessage> {
+        let available_nodes = repository.find_nodes_with_pin_cid(cid)
+            .await.map_err(services::db::handle_db_error)?;
+        let available_node_num = available_nodes.len();
+        let (healthy_nodes, other_nodes): (Vec<_>, Vec<_>) = available_nodes.into_iter()
+            .partition(|v| v.node_status == sea_orm_active_enums::NodeStatus::Online);
+
+        if healthy_nodes.is_empty() {
+            // no better choice
+            warn!("No healthy node stores cid {cid}. Choose from {available_node_num} nodes randomly");
+            return choose_random_avoiding_maintenance(other_nodes)
+                .ok_or_else(|| errors::IPFS_NODE_CLUSTER_UNHEALTHY.clone_to_error());
+        }
+
+        // explore
+        if fastrand::f64() < self.exploration_rate {
+            let decide_result = fastrand::choice(healthy_nodes).unwrap();
+            info!("Explore a random healthy node. Result: {decide_result:?}");
+            return Ok(decide_result);
+        }
+
+        let mut best: Option<(f64, TargetPublicWrapperMessage)> = None;
+        let mut unknown_nodes = Vec::new();
+        for node in healthy_nodes {
+            let cost = self.estimates.read_async(&node.id, |_, v| Self::estimate_cost(v)).await.flatten();
+            match (cost, &best) {
+                (Some(cost), Some((best_cost, _))) if cost >= *best_cost => {}
+                (Some(cost), _) => best = Some((cost, node)),
+                (None, _) => unknown_nodes.push(node),
+            }
+        }
+
+        let decide_result = match best {
+            Some((cost, node)) => {
+                debug!("Fastest node cost {cost} ms");
+                node
+            }
+            // no estimate yet
+            None => fastrand::choice(unknown_nodes).unwrap(),
+        };
+        info!("Find {available_node_num} available IPFS nodes. Result: {decide_result:?}");
+        Ok(decide_result)
+    }
+
+    async fn report_download(&self, node_id: &str, size: Option<u64>, duration_ms: u64) -> ApiResult<()> {
+        let Some(size) = size else {
+            return Ok(());
+        };
+        // avoid dividing by zero
+        let throughput = size as f64 / (duration_ms.max(1) as f64 / 1000.0);
+        let mut entry = self.estimates.entry_async(node_id.to_owned()).await.or_default();
+        let record = entry.get_mut();
+        record.throughput_bytes_per_sec = Some(Self::smooth(record.throughput_bytes_per_sec, throughput));
+        debug!("Node {node_id} download throughput sample: {throughput} bytes/s");
+        Ok(())
+    }
+
+    async fn forget_node(&self, node_id: &str) {
+        self.estimates.remove_async(node_id).await;
+    }
+
+    async fn list_node_estimates(&self) -> ApiResult<Vec<NodeDownloadEstimate>> {
+        let mut list = Vec::with_capacity(self.estimates.len());
+        self.estimates.scan_async(|k, v| {
+            list.push(NodeDownloadEstimate {
+                node_id: k.clone(),
+                rtt_ms: v.rtt_ms,
+                throughput_bytes_per_sec: v.throughput_bytes_per_sec,
+                consecutive_probe_failures: v.consecutive_probe_failures,
+            });
+        }).await;
+        Ok(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::memory::MemoryRepository;
+    use crate::test_utils::test_node;
+
+    fn node_model(id: &str, node_status: sea_orm_active_enums::NodeStatus, agent_version: &str) -> node::Model {
+        node::Model {
+            wrapper_public_address: Some(format!("{id}:80")),
+            wrapper_admin_address: Some(format!("{id}:8080")),
+            node_status,
+            agent_version: Some(agent_version.to_owned()),
+            ..test_node(id, &format!("{id}:5001"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_random_storage_avoid_unavailable_nodes() {
+        let repository = MemoryRepository::new();
+        repository.insert_node(node_model("a", sea_orm_active_enums::NodeStatus::Online, "kubo/0.26.0/"));
+        repository.insert_node(node_model("b", sea_orm_active_enums::NodeStatus::Unhealthy, "kubo/0.27.0/"));
+        repository.insert_node(node_model("c", sea_orm_active_enums::NodeStatus::Online, "kubo/0.20.0/"));
+        repository.insert_node(node_model("d", sea_orm_active_enums::NodeStatus::Offline, "kubo/0.27.0/"));
+        repository.insert_node(node_model("e", sea_orm_active_enums::NodeStatus::Maintenance, "kubo/0.27.0/"));
+        let maker = RandomFileStorageDecisionMaker::new()
+            .with_min_kubo_version(Some(KuboVersion(0, 26, 0)));
+        let client = Client::new();
+
+        let decision = maker.decide_store_node("cid", &repository, &client).await.unwrap();
+        let mut decision: Vec<_> = decision.into_iter().map(|v| v.id).collect();
+        decision.sort();
+        assert_eq!(decision, ["a", "b"]);
+
+        // no more available node
+        let retry_decision = maker.decide_store_node_fail_one("cid", &repository, &client).await.unwrap();
+        assert!(retry_decision.is_empty());
+        maker.finish_storage("cid").await.unwrap();
+        assert!(maker.finish_storage("cid").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_random_storage_retry_on_other_nodes() {
+        let repository = MemoryRepository::new();
+        for id in ["a", "b", "c"] {
+            repository.insert_node(node_model(id, sea_orm_active_enums::NodeStatus::Online, "kubo/0.26.0/"));
+        }
+        let maker = RandomFileStorageDecisionMaker::new();
+        let client = Client::new();
+
+        let decision = maker.decide_store_node("cid", &repository, &client).await.unwrap();
+        assert_eq!(decision.len(), 2);
+        let retry_decision = maker.decide_store_node_fail_one("cid", &repository, &client).await.unwrap();
+        assert_eq!(retry_decision.len(), 1);
+        assert!(decision.iter().all(|v| v.id != retry_decision[0].id));
+        // the same cid could not be decided twice in storing
+        assert!(maker.decide_store_node("cid", &repository, &client).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_latency_download_prefer_fastest_healthy_node() {
+        let repository = MemoryRepository::new();
+        repository.insert_node(node_model("fast", sea_orm_active_enums::NodeStatus::Online, "kubo/0.26.0/"));
+        repository.insert_node(node_model("slow", sea_orm_active_enums::NodeStatus::Online, "kubo/0.26.0/"));
+        repository.insert_node(node_model("sick", sea_orm_active_enums::NodeStatus::Unhealthy, "kubo/0.26.0/"));
+        let now = chrono::Utc::now();
+        repository.insert_pin(pin::Model {
+            id: "pin".to_owned(),
+            status: sea_orm_active_enums::Status::Pinned,
+            cid: "cid".to_owned(),
+            created: now,
+            updated: now,
+            size: None,
+            name: None,
+            meta: None,
+            origins: None,
+            expires_at: None,
+        }).await.unwrap();
+        repository.add_replicas("pin", vec!["fast".to_owned(), "slow".to_owned(), "sick".to_owned()]).await.unwrap();
+
+        let maker = LatencyFileDownloadDecisionMaker::new(0.0);
+        maker.report_download("fast", Some(10_000_000), 100).await.unwrap();
+        maker.report_download("slow", Some(10_000_000), 10_000).await.unwrap();
+        let client = Client::new();
+        for _ in 0..10 {
+            let decision = maker.decide_download_node("cid", &repository, &client).await.unwrap();
+            assert_eq!(decision.id, "fast");
+        }
+
+        let res = maker.decide_download_node("unknown cid", &repository, &client).await;
+        assert_eq!(res.unwrap_err(), errors::IPFS_NODE_CLUSTER_UNHEALTHY);
+    }
+}
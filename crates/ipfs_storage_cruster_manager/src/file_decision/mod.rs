@@ -1,8 +1,10 @@
 use std::fmt::Debug;
-use sea_orm::DatabaseConnection;
+use std::str::FromStr;
 use axum::async_trait;
+use serde::Serialize;
 use crate::app::common::ApiResult;
 use crate::imports::dao_imports::*;
+use crate::repository::Repository;
 
 pub mod decision_makers;
 
@@ -17,7 +19,7 @@ pub trait FileStorageDecisionMaker: Send + Sync + Debug {
     /// Returning an empty vec would cause an error (`IPFS_NODE_CLUSTER_UNHEALTHY`).
     async fn decide_store_node(&self,
                                cid: &str,
-                               db_conn: &DatabaseConnection,
+                               repository: &dyn Repository,
                                reqwest_client: &reqwest::Client,
     ) -> ApiResult<Vec<TargetAdminIpfsNodeMessage>>;
 
@@ -27,7 +29,7 @@ pub trait FileStorageDecisionMaker: Send + Sync + Debug {
     /// Could return empty vec.
     async fn decide_store_node_fail_one(&self,
                                         cid: &str,
-                                        db_conn: &DatabaseConnection,
+                                        repository: &dyn Repository,
                                         reqwest_client: &reqwest::Client,
     ) -> ApiResult<Vec<TargetAdminIpfsNodeMessage>>;
 
@@ -43,9 +45,28 @@ pub trait FileDownloadDecisionMaker: Send + Sync + Debug {
     /// Decide which node to download data from.
     async fn decide_download_node(&self,
                                   cid: &str,
-                                  db_conn: &DatabaseConnection,
+                                  repository: &dyn Repository,
                                   reqwest_client: &reqwest::Client,
     ) -> ApiResult<TargetPublicWrapperMessage>;
+
+    /// Receive the feedback of a finished download from the node.
+    ///
+    /// Ignored by default.
+    async fn report_download(&self, _node_id: &str, _size: Option<u64>, _duration_ms: u64) -> ApiResult<()> {
+        Ok(())
+    }
+
+    /// Forget what is known about a node removed from the cluster.
+    ///
+    /// Ignored by default.
+    async fn forget_node(&self, _node_id: &str) {}
+
+    /// List the estimates of nodes used to make decisions.
+    ///
+    /// Return empty vec by default.
+    async fn list_node_estimates(&self) -> ApiResult<Vec<NodeDownloadEstimate>> {
+        Ok(vec![])
+    }
 }
 
 /// Message (admin) about IPFS node to contact.
@@ -57,6 +78,10 @@ pub struct TargetAdminIpfsNodeMessage {
     pub id: String,
     /// RPC address to contact.
     pub rpc_address: String,
+    /// Address of Wrapper (admin) to contact. Used when pin by Wrapper.
+    pub wrapper_admin_address: Option<String>,
+    /// Agent version of IPFS node. Used to check the version.
+    pub agent_version: Option<String>,
 }
 
 /// Message (public) about Wrapper to contact.
@@ -68,4 +93,92 @@ pub struct TargetPublicWrapperMessage {
     pub id: String,
     /// Public address of Wrapper to contact.
     pub wrapper_public_address: String,
+    pub node_status: sea_orm_active_enums::NodeStatus,
+}
+
+/// The estimate of downloading from a node.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeDownloadEstimate {
+    /// Node's id in database.
+    pub node_id: String,
+    /// Rolling round-trip time of probes.
+    pub rtt_ms: Option<f64>,
+    /// Rolling throughput of observed downloads.
+    pub throughput_bytes_per_sec: Option<f64>,
+    /// The number of failed probes in a row.
+    pub consecutive_probe_failures: u32,
+}
+
+/// Choose a node randomly. Nodes in `Maintenance` are only chosen when no other node exists.
+pub fn choose_random_avoiding_maintenance(nodes: Vec<TargetPublicWrapperMessage>) -> Option<TargetPublicWrapperMessage> {
+    let (maintenance_nodes, other_nodes): (Vec<_>, Vec<_>) = nodes.into_iter()
+        .partition(|v| v.node_status == sea_orm_active_enums::NodeStatus::Maintenance);
+    fastrand::choice(other_nodes)
+        .or_else(|| fastrand::choice(maintenance_nodes))
+}
+
+/// Version of Kubo, like `0.26.0`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KuboVersion(pub u64, pub u64, pub u64);
+
+impl KuboVersion {
+    /// Parse from agent version of IPFS node, like `kubo/0.26.0/` or `go-ipfs/0.12.0/abcdef`.
+    ///
+    /// Return `None` if the agent is not Kubo (or go-ipfs).
+    pub fn from_agent_version(agent_version: &str) -> Option<Self> {
+        let mut parts = agent_version.split('/');
+        match parts.next()? {
+            "kubo" | "go-ipfs" => parts.next()?.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for KuboVersion {
+    type Err = ();
+
+    /// Parse from `0.26.0`, `v0.26.0` or `0.26.0-rc1`. The pre-release part is ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix('v').unwrap_or(s);
+        let s = s.split('-').next().ok_or(())?;
+        let mut numbers = s.split('.').map(|v| v.parse::<u64>());
+        let mut next_number = || numbers.next().ok_or(())?.map_err(|_| ());
+        let version = KuboVersion(next_number()?, next_number()?, next_number()?);
+        Ok(version)
+    }
+}
+
+/// Keep the nodes whose Kubo version is not less than `min_version`.
+///
+/// Nodes with unknown version are excluded when `min_version` is set.
+pub fn filter_nodes_by_min_kubo_version(nodes: Vec<TargetAdminIpfsNodeMessage>,
+                                        min_version: Option<KuboVersion>)
+                                        -> Vec<TargetAdminIpfsNodeMessage> {
+    let Some(min_version) = min_version else {
+        return nodes;
+    };
+    nodes.into_iter()
+        .filter(|v| {
+            v.agent_version.as_deref()
+                .and_then(KuboVersion::from_agent_version)
+                .is_some_and(|version| version >= min_version)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kubo_version() {
+        assert_eq!(KuboVersion::from_agent_version("kubo/0.26.0/"), Some(KuboVersion(0, 26, 0)));
+        assert_eq!(KuboVersion::from_agent_version("kubo/0.27.0-rc1/abcdef"), Some(KuboVersion(0, 27, 0)));
+        assert_eq!(KuboVersion::from_agent_version("go-ipfs/0.12.2/"), Some(KuboVersion(0, 12, 2)));
+        assert_eq!(KuboVersion::from_agent_version("rust-ipfs/0.1.0/"), None);
+        assert_eq!("v0.26.1".parse(), Ok(KuboVersion(0, 26, 1)));
+        assert!("0.26".parse::<KuboVersion>().is_err());
+        assert!(KuboVersion(0, 26, 0) > KuboVersion(0, 9, 1));
+    }
 }
@@ -1,5 +1,8 @@
 pub mod app;
 pub mod app_builder;
 pub mod file_decision;
+pub mod repository;
 mod utils;
-mod imports;
\ No newline at end of file
+mod imports;
+#[cfg(test)]
+mod test_utils;
\ No newline at end of file
@@ -0,0 +1,406 @@
+//! Repository in memory. Used to test without database.
+
+use std::collections::BTreeMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use axum::async_trait;
+use crate::app::services::db::DbResult;
+use crate::file_decision::{TargetAdminIpfsNodeMessage, TargetPublicWrapperMessage};
+use crate::imports::dao_imports::*;
+use crate::repository::{NewReplication, NodeFilter, Repository, extended_expires_at};
+
+/// Keep nodes, pins, replicas, replication intents and their pin requests in memory. Tables are ordered by id.
+#[derive(Debug, Default)]
+pub struct MemoryRepository {
+    tables: RwLock<Tables>,
+}
+
+#[derive(Debug, Default)]
+struct Tables {
+    nodes: BTreeMap<String, node::Model>,
+    pins: BTreeMap<String, pin::Model>,
+    replicas: BTreeMap<String, pins_stored_nodes::Model>,
+    replications: BTreeMap<i64, replication_outbox::Model>,
+    last_replication_id: i64,
+    requests: BTreeMap<String, users_pins::Model>,
+}
+
+impl Tables {
+    fn insert_replicas(&mut self, pin_id: &str, node_ids: Vec<String>) {
+        for node_id in node_ids {
+            if self.replicas.values().any(|v| v.pin_id == pin_id && v.node_id == node_id) {
+                continue;
+            }
+            let id = Uuid::new_v4().to_string();
+            self.replicas.insert(id.clone(), pins_stored_nodes::Model {
+                id,
+                pin_id: pin_id.to_owned(),
+                node_id,
+            });
+        }
+    }
+
+    fn set_pin_status(&mut self, pin_id: &str, status: sea_orm_active_enums::Status) {
+        if let Some(pin_model) = self.pins.get_mut(pin_id) {
+            pin_model.status = status;
+            pin_model.updated = chrono::Utc::now();
+        }
+    }
+
+    fn insert_replication(&mut self, pin_id: &str, intent: NewReplication) -> replication_outbox::Model {
+        if let Some(request) = intent.request {
+            let request = users_pins::Model { pin_id: pin_id.to_owned(), ..request };
+            self.requests.insert(request.id.clone(), request);
+        }
+        self.last_replication_id += 1;
+        let model = replication_outbox::Model {
+            id: self.last_replication_id,
+            pin_id: pin_id.to_owned(),
+            origins: Some(serde_json::json!(intent.origins)),
+            target_node_ids: None,
+            attempts: 1,
+            available_at: intent.claimed_until,
+            last_error: None,
+            created: chrono::Utc::now(),
+        };
+        self.replications.insert(model.id, model.clone());
+        model
+    }
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert or replace a node.
+    pub fn insert_node(&self, node_model: node::Model) {
+        self.write().nodes.insert(node_model.id.clone(), node_model);
+    }
+
+    pub fn find_replication(&self, intent_id: i64) -> Option<replication_outbox::Model> {
+        self.read().replications.get(&intent_id).cloned()
+    }
+
+    /// Ids of the nodes that store the pin.
+    pub fn find_replica_node_ids(&self, pin_id: &str) -> Vec<String> {
+        self.read().replicas.values()
+            .filter(|v| v.pin_id == pin_id)
+            .map(|v| v.node_id.clone())
+            .collect()
+    }
+
+    // A panic while holding the lock never leaves tables half updated, so poisoning is ignored.
+    fn read(&self) -> RwLockReadGuard<'_, Tables> {
+        self.tables.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Tables> {
+        self.tables.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl Repository for MemoryRepository {
+    async fn find_nodes(&self, filter: &NodeFilter) -> DbResult<Vec<node::Model>> {
+        let res = self.read().nodes.values()
+            .filter(|v| filter.matches(v))
+            .cloned()
+            .collect();
+        Ok(res)
+    }
+
+    async fn find_admin_nodes(&self, filter: &NodeFilter) -> DbResult<Vec<TargetAdminIpfsNodeMessage>> {
+        let res = self.read().nodes.values()
+            .filter(|v| filter.matches(v))
+            .map(|v| TargetAdminIpfsNodeMessage {
+                id: v.id.clone(),
+                rpc_address: v.rpc_address.clone(),
+                wrapper_admin_address: v.wrapper_admin_address.clone(),
+                agent_version: v.agent_version.clone(),
+            })
+            .collect();
+        Ok(res)
+    }
+
+    /// Nodes without public address are skipped.
+    async fn find_nodes_with_pin_cid(&self, cid: &str) -> DbResult<Vec<TargetPublicWrapperMessage>> {
+        let tables = self.read();
+        let Some(pin_model) = tables.pins.values().find(|v| v.cid == cid) else {
+            return Ok(vec![]);
+        };
+        let res = tables.replicas.values()
+            .filter(|v| v.pin_id == pin_model.id)
+            .filter_map(|v| tables.nodes.get(&v.node_id))
+            .filter_map(|v| Some(TargetPublicWrapperMessage {
+                id: v.id.clone(),
+                wrapper_public_address: v.wrapper_public_address.clone()?,
+                node_status: v.node_status.clone(),
+            }))
+            .collect();
+        Ok(res)
+    }
+
+    async fn find_available_wrapper_admin_addresses(&self) -> DbResult<Vec<(String, String)>> {
+        let res = self.read().nodes.values()
+            .filter(|v| v.node_status != sea_orm_active_enums::NodeStatus::Offline)
+            .filter_map(|v| v.wrapper_admin_address.clone().map(|addr| (v.id.clone(), addr)))
+            .collect();
+        Ok(res)
+    }
+
+    async fn find_pin_by_id(&self, pin_id: &str) -> DbResult<Option<pin::Model>> {
+        Ok(self.read().pins.get(pin_id).cloned())
+    }
+
+    async fn find_pin_by_cid(&self, cid: &str) -> DbResult<Option<pin::Model>> {
+        let res = self.read().pins.values()
+            .find(|v| v.cid == cid)
+            .cloned();
+        Ok(res)
+    }
+
+    async fn insert_pin(&self, pin_model: pin::Model) -> DbResult<bool> {
+        let mut tables = self.write();
+        if tables.pins.contains_key(&pin_model.id) || tables.pins.values().any(|v| v.cid == pin_model.cid) {
+            return Ok(false);
+        }
+        tables.pins.insert(pin_model.id.clone(), pin_model);
+        Ok(true)
+    }
+
+    async fn update_pin(&self, pin_model: pin::Model) -> DbResult<pin::Model> {
+        let mut tables = self.write();
+        let Some(old) = tables.pins.get_mut(&pin_model.id) else {
+            return Err(DbErr::RecordNotUpdated);
+        };
+        *old = pin_model.clone();
+        Ok(pin_model)
+    }
+
+    async fn update_pin_status(&self, pin_id: &str, status: sea_orm_active_enums::Status) -> DbResult<()> {
+        self.write().set_pin_status(pin_id, status);
+        Ok(())
+    }
+
+    async fn keep_pin_until(&self, pin_id: &str, expires_at: Option<DateTimeUtc>) -> DbResult<bool> {
+        let mut tables = self.write();
+        let Some(pin_model) = tables.pins.get_mut(pin_id) else {
+            return Ok(false);
+        };
+        if pin_model.status == sea_orm_active_enums::Status::Deleted {
+            return Ok(false);
+        }
+        if let Some(new_expires_at) = extended_expires_at(pin_model.expires_at, expires_at) {
+            pin_model.expires_at = new_expires_at;
+            pin_model.updated = chrono::Utc::now();
+        }
+        Ok(true)
+    }
+
+    async fn find_expired_pins(&self, now: DateTimeUtc, limit: u64) -> DbResult<Vec<pin::Model>> {
+        let mut res: Vec<_> = self.read().pins.values()
+            .filter(|v| v.expires_at.is_some_and(|t| t <= now))
+            .filter(|v| v.status != sea_orm_active_enums::Status::Deleted)
+            .cloned()
+            .collect();
+        res.sort_by_key(|v| v.expires_at);
+        res.truncate(limit as usize);
+        Ok(res)
+    }
+
+    async fn claim_expired_pin(&self, pin_id: &str, now: DateTimeUtc) -> DbResult<bool> {
+        let mut tables = self.write();
+        let Some(pin_model) = tables.pins.get(pin_id) else {
+            return Ok(false);
+        };
+        if pin_model.expires_at.is_none_or(|t| t > now) || pin_model.status == sea_orm_active_enums::Status::Deleted {
+            return Ok(false);
+        }
+        tables.set_pin_status(pin_id, sea_orm_active_enums::Status::Deleted);
+        Ok(true)
+    }
+
+    async fn find_deleted_pins_with_replicas(&self, now: DateTimeUtc, limit: u64) -> DbResult<Vec<pin::Model>> {
+        let tables = self.read();
+        let res = tables.pins.values()
+            .filter(|v| v.expires_at.is_some_and(|t| t <= now))
+            .filter(|v| v.status == sea_orm_active_enums::Status::Deleted)
+            .filter(|v| tables.replicas.values().any(|r| r.pin_id == v.id))
+            .take(limit as usize)
+            .cloned()
+            .collect();
+        Ok(res)
+    }
+
+    async fn add_replicas(&self, pin_id: &str, node_ids: Vec<String>) -> DbResult<()> {
+        self.write().insert_replicas(pin_id, node_ids);
+        Ok(())
+    }
+
+    async fn find_replicas(&self, pin_id: &str) -> DbResult<Vec<(pins_stored_nodes::Model, Option<node::Model>)>> {
+        let tables = self.read();
+        let res = tables.replicas.values()
+            .filter(|v| v.pin_id == pin_id)
+            .map(|v| (v.clone(), tables.nodes.get(&v.node_id).cloned()))
+            .collect();
+        Ok(res)
+    }
+
+    async fn remove_replicas(&self, pin_id: &str, node_ids: Vec<String>) -> DbResult<()> {
+        self.write().replicas
+            .retain(|_, v| v.pin_id != pin_id || !node_ids.contains(&v.node_id));
+        Ok(())
+    }
+
+    async fn insert_pin_request(&self, request: users_pins::Model) -> DbResult<users_pins::Model> {
+        let mut tables = self.write();
+        if tables.requests.contains_key(&request.id) {
+            return Err(DbErr::RecordNotInserted);
+        }
+        tables.requests.insert(request.id.clone(), request.clone());
+        Ok(request)
+    }
+
+    async fn has_pin_request_of_cid(&self, user_id: &str, cid: &str) -> DbResult<bool> {
+        let tables = self.read();
+        let res = tables.requests.values()
+            .filter(|v| v.user_id == user_id)
+            .filter_map(|v| tables.pins.get(&v.pin_id))
+            .any(|v| v.cid == cid);
+        Ok(res)
+    }
+
+    async fn find_requested_pins(&self, user_id: &str) -> DbResult<Vec<pin::Model>> {
+        let tables = self.read();
+        let pin_ids: std::collections::BTreeSet<_> = tables.requests.values()
+            .filter(|v| v.user_id == user_id)
+            .map(|v| &v.pin_id)
+            .collect();
+        let res = pin_ids.into_iter()
+            .filter_map(|v| tables.pins.get(v))
+            .filter(|v| v.status != sea_orm_active_enums::Status::Deleted)
+            .cloned()
+            .collect();
+        Ok(res)
+    }
+
+    async fn insert_pin_to_replicate(&self, pin_model: pin::Model, intent: NewReplication) -> DbResult<Option<replication_outbox::Model>> {
+        let mut tables = self.write();
+        if tables.pins.contains_key(&pin_model.id) || tables.pins.values().any(|v| v.cid == pin_model.cid) {
+            return Ok(None);
+        }
+        let intent = tables.insert_replication(&pin_model.id, intent);
+        tables.pins.insert(pin_model.id.clone(), pin_model);
+        Ok(Some(intent))
+    }
+
+    async fn update_pin_to_replicate(&self, pin_model: pin::Model, intent: NewReplication) -> DbResult<replication_outbox::Model> {
+        let mut tables = self.write();
+        let Some(old) = tables.pins.get_mut(&pin_model.id) else {
+            return Err(DbErr::RecordNotUpdated);
+        };
+        *old = pin_model.clone();
+        Ok(tables.insert_replication(&pin_model.id, intent))
+    }
+
+    async fn find_available_replications(&self, limit: u64) -> DbResult<Vec<replication_outbox::Model>> {
+        let now = chrono::Utc::now();
+        let res = self.read().replications.values()
+            .filter(|v| v.available_at <= now)
+            .take(limit as usize)
+            .cloned()
+            .collect();
+        Ok(res)
+    }
+
+    async fn claim_replication(&self, intent_id: i64, claimed_until: DateTimeUtc) -> DbResult<bool> {
+        let mut tables = self.write();
+        match tables.replications.get_mut(&intent_id) {
+            Some(intent) if intent.available_at <= chrono::Utc::now() => {
+                intent.available_at = claimed_until;
+                intent.attempts += 1;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn record_replication_targets(&self, intent_id: i64, node_ids: Vec<String>) -> DbResult<()> {
+        if let Some(intent) = self.write().replications.get_mut(&intent_id) {
+            intent.target_node_ids = Some(serde_json::json!(node_ids));
+        }
+        Ok(())
+    }
+
+    async fn delay_replication(&self, intent_id: i64, available_at: DateTimeUtc, last_error: String) -> DbResult<()> {
+        if let Some(intent) = self.write().replications.get_mut(&intent_id) {
+            intent.available_at = available_at;
+            intent.last_error = Some(last_error);
+        }
+        Ok(())
+    }
+
+    async fn finish_replication(&self, intent: &replication_outbox::Model, node_ids: Vec<String>, size: Option<i64>) -> DbResult<()> {
+        let mut tables = self.write();
+        tables.insert_replicas(&intent.pin_id, node_ids);
+        tables.set_pin_status(&intent.pin_id, sea_orm_active_enums::Status::Pinned);
+        if let (Some(pin_model), Some(size)) = (tables.pins.get_mut(&intent.pin_id), size) {
+            pin_model.size = Some(size);
+        }
+        tables.replications.remove(&intent.id);
+        Ok(())
+    }
+
+    async fn abandon_replication(&self, intent: &replication_outbox::Model) -> DbResult<()> {
+        let mut tables = self.write();
+        tables.set_pin_status(&intent.pin_id, sea_orm_active_enums::Status::Failed);
+        tables.replications.remove(&intent.id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::daos;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn test_memory_repository_expiry_and_requests() {
+        let repository = MemoryRepository::new();
+        let now = chrono::Utc::now();
+        for (id, expires_in) in [("expired", -10), ("kept", 3600)] {
+            repository.insert_pin(pin::Model {
+                expires_at: Some(now + chrono::Duration::seconds(expires_in)),
+                size: Some(10),
+                ..test_pin(id, &format!("cid {id}"))
+            }).await.unwrap();
+            repository.add_replicas(id, vec!["a".to_string(), "b".to_string()]).await.unwrap();
+        }
+        let expired = repository.find_expired_pins(now, 10).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        assert!(repository.claim_expired_pin("expired", now).await.unwrap());
+        assert!(!repository.claim_expired_pin("expired", now).await.unwrap());
+        assert!(!repository.claim_expired_pin("kept", now).await.unwrap());
+        assert!(repository.find_expired_pins(now, 10).await.unwrap().is_empty());
+
+        // replicas failed to unpin are retried
+        repository.remove_replicas("expired", vec!["a".to_string()]).await.unwrap();
+        let retried = repository.find_deleted_pins_with_replicas(now, 10).await.unwrap();
+        assert_eq!(retried[0].id, "expired");
+        let replicas = repository.find_replicas("expired").await.unwrap();
+        assert_eq!(replicas.len(), 1);
+        assert_eq!(replicas[0].0.node_id, "b");
+        assert!(replicas[0].1.is_none());
+
+        // deleted pins are not requested any more
+        for pin_id in ["expired", "kept", "kept"] {
+            repository.insert_pin_request(daos::new_pin_request("alice", pin_id, None, None, None)).await.unwrap();
+        }
+        assert!(repository.has_pin_request_of_cid("alice", "cid kept").await.unwrap());
+        assert!(!repository.has_pin_request_of_cid("bob", "cid kept").await.unwrap());
+        let pins = repository.find_requested_pins("alice").await.unwrap();
+        assert_eq!(pins.len(), 1);
+        assert_eq!(pins[0].id, "kept");
+    }
+}
@@ -0,0 +1,159 @@
+//! Access to nodes, pins, replicas (`pins_stored_nodes`) and pin requests (`users_pins`), regardless of where they are stored.
+//!
+//! Decision makers, background tasks and handlers of files depend on `Repository` instead of a database connection,
+//! so they could be tested with `memory::MemoryRepository`.
+//! Users, their tokens and quotas, the audit log and the queries of pin service still use the database directly.
+//!
+//! Methods changing several rows are atomic, i.e. run in a transaction of database.
+
+use std::fmt::Debug;
+use axum::async_trait;
+use crate::app::services::db::DbResult;
+use crate::file_decision::{TargetAdminIpfsNodeMessage, TargetPublicWrapperMessage};
+use crate::imports::dao_imports::*;
+
+pub mod seaorm;
+pub mod memory;
+
+/// Conditions to find nodes. Default to all nodes.
+#[derive(Debug, Clone, Default)]
+pub struct NodeFilter {
+    /// Only nodes with these ids. Empty for any id.
+    pub id_in: Vec<String>,
+    /// Only nodes with these status. Empty for any status.
+    pub status_in: Vec<sea_orm_active_enums::NodeStatus>,
+    /// Exclude nodes with these status.
+    pub status_not_in: Vec<sea_orm_active_enums::NodeStatus>,
+    /// Exclude nodes with these ids.
+    pub id_not_in: Vec<String>,
+}
+
+impl NodeFilter {
+    /// Check a node in memory.
+    pub fn matches(&self, node_model: &node::Model) -> bool {
+        (self.id_in.is_empty() || self.id_in.contains(&node_model.id))
+            && (self.status_in.is_empty() || self.status_in.contains(&node_model.node_status))
+            && !self.status_not_in.contains(&node_model.node_status)
+            && !self.id_not_in.contains(&node_model.id)
+    }
+}
+
+/// Storage of nodes, pins and replicas.
+#[async_trait]
+pub trait Repository: Send + Sync + Debug {
+    /// Find nodes with all columns.
+    async fn find_nodes(&self, filter: &NodeFilter) -> DbResult<Vec<node::Model>>;
+
+    /// Find nodes to contact by admin APIs.
+    async fn find_admin_nodes(&self, filter: &NodeFilter) -> DbResult<Vec<TargetAdminIpfsNodeMessage>>;
+
+    /// Find all nodes that store the pin with certain CID.
+    async fn find_nodes_with_pin_cid(&self, cid: &str) -> DbResult<Vec<TargetPublicWrapperMessage>>;
+
+    /// Find the admin addresses of Wrappers whose node is not `Offline`.
+    ///
+    /// Return `(node_id, wrapper_admin_address)`. Nodes without admin address are skipped.
+    async fn find_available_wrapper_admin_addresses(&self) -> DbResult<Vec<(String, String)>>;
+
+    async fn find_pin_by_id(&self, pin_id: &str) -> DbResult<Option<pin::Model>>;
+
+    async fn find_pin_by_cid(&self, cid: &str) -> DbResult<Option<pin::Model>>;
+
+    /// Insert a new pin.
+    ///
+    /// Return false if a pin with the same CID exists.
+    async fn insert_pin(&self, pin_model: pin::Model) -> DbResult<bool>;
+
+    /// Overwrite all columns of an existing pin.
+    async fn update_pin(&self, pin_model: pin::Model) -> DbResult<pin::Model>;
+
+    /// Set the status of a pin, and update its updated time.
+    async fn update_pin_status(&self, pin_id: &str, status: sea_orm_active_enums::Status) -> DbResult<()>;
+
+    /// Keep the pin at least until `expires_at`, which is `None` for forever.
+    ///
+    /// The expiry time is never brought forward, because the pin might be shared.
+    /// It's compared with the current one in storage, not the one read before.
+    ///
+    /// Return false if the pin is `Deleted` (e.g. claimed by the expiry reaper), in which case nothing is changed.
+    async fn keep_pin_until(&self, pin_id: &str, expires_at: Option<DateTimeUtc>) -> DbResult<bool>;
+
+    /// Find pins expired at `now` that are not `Deleted`, the earliest first.
+    async fn find_expired_pins(&self, now: DateTimeUtc, limit: u64) -> DbResult<Vec<pin::Model>>;
+
+    /// Mark the pin `Deleted` if it's still expired at `now` and not `Deleted`.
+    ///
+    /// Return false if it's changed by others, e.g. extended by an upload.
+    async fn claim_expired_pin(&self, pin_id: &str, now: DateTimeUtc) -> DbResult<bool>;
+
+    /// Find `Deleted` pins expired at `now` that still have replicas, i.e. failed to be unpinned from some nodes.
+    async fn find_deleted_pins_with_replicas(&self, now: DateTimeUtc, limit: u64) -> DbResult<Vec<pin::Model>>;
+
+    /// Record that the pin is stored in these nodes. Existing replicas are kept.
+    async fn add_replicas(&self, pin_id: &str, node_ids: Vec<String>) -> DbResult<()>;
+
+    /// Find the replicas of the pin with their nodes. The node is `None` if it has been removed.
+    async fn find_replicas(&self, pin_id: &str) -> DbResult<Vec<(pins_stored_nodes::Model, Option<node::Model>)>>;
+
+    /// Remove the replicas of the pin in these nodes.
+    async fn remove_replicas(&self, pin_id: &str, node_ids: Vec<String>) -> DbResult<()>;
+
+    /// Insert a pin request of a user.
+    async fn insert_pin_request(&self, request: users_pins::Model) -> DbResult<users_pins::Model>;
+
+    /// Check whether the user has a pin request of the CID.
+    async fn has_pin_request_of_cid(&self, user_id: &str, cid: &str) -> DbResult<bool>;
+
+    /// Find the pins requested by the user, except `Deleted` ones. A pin is returned once even if it's requested many times.
+    async fn find_requested_pins(&self, user_id: &str) -> DbResult<Vec<pin::Model>>;
+
+    /// Insert a new pin with an intent to replicate it, and the pin request of the intent if any.
+    ///
+    /// Return `None` if a pin with the same CID exists.
+    async fn insert_pin_to_replicate(&self, pin_model: pin::Model, intent: NewReplication) -> DbResult<Option<replication_outbox::Model>>;
+
+    /// Overwrite all columns of an existing pin, with an intent to replicate it and the pin request of the intent if any.
+    async fn update_pin_to_replicate(&self, pin_model: pin::Model, intent: NewReplication) -> DbResult<replication_outbox::Model>;
+
+    /// Find replication intents available now, in the order of creation.
+    async fn find_available_replications(&self, limit: u64) -> DbResult<Vec<replication_outbox::Model>>;
+
+    /// Claim an available replication intent until `claimed_until`, and count an attempt.
+    ///
+    /// Return false if it's claimed by others or finished.
+    async fn claim_replication(&self, intent_id: i64, claimed_until: DateTimeUtc) -> DbResult<bool>;
+
+    /// Record the nodes tried to store the pin of a replication intent.
+    async fn record_replication_targets(&self, intent_id: i64, node_ids: Vec<String>) -> DbResult<()>;
+
+    /// Make the replication intent available again at `available_at` after a failed attempt.
+    async fn delay_replication(&self, intent_id: i64, available_at: DateTimeUtc, last_error: String) -> DbResult<()>;
+
+    /// Record replicas, set the pin `Pinned`, and remove the replication intent.
+    ///
+    /// The size of pin is updated if it's `Some`.
+    async fn finish_replication(&self, intent: &replication_outbox::Model, node_ids: Vec<String>, size: Option<i64>) -> DbResult<()>;
+
+    /// Set the pin `Failed`, and remove the replication intent.
+    async fn abandon_replication(&self, intent: &replication_outbox::Model) -> DbResult<()>;
+}
+
+/// A new intent to replicate a pin.
+#[derive(Debug, Clone)]
+pub struct NewReplication {
+    /// Multi addresses which have the data. Connected before replicating.
+    pub origins: Vec<String>,
+    /// The intent is claimed by its creator until this time, which counts as the first attempt.
+    pub claimed_until: DateTimeUtc,
+    /// The pin request of a user, inserted with the intent. Its `pin_id` is set to the pin's.
+    pub request: Option<users_pins::Model>,
+}
+
+/// The new expiry time by `Repository::keep_pin_until`. `None` if no need to update.
+pub(crate) fn extended_expires_at(old: Option<DateTimeUtc>, new: Option<DateTimeUtc>) -> Option<Option<DateTimeUtc>> {
+    match (old, new) {
+        (Some(old), Some(new)) if new > old => Some(Some(new)),
+        (Some(_), None) => Some(None),
+        _ => None,
+    }
+}
@@ -0,0 +1,391 @@
+//! Repository on database by SeaORM.
+
+use axum::async_trait;
+use crate::app::daos;
+use crate::app::services::db::{self, DbResult};
+use crate::file_decision::{TargetAdminIpfsNodeMessage, TargetPublicWrapperMessage};
+use crate::imports::dao_imports::*;
+use crate::repository::{NewReplication, NodeFilter, Repository};
+
+#[derive(Debug, Clone)]
+pub struct SeaOrmRepository {
+    db_conn: DatabaseConnection,
+}
+
+impl SeaOrmRepository {
+    pub fn new(db_conn: DatabaseConnection) -> Self {
+        SeaOrmRepository { db_conn }
+    }
+}
+
+#[async_trait]
+impl Repository for SeaOrmRepository {
+    async fn find_nodes(&self, filter: &NodeFilter) -> DbResult<Vec<node::Model>> {
+        find_nodes_by_filter(filter)
+            .all(&self.db_conn).await
+    }
+
+    async fn find_admin_nodes(&self, filter: &NodeFilter) -> DbResult<Vec<TargetAdminIpfsNodeMessage>> {
+        find_nodes_by_filter(filter)
+            .into_partial_model::<TargetAdminIpfsNodeMessage>()
+            .all(&self.db_conn).await
+    }
+
+    async fn find_nodes_with_pin_cid(&self, cid: &str) -> DbResult<Vec<TargetPublicWrapperMessage>> {
+        daos::find_nodes_with_pin_cid(cid, &self.db_conn).await
+    }
+
+    async fn find_available_wrapper_admin_addresses(&self) -> DbResult<Vec<(String, String)>> {
+        daos::find_available_wrapper_admin_addresses(&self.db_conn).await
+    }
+
+    async fn find_pin_by_id(&self, pin_id: &str) -> DbResult<Option<pin::Model>> {
+        Pin::find_by_id(pin_id)
+            .one(&self.db_conn).await
+    }
+
+    async fn find_pin_by_cid(&self, cid: &str) -> DbResult<Option<pin::Model>> {
+        Pin::find()
+            .filter(pin::Column::Cid.eq(cid))
+            .one(&self.db_conn).await
+    }
+
+    async fn insert_pin(&self, pin_model: pin::Model) -> DbResult<bool> {
+        let res = Pin::insert(pin_model.into_active_model())
+            .exec_without_returning(&self.db_conn).await;
+        match res {
+            Ok(_) => Ok(true),
+            Err(e) => db::check_duplicate_key_error(e).map(|_| false),
+        }
+    }
+
+    async fn update_pin(&self, pin_model: pin::Model) -> DbResult<pin::Model> {
+        pin_model.into_active_model()
+            .reset_all()
+            .update(&self.db_conn).await
+    }
+
+    async fn update_pin_status(&self, pin_id: &str, status: sea_orm_active_enums::Status) -> DbResult<()> {
+        daos::update_pin_status(pin_id, status, &self.db_conn).await
+    }
+
+    async fn keep_pin_until(&self, pin_id: &str, expires_at: Option<DateTimeUtc>) -> DbResult<bool> {
+        daos::keep_pin_until(pin_id, expires_at, &self.db_conn).await
+    }
+
+    async fn find_expired_pins(&self, now: DateTimeUtc, limit: u64) -> DbResult<Vec<pin::Model>> {
+        Pin::find()
+            .filter(pin::Column::ExpiresAt.lte(now))
+            .filter(pin::Column::Status.ne(sea_orm_active_enums::Status::Deleted))
+            .order_by_asc(pin::Column::ExpiresAt)
+            .limit(limit)
+            .all(&self.db_conn).await
+    }
+
+    async fn claim_expired_pin(&self, pin_id: &str, now: DateTimeUtc) -> DbResult<bool> {
+        daos::claim_expired_pin(pin_id, now, &self.db_conn).await
+    }
+
+    async fn find_deleted_pins_with_replicas(&self, now: DateTimeUtc, limit: u64) -> DbResult<Vec<pin::Model>> {
+        Pin::find()
+            .inner_join(PinsStoredNodes)
+            .filter(pin::Column::ExpiresAt.lte(now))
+            .filter(pin::Column::Status.eq(sea_orm_active_enums::Status::Deleted))
+            .distinct()
+            .limit(limit)
+            .all(&self.db_conn).await
+    }
+
+    async fn add_replicas(&self, pin_id: &str, node_ids: Vec<String>) -> DbResult<()> {
+        insert_replicas(pin_id, node_ids, &self.db_conn).await
+    }
+
+    async fn find_replicas(&self, pin_id: &str) -> DbResult<Vec<(pins_stored_nodes::Model, Option<node::Model>)>> {
+        PinsStoredNodes::find()
+            .filter(pins_stored_nodes::Column::PinId.eq(pin_id))
+            .find_also_related(Node)
+            .all(&self.db_conn).await
+    }
+
+    async fn remove_replicas(&self, pin_id: &str, node_ids: Vec<String>) -> DbResult<()> {
+        if node_ids.is_empty() {
+            return Ok(());
+        }
+        PinsStoredNodes::delete_many()
+            .filter(pins_stored_nodes::Column::PinId.eq(pin_id))
+            .filter(pins_stored_nodes::Column::NodeId.is_in(node_ids))
+            .exec(&self.db_conn).await?;
+        Ok(())
+    }
+
+    async fn insert_pin_request(&self, request: users_pins::Model) -> DbResult<users_pins::Model> {
+        request.into_active_model()
+            .insert(&self.db_conn).await
+    }
+
+    async fn has_pin_request_of_cid(&self, user_id: &str, cid: &str) -> DbResult<bool> {
+        daos::has_pin_request_of_cid(user_id, cid, &self.db_conn).await
+    }
+
+    async fn find_requested_pins(&self, user_id: &str) -> DbResult<Vec<pin::Model>> {
+        Pin::find()
+            .inner_join(UsersPins)
+            .filter(users_pins::Column::UserId.eq(user_id))
+            .filter(pin::Column::Status.ne(sea_orm_active_enums::Status::Deleted))
+            .distinct()
+            .all(&self.db_conn).await
+    }
+
+    async fn insert_pin_to_replicate(&self, pin_model: pin::Model, intent: NewReplication) -> DbResult<Option<replication_outbox::Model>> {
+        let txn = self.db_conn.begin().await?;
+        let pin_id = pin_model.id.clone();
+        let res = Pin::insert(pin_model.into_active_model())
+            .exec_without_returning(&txn).await;
+        if let Err(e) = res {
+            // rollback on drop
+            db::check_duplicate_key_error(e)?;
+            return Ok(None);
+        }
+        insert_request(&pin_id, intent.request.clone(), &txn).await?;
+        let intent = new_intent(&pin_id, intent)
+            .insert(&txn).await?;
+        txn.commit().await?;
+        Ok(Some(intent))
+    }
+
+    async fn update_pin_to_replicate(&self, pin_model: pin::Model, intent: NewReplication) -> DbResult<replication_outbox::Model> {
+        let txn = self.db_conn.begin().await?;
+        let pin_model = pin_model.into_active_model()
+            .reset_all()
+            .update(&txn).await?;
+        insert_request(&pin_model.id, intent.request.clone(), &txn).await?;
+        let intent = new_intent(&pin_model.id, intent)
+            .insert(&txn).await?;
+        txn.commit().await?;
+        Ok(intent)
+    }
+
+    async fn find_available_replications(&self, limit: u64) -> DbResult<Vec<replication_outbox::Model>> {
+        ReplicationOutbox::find()
+            .filter(replication_outbox::Column::AvailableAt.lte(chrono::Utc::now()))
+            .order_by_asc(replication_outbox::Column::Id)
+            .limit(limit)
+            .all(&self.db_conn).await
+    }
+
+    async fn claim_replication(&self, intent_id: i64, claimed_until: DateTimeUtc) -> DbResult<bool> {
+        let res = ReplicationOutbox::update_many()
+            .col_expr(replication_outbox::Column::AvailableAt, Expr::value(claimed_until))
+            .col_expr(replication_outbox::Column::Attempts, Expr::col(replication_outbox::Column::Attempts).add(1))
+            .filter(replication_outbox::Column::Id.eq(intent_id))
+            .filter(replication_outbox::Column::AvailableAt.lte(chrono::Utc::now()))
+            .exec(&self.db_conn).await?;
+        Ok(res.rows_affected > 0)
+    }
+
+    async fn record_replication_targets(&self, intent_id: i64, node_ids: Vec<String>) -> DbResult<()> {
+        ReplicationOutbox::update_many()
+            .col_expr(replication_outbox::Column::TargetNodeIds, Expr::value(serde_json::json!(node_ids)))
+            .filter(replication_outbox::Column::Id.eq(intent_id))
+            .exec(&self.db_conn).await?;
+        Ok(())
+    }
+
+    async fn delay_replication(&self, intent_id: i64, available_at: DateTimeUtc, last_error: String) -> DbResult<()> {
+        ReplicationOutbox::update_many()
+            .col_expr(replication_outbox::Column::AvailableAt, Expr::value(available_at))
+            .col_expr(replication_outbox::Column::LastError, Expr::value(last_error))
+            .filter(replication_outbox::Column::Id.eq(intent_id))
+            .exec(&self.db_conn).await?;
+        Ok(())
+    }
+
+    async fn finish_replication(&self, intent: &replication_outbox::Model, node_ids: Vec<String>, size: Option<i64>) -> DbResult<()> {
+        let txn = self.db_conn.begin().await?;
+        insert_replicas(&intent.pin_id, node_ids, &txn).await?;
+        let mut update = Pin::update_many()
+            .col_expr(pin::Column::Status, Expr::value(sea_orm_active_enums::Status::Pinned))
+            .col_expr(pin::Column::Updated, Expr::value(chrono::Utc::now()))
+            .filter(pin::Column::Id.eq(intent.pin_id.clone()));
+        if let Some(size) = size {
+            update = update.col_expr(pin::Column::Size, Expr::value(size));
+        }
+        update.exec(&txn).await?;
+        ReplicationOutbox::delete_by_id(intent.id)
+            .exec(&txn).await?;
+        txn.commit().await
+    }
+
+    async fn abandon_replication(&self, intent: &replication_outbox::Model) -> DbResult<()> {
+        let txn = self.db_conn.begin().await?;
+        daos::update_pin_status(&intent.pin_id, sea_orm_active_enums::Status::Failed, &txn).await?;
+        ReplicationOutbox::delete_by_id(intent.id)
+            .exec(&txn).await?;
+        txn.commit().await
+    }
+}
+
+/// Insert replicas. Existing ones are kept.
+async fn insert_replicas<C: ConnectionTrait>(pin_id: &str, node_ids: Vec<String>, db: &C) -> DbResult<()> {
+    if node_ids.is_empty() {
+        return Ok(());
+    }
+    let models: Vec<_> = node_ids.into_iter()
+        .map(|node_id| pins_stored_nodes::ActiveModel {
+            id: Set(Uuid::new_v4().to_string()),
+            pin_id: Set(pin_id.to_owned()),
+            node_id: Set(node_id),
+        }).collect();
+    // a no-op update, since `do_nothing` is not supported by MySQL
+    let on_conflict = sea_query::OnConflict::columns([pins_stored_nodes::Column::PinId, pins_stored_nodes::Column::NodeId])
+        .update_column(pins_stored_nodes::Column::PinId)
+        .to_owned();
+    PinsStoredNodes::insert_many(models)
+        .on_conflict(on_conflict)
+        .exec_without_returning(db).await?;
+    Ok(())
+}
+
+fn find_nodes_by_filter(filter: &NodeFilter) -> Select<Node> {
+    let mut query = Node::find();
+    if !filter.id_in.is_empty() {
+        query = query.filter(node::Column::Id.is_in(filter.id_in.clone()));
+    }
+    if !filter.status_in.is_empty() {
+        query = query.filter(node::Column::NodeStatus.is_in(filter.status_in.clone()));
+    }
+    if !filter.status_not_in.is_empty() {
+        query = query.filter(node::Column::NodeStatus.is_not_in(filter.status_not_in.clone()));
+    }
+    if !filter.id_not_in.is_empty() {
+        query = query.filter(node::Column::Id.is_not_in(filter.id_not_in.clone()));
+    }
+    query
+}
+
+async fn insert_request(pin_id: &str, request: Option<users_pins::Model>, txn: &DatabaseTransaction) -> DbResult<()> {
+    if let Some(request) = request {
+        users_pins::Model { pin_id: pin_id.to_owned(), ..request }
+            .into_active_model()
+            .insert(txn).await?;
+    }
+    Ok(())
+}
+
+fn new_intent(pin_id: &str, intent: NewReplication) -> replication_outbox::ActiveModel {
+    replication_outbox::ActiveModel {
+        pin_id: Set(pin_id.to_owned()),
+        origins: Set(Some(serde_json::json!(intent.origins))),
+        target_node_ids: Set(None),
+        attempts: Set(1),
+        available_at: Set(intent.claimed_until),
+        last_error: Set(None),
+        created: Set(chrono::Utc::now()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::services;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn try_seaorm_repository() {
+        let conn = connect_test_db().await;
+        let repository = SeaOrmRepository::new(conn);
+
+        assert!(repository.insert_pin(test_pin("pin", "cid")).await.unwrap());
+        // duplicate cid
+        assert!(!repository.insert_pin(test_pin("other pin", "cid")).await.unwrap());
+
+        let pin_model = repository.find_pin_by_cid("cid").await.unwrap().unwrap();
+        let pin_model = repository.update_pin(pin::Model {
+            status: sea_orm_active_enums::Status::Deleted,
+            ..pin_model
+        }).await.unwrap();
+        let found = repository.find_pin_by_cid("cid").await.unwrap().unwrap();
+        assert_eq!(found.status, pin_model.status);
+        assert!(repository.find_nodes_with_pin_cid("cid").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn try_replication_outbox() {
+        let conn = connect_test_db().await;
+        let repository = SeaOrmRepository::new(conn.clone());
+        insert_test_node(test_node("node", "1.1.1.1:5001"), &conn).await;
+
+        let claimed_until = chrono::Utc::now() + chrono::Duration::minutes(10);
+        let new_replication = |user_id: &str| NewReplication {
+            origins: vec![],
+            claimed_until,
+            request: Some(daos::new_pin_request(user_id, "pin", None, None, None)),
+        };
+        let intent = repository.insert_pin_to_replicate(test_pin("pin", "cid"), new_replication("alice"))
+            .await.unwrap().unwrap();
+        assert_eq!(intent.attempts, 1);
+        // duplicate cid, nothing is inserted
+        let res = repository.insert_pin_to_replicate(test_pin("other pin", "cid"), new_replication("bob"))
+            .await.unwrap();
+        assert!(res.is_none());
+        assert_eq!(ReplicationOutbox::find().count(&conn).await.unwrap(), 1);
+        let request_vec = UsersPins::find().all(&conn).await.unwrap();
+        assert_eq!(request_vec.len(), 1);
+        assert_eq!(request_vec[0].user_id, "alice");
+
+        // claimed by the creator
+        assert!(repository.find_available_replications(10).await.unwrap().is_empty());
+        assert!(!repository.claim_replication(intent.id, claimed_until).await.unwrap());
+
+        let past = chrono::Utc::now() - chrono::Duration::seconds(1);
+        repository.delay_replication(intent.id, past, "error".to_string()).await.unwrap();
+        let available = repository.find_available_replications(10).await.unwrap();
+        assert_eq!(available[0].last_error.as_deref(), Some("error"));
+        assert!(repository.claim_replication(intent.id, claimed_until).await.unwrap());
+        assert!(!repository.claim_replication(intent.id, claimed_until).await.unwrap());
+
+        repository.finish_replication(&intent, vec!["node".to_string()], Some(10)).await.unwrap();
+        let pin_model = repository.find_pin_by_id("pin").await.unwrap().unwrap();
+        assert_eq!(pin_model.status, sea_orm_active_enums::Status::Pinned);
+        assert_eq!(pin_model.size, Some(10));
+        assert_eq!(PinsStoredNodes::find().count(&conn).await.unwrap(), 1);
+        assert_eq!(ReplicationOutbox::find().count(&conn).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn try_referential_integrity() {
+        let conn = connect_test_db().await;
+        let repository = SeaOrmRepository::new(conn.clone());
+
+        insert_test_node(test_node("node", "1.1.1.1:5001"), &conn).await;
+        repository.insert_pin(test_pin("pin", "cid")).await.unwrap();
+        users_pins::ActiveModel {
+            id: Set("request".to_string()),
+            user_id: Set("user".to_string()),
+            pin_id: Set("pin".to_string()),
+            pin_name: Set(None),
+            created: Set(chrono::Utc::now()),
+            ..Default::default()
+        }.insert(&conn).await.unwrap();
+
+        // existing replicas are kept
+        repository.add_replicas("pin", vec!["node".to_string()]).await.unwrap();
+        repository.add_replicas("pin", vec!["node".to_string()]).await.unwrap();
+        assert_eq!(PinsStoredNodes::find().count(&conn).await.unwrap(), 1);
+        let dup_replica = pins_stored_nodes::ActiveModel {
+            id: Set("dup".to_string()),
+            pin_id: Set("pin".to_string()),
+            node_id: Set("node".to_string()),
+        }.insert(&conn).await;
+        assert!(services::db::check_duplicate_key_error(dup_replica.unwrap_err()).is_ok());
+
+        let nodes = Pin::find_by_id("pin").find_with_related(Node).all(&conn).await.unwrap();
+        assert_eq!(nodes[0].1.len(), 1);
+
+        // replicas and requests go away with the pin
+        Pin::delete_by_id("pin").exec(&conn).await.unwrap();
+        assert_eq!(PinsStoredNodes::find().count(&conn).await.unwrap(), 0);
+        assert_eq!(UsersPins::find().count(&conn).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,127 @@
+//! Fixtures shared by tests of the crate.
+
+use std::sync::Arc;
+use hyper_util::{client::legacy::connect::HttpConnector, rt::TokioExecutor};
+use ipfs_storage_cruster_manager_migration::{Migrator, MigratorTrait};
+use crate::app::{AppState, IpfsMetadata, services};
+use crate::app::services::auth::AuthUser;
+use crate::app_builder::PinMode;
+use crate::file_decision::decision_makers::{RandomFileDownloadDecisionMaker, RandomFileStorageDecisionMaker};
+use crate::imports::dao_imports::*;
+use crate::repository::Repository;
+
+/// Each connection to SQLite in memory has its own database, so keep only one.
+pub(crate) async fn connect_test_db() -> DatabaseConnection {
+    let _ = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .try_init();
+
+    let mut opt = ConnectOptions::new("sqlite::memory:");
+    opt.max_connections(1);
+    let conn = Database::connect(opt)
+        .await
+        .expect("Database connection failed");
+    Migrator::up(&conn, None)
+        .await
+        .expect("Migration failed");
+    conn
+}
+
+/// App state without database and IPFS.
+pub(crate) fn test_state(repository: Arc<dyn Repository>) -> AppState {
+    let reqwest_client = reqwest::Client::new();
+    AppState {
+        reqwest_client: reqwest_client.clone(),
+        ipfs_client: Arc::new(tiny_ipfs_client::ReqwestIpfsClient::new_with_reqwest_client(
+            "127.0.0.1:5001".to_string(),
+            reqwest_client,
+        )),
+        ipfs_metadata: Arc::new(IpfsMetadata {
+            ipfs_peer_id: "test peer id".to_string(),
+            ipfs_swarm_multi_address: "/ip4/127.0.0.1/tcp/4001".to_string(),
+        }),
+        raw_hyper_client: hyper_util::client::legacy::Client::<(), ()>::builder(TokioExecutor::new())
+            .build(HttpConnector::new()),
+        db_conn: DatabaseConnection::Disconnected,
+        repository,
+        file_storage_decision_maker: Arc::new(RandomFileStorageDecisionMaker::new()),
+        file_download_decision_maker: Arc::new(RandomFileDownloadDecisionMaker::new()),
+        node_health_records: Arc::new(scc::HashMap::new()),
+        node_connectivity: Arc::new(scc::HashMap::new()),
+        pin_config: Arc::new(services::file::PinConfig {
+            mode: PinMode::Rpc,
+            poll_interval_ms: 1000,
+            timeout_ms: 1000,
+        }),
+        join_secret: None,
+        rate_limits: Arc::new(services::rate_limit::RateLimits::default()),
+        default_quota: Arc::new(services::quota::Quota::default()),
+        quota_locks: Arc::new(scc::HashMap::new()),
+        download_url_config: None,
+    }
+}
+
+/// App state with the database in `conn`, behind the SeaORM repository.
+pub(crate) fn test_db_state(conn: &DatabaseConnection) -> AppState {
+    AppState {
+        db_conn: conn.clone(),
+        ..test_state(Arc::new(crate::repository::seaorm::SeaOrmRepository::new(conn.clone())))
+    }
+}
+
+pub(crate) fn test_pin(id: &str, cid: &str) -> pin::Model {
+    let now = chrono::Utc::now();
+    pin::Model {
+        id: id.to_string(),
+        status: sea_orm_active_enums::Status::Pinned,
+        cid: cid.to_string(),
+        created: now,
+        updated: now,
+        size: None,
+        name: None,
+        meta: None,
+        origins: None,
+        expires_at: None,
+    }
+}
+
+/// An online node without Wrapper.
+pub(crate) fn test_node(id: &str, rpc_address: &str) -> node::Model {
+    node::Model {
+        id: id.to_string(),
+        peer_id: format!("peer {id}"),
+        rpc_address: rpc_address.to_string(),
+        wrapper_public_address: None,
+        wrapper_admin_address: None,
+        node_status: sea_orm_active_enums::NodeStatus::Online,
+        agent_version: None,
+        multiaddrs: None,
+        repo_size: None,
+        storage_max: None,
+        last_seen: None,
+    }
+}
+
+pub(crate) async fn insert_test_node(node_model: node::Model, conn: &DatabaseConnection) {
+    node::ActiveModel::from(node_model).reset_all().insert(conn).await.unwrap();
+}
+
+pub(crate) fn test_user(id: &str) -> AuthUser {
+    AuthUser(users::Model {
+        id: id.to_string(),
+        name: id.to_string(),
+        role: sea_orm_active_enums::UserRole::User,
+        created: chrono::Utc::now(),
+        max_total_bytes: None,
+        max_pin_count: None,
+        max_file_bytes: None,
+    })
+}
+
+/// Serve a fake IPFS RPC or Wrapper, returning its address.
+pub(crate) async fn serve_fake(app: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    address
+}
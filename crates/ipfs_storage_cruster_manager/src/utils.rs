@@ -1,3 +1,4 @@
+use std::time::{SystemTime, UNIX_EPOCH};
 use axum::http;
 
 #[inline]
@@ -9,3 +10,19 @@ pub fn move_entry_between_header_map(key: http::HeaderName,
         to_header_map.insert(key, v.clone());
     }
 }
+
+/// Compare two byte slices in constant time (only depends on the length).
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Current unix timestamp in milliseconds.
+pub fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|v| v.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -2,8 +2,12 @@
 
 pub mod prelude;
 
+pub mod api_token;
+pub mod audit_log;
 pub mod node;
 pub mod pin;
 pub mod pins_stored_nodes;
+pub mod replication_outbox;
 pub mod sea_orm_active_enums;
+pub mod users;
 pub mod users_pins;
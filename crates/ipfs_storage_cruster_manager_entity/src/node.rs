@@ -16,9 +16,32 @@ pub struct Model {
     pub wrapper_public_address: Option<String>,
     pub wrapper_admin_address: Option<String>,
     pub node_status: NodeStatus,
+    pub agent_version: Option<String>,
+    pub multiaddrs: Option<Json>,
+    pub repo_size: Option<i64>,
+    pub storage_max: Option<i64>,
+    pub last_seen: Option<DateTimeUtc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::pins_stored_nodes::Entity")]
+    PinsStoredNodes,
+}
+
+impl Related<super::pins_stored_nodes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PinsStoredNodes.def()
+    }
+}
+
+impl Related<super::pin::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::pins_stored_nodes::Relation::Pin.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::pins_stored_nodes::Relation::Node.def().rev())
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -13,9 +13,50 @@ pub struct Model {
     pub status: Status,
     #[sea_orm(unique)]
     pub cid: String,
+    pub created: DateTimeUtc,
+    pub updated: DateTimeUtc,
+    pub size: Option<i64>,
+    pub name: Option<String>,
+    pub meta: Option<Json>,
+    pub origins: Option<Json>,
+    pub expires_at: Option<DateTimeUtc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::pins_stored_nodes::Entity")]
+    PinsStoredNodes,
+    #[sea_orm(has_many = "super::replication_outbox::Entity")]
+    ReplicationOutbox,
+    #[sea_orm(has_many = "super::users_pins::Entity")]
+    UsersPins,
+}
+
+impl Related<super::pins_stored_nodes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PinsStoredNodes.def()
+    }
+}
+
+impl Related<super::replication_outbox::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReplicationOutbox.def()
+    }
+}
+
+impl Related<super::users_pins::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UsersPins.def()
+    }
+}
+
+impl Related<super::node::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::pins_stored_nodes::Relation::Node.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::pins_stored_nodes::Relation::Pin.def().rev())
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
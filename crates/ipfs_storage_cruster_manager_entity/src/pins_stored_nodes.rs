@@ -14,6 +14,35 @@ pub struct Model {
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::node::Entity",
+        from = "Column::NodeId",
+        to = "super::node::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Node,
+    #[sea_orm(
+        belongs_to = "super::pin::Entity",
+        from = "Column::PinId",
+        to = "super::pin::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Pin,
+}
+
+impl Related<super::node::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Node.def()
+    }
+}
+
+impl Related<super::pin::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Pin.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -1,6 +1,10 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.14
 
+pub use super::api_token::Entity as ApiToken;
+pub use super::audit_log::Entity as AuditLog;
 pub use super::node::Entity as Node;
 pub use super::pin::Entity as Pin;
 pub use super::pins_stored_nodes::Entity as PinsStoredNodes;
+pub use super::replication_outbox::Entity as ReplicationOutbox;
+pub use super::users::Entity as Users;
 pub use super::users_pins::Entity as UsersPins;
@@ -1,10 +1,49 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.14
+//!
+//! Enums are stored as strings to be portable among databases. They are `ENUM`s only in MySQL.
 
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
-#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "node_status")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(20))")]
+pub enum AuditAction {
+    #[sea_orm(string_value = "upload")]
+    Upload,
+    #[sea_orm(string_value = "add_pin_request")]
+    AddPinRequest,
+    #[sea_orm(string_value = "delete_pin_request")]
+    DeletePinRequest,
+    #[sea_orm(string_value = "pin")]
+    Pin,
+    #[sea_orm(string_value = "unpin")]
+    Unpin,
+    #[sea_orm(string_value = "rehome")]
+    Rehome,
+    #[sea_orm(string_value = "expire")]
+    Expire,
+    #[sea_orm(string_value = "add_node")]
+    AddNode,
+    #[sea_orm(string_value = "remove_node")]
+    RemoveNode,
+    #[sea_orm(string_value = "re_bootstrap")]
+    ReBootstrap,
+    #[sea_orm(string_value = "set_maintenance")]
+    SetMaintenance,
+    #[sea_orm(string_value = "clear_maintenance")]
+    ClearMaintenance,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(20))")]
+pub enum AuditOutcome {
+    #[sea_orm(string_value = "success")]
+    Success,
+    #[sea_orm(string_value = "failure")]
+    Failure,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(20))")]
 pub enum NodeStatus {
     #[sea_orm(string_value = "online")]
     Online,
@@ -12,9 +51,11 @@ pub enum NodeStatus {
     Unhealthy,
     #[sea_orm(string_value = "offline")]
     Offline,
+    #[sea_orm(string_value = "maintenance")]
+    Maintenance,
 }
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
-#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "status")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(20))")]
 pub enum Status {
     #[sea_orm(string_value = "Queued")]
     Queued,
@@ -26,4 +67,15 @@ pub enum Status {
     Failed,
     #[sea_orm(string_value = "NotFound")]
     NotFound,
+    #[sea_orm(string_value = "Deleted")]
+    Deleted,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(20))")]
+#[serde(rename_all = "snake_case")]
+pub enum UserRole {
+    #[sea_orm(string_value = "admin")]
+    Admin,
+    #[sea_orm(string_value = "user")]
+    User,
 }
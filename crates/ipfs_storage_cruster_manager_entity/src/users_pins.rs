@@ -12,9 +12,27 @@ pub struct Model {
     pub user_id: String,
     pub pin_id: String,
     pub pin_name: Option<String>,
+    pub created: DateTimeUtc,
+    pub meta: Option<Json>,
+    pub origins: Option<Json>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::pin::Entity",
+        from = "Column::PinId",
+        to = "super::pin::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Pin,
+}
+
+impl Related<super::pin::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Pin.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,81 @@
+//! Database migrations of the manager.
+//!
+//! Migrations are append-only. Never modify an applied one, add a new one instead.
+//! A new migration runs before applied ones only if new databases need it, like `m20261018_000000_create_postgres_enum_types`.
+
+pub use sea_orm_migration::prelude::*;
+
+mod m20261018_000000_create_postgres_enum_types;
+mod m20261018_000001_create_tables;
+mod m20261018_000002_create_audit_log;
+mod m20261018_000003_referential_integrity;
+mod m20261018_000004_create_replication_outbox;
+mod m20261018_000005_create_users;
+mod m20261018_000006_add_user_quota;
+mod m20261018_000007_add_pin_request_meta;
+mod m20261018_000008_portable_enum_columns;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            // added later, but must run before the tables are created
+            Box::new(m20261018_000000_create_postgres_enum_types::Migration),
+            Box::new(m20261018_000001_create_tables::Migration),
+            Box::new(m20261018_000002_create_audit_log::Migration),
+            Box::new(m20261018_000003_referential_integrity::Migration),
+            Box::new(m20261018_000004_create_replication_outbox::Migration),
+            Box::new(m20261018_000005_create_users::Migration),
+            Box::new(m20261018_000006_add_user_quota::Migration),
+            Box::new(m20261018_000007_add_pin_request_meta::Migration),
+            Box::new(m20261018_000008_portable_enum_columns::Migration),
+        ]
+    }
+}
+
+/// Define a column of enum values.
+///
+/// It's `ENUM` in MySQL. Other databases use a string with check,
+/// so that no custom type is needed in PostgreSQL.
+pub(crate) fn enum_column<C>(manager: &SchemaManager<'_>, column: C, enum_name: &str, variants: &[&str]) -> ColumnDef
+    where C: Iden + Copy + 'static {
+    let mut def = ColumnDef::new(column);
+    match manager.get_database_backend() {
+        sea_orm::DbBackend::MySql => {
+            def.enumeration(Alias::new(enum_name), variants.iter().map(|v| Alias::new(*v)));
+        }
+        sea_orm::DbBackend::Postgres | sea_orm::DbBackend::Sqlite => {
+            let max_len = variants.iter().map(|v| v.len()).max().unwrap_or_default();
+            def.string_len(max_len as u32)
+                .check(Expr::col(column).is_in(variants.iter().copied()));
+        }
+    }
+    def.not_null();
+    def
+}
+
+/// Create the index unless an index with the same name exists.
+///
+/// MySQL does not support `CREATE INDEX IF NOT EXISTS`.
+pub(crate) async fn create_index_if_missing<T, C>(manager: &SchemaManager<'_>,
+                                                  name: &str,
+                                                  table: T,
+                                                  columns: &[C],
+                                                  unique: bool) -> Result<(), DbErr>
+    where T: Iden + Copy + 'static,
+          C: Iden + Copy + 'static {
+    if manager.has_index(table.to_string(), name).await? {
+        return Ok(());
+    }
+    let mut index = Index::create();
+    index.name(name).table(table);
+    for column in columns {
+        index.col(*column);
+    }
+    if unique {
+        index.unique();
+    }
+    manager.create_index(index).await
+}
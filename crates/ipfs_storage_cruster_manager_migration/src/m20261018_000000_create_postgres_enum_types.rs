@@ -0,0 +1,67 @@
+//! Enum types of PostgreSQL used by `m20261018_000001_create_tables` and `m20261018_000002_create_audit_log`,
+//! which define enum columns like MySQL.
+//!
+//! It's listed before them so that a new database of PostgreSQL could be created,
+//! and does nothing in other databases.
+//! The columns are changed to strings by `m20261018_000008_portable_enum_columns`, then the types are dropped.
+
+use sea_orm_migration::prelude::*;
+use sea_orm::{ConnectionTrait, DbBackend};
+
+/// Enum columns of the first migrations. `(table, column, enum name, variants)`.
+pub(crate) const ENUM_COLUMNS: [(&str, &str, &str, &[&str]); 4] = [
+    ("node", "node_status", "node_status", &["online", "unhealthy", "offline", "maintenance"]),
+    ("pin", "status", "status", &["Queued", "Pinning", "Pinned", "Failed", "NotFound", "Deleted"]),
+    ("audit_log", "action", "audit_action", &[
+        "upload",
+        "add_pin_request",
+        "delete_pin_request",
+        "pin",
+        "unpin",
+        "rehome",
+        "expire",
+        "add_node",
+        "remove_node",
+        "re_bootstrap",
+        "set_maintenance",
+        "clear_maintenance",
+    ]),
+    ("audit_log", "outcome", "audit_outcome", &["success", "failure"]),
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() != DbBackend::Postgres {
+            return Ok(());
+        }
+        for (_, _, enum_name, variants) in ENUM_COLUMNS {
+            let variants = variants.iter()
+                .map(|v| format!("'{v}'"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let sql = format!("DO $$ BEGIN \
+                IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = '{enum_name}') THEN \
+                CREATE TYPE {enum_name} AS ENUM ({variants}); \
+                END IF; \
+                END $$");
+            manager.get_connection().execute_unprepared(&sql).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() != DbBackend::Postgres {
+            return Ok(());
+        }
+        for (_, _, enum_name, _) in ENUM_COLUMNS {
+            manager.get_connection()
+                .execute_unprepared(&format!("DROP TYPE IF EXISTS {enum_name}"))
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+//! Tables existing before migrations are introduced.
+//!
+//! Tables are created only if not exist, so a database created by the old SQL scripts could be adopted.
+
+use crate::create_index_if_missing;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(Node::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(Node::Id).string_len(100).not_null().primary_key())
+                .col(ColumnDef::new(Node::PeerId).string_len(100).not_null())
+                .col(ColumnDef::new(Node::RpcAddress).string_len(100).not_null())
+                .col(ColumnDef::new(Node::WrapperPublicAddress).string_len(100).null())
+                .col(ColumnDef::new(Node::WrapperAdminAddress).string_len(100).null())
+                .col(ColumnDef::new(Node::NodeStatus)
+                    .enumeration(Alias::new("node_status"), [
+                        Alias::new("online"),
+                        Alias::new("unhealthy"),
+                        Alias::new("offline"),
+                        Alias::new("maintenance"),
+                    ])
+                    .not_null())
+                .col(ColumnDef::new(Node::AgentVersion).string_len(100).null())
+                .col(ColumnDef::new(Node::Multiaddrs).json().null())
+                .col(ColumnDef::new(Node::RepoSize).big_integer().null())
+                .col(ColumnDef::new(Node::StorageMax).big_integer().null())
+                .col(ColumnDef::new(Node::LastSeen).timestamp_with_time_zone().null())
+                .to_owned()
+        ).await?;
+        create_index_if_missing(manager, "node_peer_id_uindex", Node::Table, &[Node::PeerId], true).await?;
+
+        manager.create_table(
+            Table::create()
+                .table(Pin::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(Pin::Id).string_len(100).not_null().primary_key())
+                .col(ColumnDef::new(Pin::Status)
+                    .enumeration(Alias::new("status"), [
+                        Alias::new("Queued"),
+                        Alias::new("Pinning"),
+                        Alias::new("Pinned"),
+                        Alias::new("Failed"),
+                        Alias::new("NotFound"),
+                        Alias::new("Deleted"),
+                    ])
+                    .not_null())
+                .col(ColumnDef::new(Pin::Cid).string_len(100).not_null())
+                .col(ColumnDef::new(Pin::Created).timestamp_with_time_zone().not_null().default(Expr::current_timestamp()))
+                .col(ColumnDef::new(Pin::Updated).timestamp_with_time_zone().not_null().default(Expr::current_timestamp()))
+                .col(ColumnDef::new(Pin::Size).big_integer().null())
+                .col(ColumnDef::new(Pin::Name).string_len(255).null())
+                .col(ColumnDef::new(Pin::Meta).json().null())
+                .col(ColumnDef::new(Pin::Origins).json().null())
+                .col(ColumnDef::new(Pin::ExpiresAt).timestamp_with_time_zone().null())
+                .to_owned()
+        ).await?;
+        create_index_if_missing(manager, "pin_cid_uindex", Pin::Table, &[Pin::Cid], true).await?;
+        create_index_if_missing(manager, "pin_expires_at_index", Pin::Table, &[Pin::ExpiresAt], false).await?;
+
+        manager.create_table(
+            Table::create()
+                .table(PinsStoredNodes::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(PinsStoredNodes::Id).string_len(100).not_null().primary_key())
+                .col(ColumnDef::new(PinsStoredNodes::PinId).string_len(100).not_null())
+                .col(ColumnDef::new(PinsStoredNodes::NodeId).string_len(100).not_null())
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("pins_stored_nodes_pin_id_fk")
+                        .from(PinsStoredNodes::Table, PinsStoredNodes::PinId)
+                        .to(Pin::Table, Pin::Id)
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("pins_stored_nodes_node_id_fk")
+                        .from(PinsStoredNodes::Table, PinsStoredNodes::NodeId)
+                        .to(Node::Table, Node::Id)
+                )
+                .to_owned()
+        ).await?;
+        create_index_if_missing(manager, "pins_stored_nodes_pin_id_index", PinsStoredNodes::Table, &[PinsStoredNodes::PinId], false).await?;
+        create_index_if_missing(manager, "pins_stored_nodes_node_id_index", PinsStoredNodes::Table, &[PinsStoredNodes::NodeId], false).await?;
+
+        manager.create_table(
+            Table::create()
+                .table(UsersPins::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(UsersPins::Id).string_len(100).not_null().primary_key())
+                .col(ColumnDef::new(UsersPins::UserId).string_len(100).not_null())
+                .col(ColumnDef::new(UsersPins::PinId).string_len(100).not_null())
+                .col(ColumnDef::new(UsersPins::PinName).string_len(100).null())
+                .col(ColumnDef::new(UsersPins::Created).timestamp_with_time_zone().not_null().default(Expr::current_timestamp()))
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("users_pins_pin_id_fk")
+                        .from(UsersPins::Table, UsersPins::PinId)
+                        .to(Pin::Table, Pin::Id)
+                )
+                .to_owned()
+        ).await?;
+        create_index_if_missing(manager, "users_pins_user_id_index", UsersPins::Table, &[UsersPins::UserId], false).await?;
+        create_index_if_missing(manager, "users_pins_pin_id_index", UsersPins::Table, &[UsersPins::PinId], false).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(UsersPins::Table).to_owned()).await?;
+        manager.drop_table(Table::drop().table(PinsStoredNodes::Table).to_owned()).await?;
+        manager.drop_table(Table::drop().table(Pin::Table).to_owned()).await?;
+        manager.drop_table(Table::drop().table(Node::Table).to_owned()).await
+    }
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+#[allow(clippy::enum_variant_names)]
+enum Node {
+    Table,
+    Id,
+    PeerId,
+    RpcAddress,
+    WrapperPublicAddress,
+    WrapperAdminAddress,
+    NodeStatus,
+    AgentVersion,
+    Multiaddrs,
+    RepoSize,
+    StorageMax,
+    LastSeen,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum Pin {
+    Table,
+    Id,
+    Status,
+    Cid,
+    Created,
+    Updated,
+    Size,
+    Name,
+    Meta,
+    Origins,
+    ExpiresAt,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum PinsStoredNodes {
+    Table,
+    Id,
+    PinId,
+    NodeId,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum UsersPins {
+    Table,
+    Id,
+    UserId,
+    PinId,
+    PinName,
+    Created,
+}
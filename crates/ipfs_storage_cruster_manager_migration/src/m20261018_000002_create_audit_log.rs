@@ -0,0 +1,68 @@
+//! Append-only audit log.
+
+use crate::create_index_if_missing;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(AuditLog::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(AuditLog::Id).big_integer().not_null().auto_increment().primary_key())
+                .col(ColumnDef::new(AuditLog::Created).timestamp_with_time_zone().not_null().default(Expr::current_timestamp()))
+                .col(ColumnDef::new(AuditLog::Actor).string_len(255).not_null())
+                .col(ColumnDef::new(AuditLog::Action)
+                    .enumeration(Alias::new("audit_action"), [
+                        Alias::new("upload"),
+                        Alias::new("add_pin_request"),
+                        Alias::new("delete_pin_request"),
+                        Alias::new("pin"),
+                        Alias::new("unpin"),
+                        Alias::new("rehome"),
+                        Alias::new("expire"),
+                        Alias::new("add_node"),
+                        Alias::new("remove_node"),
+                        Alias::new("re_bootstrap"),
+                        Alias::new("set_maintenance"),
+                        Alias::new("clear_maintenance"),
+                    ])
+                    .not_null())
+                .col(ColumnDef::new(AuditLog::Cid).string_len(100).null())
+                .col(ColumnDef::new(AuditLog::NodeId).string_len(100).null())
+                .col(ColumnDef::new(AuditLog::Outcome)
+                    .enumeration(Alias::new("audit_outcome"), [
+                        Alias::new("success"),
+                        Alias::new("failure"),
+                    ])
+                    .not_null())
+                .col(ColumnDef::new(AuditLog::Detail).string_len(1000).null())
+                .to_owned()
+        ).await?;
+        create_index_if_missing(manager, "audit_log_created_index", AuditLog::Table, &[AuditLog::Created], false).await?;
+        create_index_if_missing(manager, "audit_log_cid_index", AuditLog::Table, &[AuditLog::Cid], false).await?;
+        create_index_if_missing(manager, "audit_log_node_id_index", AuditLog::Table, &[AuditLog::NodeId], false).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(AuditLog::Table).to_owned()).await
+    }
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum AuditLog {
+    Table,
+    Id,
+    Created,
+    Actor,
+    Action,
+    Cid,
+    NodeId,
+    Outcome,
+    Detail,
+}
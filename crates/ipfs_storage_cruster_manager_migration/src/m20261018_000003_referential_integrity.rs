@@ -0,0 +1,252 @@
+//! Foreign keys with cascade, and unique replicas.
+//!
+//! Replicas and pin requests are deleted with their pin, and replicas are deleted with their node.
+//! Databases adopted from the old SQL scripts have no foreign keys,
+//! so orphan and duplicate rows are removed first.
+//!
+//! SQLite could not alter foreign keys, so its tables are rebuilt.
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DbBackend, Statement, StatementBuilder};
+use crate::create_index_if_missing;
+
+const PINS_STORED_NODES_UNIQUE_INDEX: &str = "pins_stored_nodes_pin_id_node_id_uindex";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        remove_orphans(manager).await?;
+        remove_duplicate_replicas(manager).await?;
+        set_foreign_keys(manager, ForeignKeyAction::Cascade).await?;
+        create_index_if_missing(manager, PINS_STORED_NODES_UNIQUE_INDEX, PinsStoredNodes::Table,
+                                &[PinsStoredNodes::PinId, PinsStoredNodes::NodeId], true).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.has_index(PinsStoredNodes::Table.to_string(), PINS_STORED_NODES_UNIQUE_INDEX).await? {
+            manager.drop_index(Index::drop()
+                .name(PINS_STORED_NODES_UNIQUE_INDEX)
+                .table(PinsStoredNodes::Table)
+                .to_owned()
+            ).await?;
+        }
+        set_foreign_keys(manager, ForeignKeyAction::NoAction).await
+    }
+}
+
+async fn remove_orphans(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let pin_ids = Query::select().column(Pin::Id).from(Pin::Table).to_owned();
+    let node_ids = Query::select().column(Node::Id).from(Node::Table).to_owned();
+    exec_query(manager, Query::delete()
+        .from_table(PinsStoredNodes::Table)
+        .cond_where(Cond::any()
+            .add(Expr::col(PinsStoredNodes::PinId).not_in_subquery(pin_ids.clone()))
+            .add(Expr::col(PinsStoredNodes::NodeId).not_in_subquery(node_ids)))
+        .to_owned()
+    ).await?;
+    exec_query(manager, Query::delete()
+        .from_table(UsersPins::Table)
+        .and_where(Expr::col(UsersPins::PinId).not_in_subquery(pin_ids))
+        .to_owned()
+    ).await
+}
+
+/// Keep one row of each `(pin_id, node_id)`.
+async fn remove_duplicate_replicas(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let kept = Alias::new("kept");
+    // MySQL could not select from the table to delete, unless in a derived table.
+    let kept_ids = Query::select()
+        .column(PinsStoredNodes::Id)
+        .from_subquery(
+            Query::select()
+                .expr_as(Expr::col(PinsStoredNodes::Id).min(), PinsStoredNodes::Id)
+                .from(PinsStoredNodes::Table)
+                .group_by_columns([PinsStoredNodes::PinId, PinsStoredNodes::NodeId])
+                .to_owned(),
+            kept,
+        )
+        .to_owned();
+    exec_query(manager, Query::delete()
+        .from_table(PinsStoredNodes::Table)
+        .and_where(Expr::col(PinsStoredNodes::Id).not_in_subquery(kept_ids))
+        .to_owned()
+    ).await
+}
+
+/// Replace foreign keys of `pins_stored_nodes` and `users_pins` with ones deleting by `on_delete`.
+async fn set_foreign_keys(manager: &SchemaManager<'_>, on_delete: ForeignKeyAction) -> Result<(), DbErr> {
+    match manager.get_database_backend() {
+        DbBackend::MySql | DbBackend::Postgres => {
+            replace_foreign_keys(manager, PinsStoredNodes::Table, pins_stored_nodes_foreign_keys(on_delete)).await?;
+            replace_foreign_keys(manager, UsersPins::Table, users_pins_foreign_keys(on_delete)).await
+        }
+        DbBackend::Sqlite => {
+            let mut pins_stored_nodes = pins_stored_nodes_table(on_delete);
+            rebuild_sqlite_table(manager, PinsStoredNodes::Table, &mut pins_stored_nodes,
+                                 &[PinsStoredNodes::Id, PinsStoredNodes::PinId, PinsStoredNodes::NodeId]).await?;
+            create_index_if_missing(manager, "pins_stored_nodes_pin_id_index", PinsStoredNodes::Table, &[PinsStoredNodes::PinId], false).await?;
+            create_index_if_missing(manager, "pins_stored_nodes_node_id_index", PinsStoredNodes::Table, &[PinsStoredNodes::NodeId], false).await?;
+
+            let mut users_pins = users_pins_table(on_delete);
+            rebuild_sqlite_table(manager, UsersPins::Table, &mut users_pins,
+                                 &[UsersPins::Id, UsersPins::UserId, UsersPins::PinId, UsersPins::PinName, UsersPins::Created]).await?;
+            create_index_if_missing(manager, "users_pins_user_id_index", UsersPins::Table, &[UsersPins::UserId], false).await?;
+            create_index_if_missing(manager, "users_pins_pin_id_index", UsersPins::Table, &[UsersPins::PinId], false).await
+        }
+    }
+}
+
+/// Drop the foreign keys with the same names if exist, then create them.
+async fn replace_foreign_keys<T, const N: usize>(manager: &SchemaManager<'_>,
+                                                 table: T,
+                                                 foreign_keys: [(&str, ForeignKeyCreateStatement); N]) -> Result<(), DbErr>
+    where T: Iden + Copy + 'static {
+    for (name, foreign_key) in foreign_keys {
+        if has_foreign_key(manager, table, name).await? {
+            manager.drop_foreign_key(ForeignKey::drop().name(name).table(table).to_owned()).await?;
+        }
+        manager.create_foreign_key(foreign_key).await?;
+    }
+    Ok(())
+}
+
+/// `(name, foreign key)`.
+fn pins_stored_nodes_foreign_keys(on_delete: ForeignKeyAction) -> [(&'static str, ForeignKeyCreateStatement); 2] {
+    const PIN_ID_FK: &str = "pins_stored_nodes_pin_id_fk";
+    const NODE_ID_FK: &str = "pins_stored_nodes_node_id_fk";
+    [
+        (PIN_ID_FK, ForeignKey::create()
+            .name(PIN_ID_FK)
+            .from(PinsStoredNodes::Table, PinsStoredNodes::PinId)
+            .to(Pin::Table, Pin::Id)
+            .on_delete(on_delete)
+            .to_owned()),
+        (NODE_ID_FK, ForeignKey::create()
+            .name(NODE_ID_FK)
+            .from(PinsStoredNodes::Table, PinsStoredNodes::NodeId)
+            .to(Node::Table, Node::Id)
+            .on_delete(on_delete)
+            .to_owned()),
+    ]
+}
+
+/// `(name, foreign key)`.
+fn users_pins_foreign_keys(on_delete: ForeignKeyAction) -> [(&'static str, ForeignKeyCreateStatement); 1] {
+    const PIN_ID_FK: &str = "users_pins_pin_id_fk";
+    [
+        (PIN_ID_FK, ForeignKey::create()
+            .name(PIN_ID_FK)
+            .from(UsersPins::Table, UsersPins::PinId)
+            .to(Pin::Table, Pin::Id)
+            .on_delete(on_delete)
+            .to_owned()),
+    ]
+}
+
+/// Same columns as `m20261018_000001_create_tables`.
+fn pins_stored_nodes_table(on_delete: ForeignKeyAction) -> TableCreateStatement {
+    let mut table = Table::create();
+    table.table(PinsStoredNodes::Table)
+        .col(ColumnDef::new(PinsStoredNodes::Id).string_len(100).not_null().primary_key())
+        .col(ColumnDef::new(PinsStoredNodes::PinId).string_len(100).not_null())
+        .col(ColumnDef::new(PinsStoredNodes::NodeId).string_len(100).not_null());
+    for (_, mut foreign_key) in pins_stored_nodes_foreign_keys(on_delete) {
+        table.foreign_key(&mut foreign_key);
+    }
+    table
+}
+
+/// Same columns as `m20261018_000001_create_tables`.
+fn users_pins_table(on_delete: ForeignKeyAction) -> TableCreateStatement {
+    let mut table = Table::create();
+    table.table(UsersPins::Table)
+        .col(ColumnDef::new(UsersPins::Id).string_len(100).not_null().primary_key())
+        .col(ColumnDef::new(UsersPins::UserId).string_len(100).not_null())
+        .col(ColumnDef::new(UsersPins::PinId).string_len(100).not_null())
+        .col(ColumnDef::new(UsersPins::PinName).string_len(100).null())
+        .col(ColumnDef::new(UsersPins::Created).timestamp_with_time_zone().not_null().default(Expr::current_timestamp()));
+    for (_, mut foreign_key) in users_pins_foreign_keys(on_delete) {
+        table.foreign_key(&mut foreign_key);
+    }
+    table
+}
+
+/// Create the table by `definition` with a temporary name, copy all rows, then replace the old one.
+async fn rebuild_sqlite_table<T, C>(manager: &SchemaManager<'_>,
+                                    table: T,
+                                    definition: &mut TableCreateStatement,
+                                    columns: &[C]) -> Result<(), DbErr>
+    where T: Iden + Copy + 'static,
+          C: Iden + Copy + 'static {
+    let new_table = Alias::new(format!("{}_new", table.to_string()));
+    manager.create_table(definition.table(new_table.clone()).to_owned()).await?;
+    exec_query(manager, Query::insert()
+        .into_table(new_table.clone())
+        .columns(columns.iter().copied())
+        .select_from(Query::select().columns(columns.iter().copied()).from(table).to_owned())
+        .map_err(|e| DbErr::Migration(e.to_string()))?
+        .to_owned()
+    ).await?;
+    manager.drop_table(Table::drop().table(table).to_owned()).await?;
+    manager.rename_table(Table::rename().table(new_table, table).to_owned()).await
+}
+
+async fn has_foreign_key<T>(manager: &SchemaManager<'_>, table: T, name: &str) -> Result<bool, DbErr>
+    where T: Iden {
+    let backend = manager.get_database_backend();
+    let sql = match backend {
+        DbBackend::MySql => "SELECT COUNT(*) FROM information_schema.table_constraints \
+            WHERE constraint_schema = DATABASE() AND table_name = ? AND constraint_name = ? \
+            AND constraint_type = 'FOREIGN KEY'",
+        DbBackend::Postgres => "SELECT COUNT(*) FROM information_schema.table_constraints \
+            WHERE constraint_schema = CURRENT_SCHEMA() AND table_name = $1 AND constraint_name = $2 \
+            AND constraint_type = 'FOREIGN KEY'",
+        DbBackend::Sqlite => return Ok(false),
+    };
+    let stmt = Statement::from_sql_and_values(backend, sql, [table.to_string().into(), name.into()]);
+    let count: i64 = match manager.get_connection().query_one(stmt).await? {
+        Some(row) => row.try_get_by_index(0)?,
+        None => 0,
+    };
+    Ok(count > 0)
+}
+
+async fn exec_query<S>(manager: &SchemaManager<'_>, stmt: S) -> Result<(), DbErr>
+    where S: StatementBuilder {
+    let builder = manager.get_database_backend();
+    manager.get_connection().execute(builder.build(&stmt)).await?;
+    Ok(())
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum Node {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum Pin {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum PinsStoredNodes {
+    Table,
+    Id,
+    PinId,
+    NodeId,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum UsersPins {
+    Table,
+    Id,
+    UserId,
+    PinId,
+    PinName,
+    Created,
+}
@@ -0,0 +1,62 @@
+//! Outbox of replication work.
+//!
+//! An intent is written with its pin in a transaction, and removed when the pin is replicated.
+
+use crate::create_index_if_missing;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(ReplicationOutbox::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(ReplicationOutbox::Id).big_integer().not_null().auto_increment().primary_key())
+                .col(ColumnDef::new(ReplicationOutbox::PinId).string_len(100).not_null())
+                .col(ColumnDef::new(ReplicationOutbox::Origins).json().null())
+                .col(ColumnDef::new(ReplicationOutbox::TargetNodeIds).json().null())
+                .col(ColumnDef::new(ReplicationOutbox::Attempts).integer().not_null().default(0))
+                .col(ColumnDef::new(ReplicationOutbox::AvailableAt).timestamp_with_time_zone().not_null().default(Expr::current_timestamp()))
+                .col(ColumnDef::new(ReplicationOutbox::LastError).string_len(1000).null())
+                .col(ColumnDef::new(ReplicationOutbox::Created).timestamp_with_time_zone().not_null().default(Expr::current_timestamp()))
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("replication_outbox_pin_id_fk")
+                        .from(ReplicationOutbox::Table, ReplicationOutbox::PinId)
+                        .to(Pin::Table, Pin::Id)
+                        .on_delete(ForeignKeyAction::Cascade)
+                )
+                .to_owned()
+        ).await?;
+        create_index_if_missing(manager, "replication_outbox_pin_id_index", ReplicationOutbox::Table, &[ReplicationOutbox::PinId], false).await?;
+        create_index_if_missing(manager, "replication_outbox_available_at_index", ReplicationOutbox::Table, &[ReplicationOutbox::AvailableAt], false).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(ReplicationOutbox::Table).to_owned()).await
+    }
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum Pin {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum ReplicationOutbox {
+    Table,
+    Id,
+    PinId,
+    Origins,
+    TargetNodeIds,
+    Attempts,
+    AvailableAt,
+    LastError,
+    Created,
+}
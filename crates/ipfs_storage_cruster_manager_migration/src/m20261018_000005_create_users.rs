@@ -0,0 +1,130 @@
+//! Users and their API tokens.
+//!
+//! Only the SHA-256 hash of a token is stored.
+//! Pin requests used the hash of token as user id before, so a user with the same id
+//! and a token with the same hash are created for each of them, and old tokens keep working.
+
+use crate::{create_index_if_missing, enum_column};
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, StatementBuilder};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(Users::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(Users::Id).string_len(100).not_null().primary_key())
+                .col(ColumnDef::new(Users::Name).string_len(100).not_null())
+                .col(&mut enum_column(manager, Users::Role, "user_role", &[
+                    "admin",
+                    "user",
+                ]))
+                .col(ColumnDef::new(Users::Created).timestamp_with_time_zone().not_null().default(Expr::current_timestamp()))
+                .to_owned()
+        ).await?;
+        create_index_if_missing(manager, "users_name_uindex", Users::Table, &[Users::Name], true).await?;
+
+        manager.create_table(
+            Table::create()
+                .table(ApiToken::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(ApiToken::Id).string_len(100).not_null().primary_key())
+                .col(ColumnDef::new(ApiToken::UserId).string_len(100).not_null())
+                .col(ColumnDef::new(ApiToken::TokenHash).string_len(64).not_null())
+                .col(ColumnDef::new(ApiToken::Name).string_len(100).null())
+                .col(ColumnDef::new(ApiToken::Created).timestamp_with_time_zone().not_null().default(Expr::current_timestamp()))
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("api_token_user_id_fk")
+                        .from(ApiToken::Table, ApiToken::UserId)
+                        .to(Users::Table, Users::Id)
+                        .on_delete(ForeignKeyAction::Cascade)
+                )
+                .to_owned()
+        ).await?;
+        create_index_if_missing(manager, "api_token_token_hash_uindex", ApiToken::Table, &[ApiToken::TokenHash], true).await?;
+        create_index_if_missing(manager, "api_token_user_id_index", ApiToken::Table, &[ApiToken::UserId], false).await?;
+
+        adopt_legacy_users(manager).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(ApiToken::Table).to_owned()).await?;
+        manager.drop_table(Table::drop().table(Users::Table).to_owned()).await
+    }
+}
+
+/// Create users and tokens for the hashed tokens in `users_pins`.
+async fn adopt_legacy_users(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let user_ids = Query::select()
+        .distinct()
+        .column(UsersPins::UserId)
+        .from(UsersPins::Table)
+        .to_owned();
+    let existing_user_ids = Query::select().column(Users::Id).from(Users::Table).to_owned();
+    exec_query(manager, Query::insert()
+        .into_table(Users::Table)
+        .columns([Users::Id, Users::Name, Users::Role])
+        .select_from(Query::select()
+            .column(UsersPins::UserId)
+            .column(UsersPins::UserId)
+            .expr(Expr::val("user"))
+            .from_subquery(user_ids.clone(), Alias::new("legacy"))
+            .and_where(Expr::col(UsersPins::UserId).not_in_subquery(existing_user_ids))
+            .to_owned())
+        .map_err(|e| DbErr::Migration(e.to_string()))?
+        .to_owned()
+    ).await?;
+
+    let existing_hashes = Query::select().column(ApiToken::TokenHash).from(ApiToken::Table).to_owned();
+    exec_query(manager, Query::insert()
+        .into_table(ApiToken::Table)
+        .columns([ApiToken::Id, ApiToken::UserId, ApiToken::TokenHash])
+        .select_from(Query::select()
+            .column(UsersPins::UserId)
+            .column(UsersPins::UserId)
+            .column(UsersPins::UserId)
+            .from_subquery(user_ids, Alias::new("legacy"))
+            .and_where(Expr::col(UsersPins::UserId).not_in_subquery(existing_hashes))
+            .to_owned())
+        .map_err(|e| DbErr::Migration(e.to_string()))?
+        .to_owned()
+    ).await
+}
+
+async fn exec_query<S>(manager: &SchemaManager<'_>, stmt: S) -> Result<(), DbErr>
+    where S: StatementBuilder {
+    let builder = manager.get_database_backend();
+    manager.get_connection().execute(builder.build(&stmt)).await?;
+    Ok(())
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum Users {
+    Table,
+    Id,
+    Name,
+    Role,
+    Created,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum ApiToken {
+    Table,
+    Id,
+    UserId,
+    TokenHash,
+    Name,
+    Created,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum UsersPins {
+    Table,
+    UserId,
+}
@@ -0,0 +1,45 @@
+//! Quotas of users. A null quota means the default in config.
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite alters one column at a time
+        for column in [Users::MaxTotalBytes, Users::MaxPinCount, Users::MaxFileBytes] {
+            if manager.has_column("users", &column.to_string()).await? {
+                continue;
+            }
+            manager.alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(column).big_integer().null())
+                    .to_owned()
+            ).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [Users::MaxTotalBytes, Users::MaxPinCount, Users::MaxFileBytes] {
+            manager.alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(column)
+                    .to_owned()
+            ).await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum Users {
+    Table,
+    MaxTotalBytes,
+    MaxPinCount,
+    MaxFileBytes,
+}
@@ -0,0 +1,44 @@
+//! Meta and origins of each pin request, since requests with the same CID share one pin.
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite alters one column at a time
+        for column in [UsersPins::Meta, UsersPins::Origins] {
+            if manager.has_column("users_pins", &column.to_string()).await? {
+                continue;
+            }
+            manager.alter_table(
+                Table::alter()
+                    .table(UsersPins::Table)
+                    .add_column(ColumnDef::new(column).json().null())
+                    .to_owned()
+            ).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [UsersPins::Meta, UsersPins::Origins] {
+            manager.alter_table(
+                Table::alter()
+                    .table(UsersPins::Table)
+                    .drop_column(column)
+                    .to_owned()
+            ).await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum UsersPins {
+    Table,
+    Meta,
+    Origins,
+}
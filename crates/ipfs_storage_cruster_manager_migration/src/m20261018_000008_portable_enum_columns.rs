@@ -0,0 +1,49 @@
+//! Change the enum columns of the first migrations like `crate::enum_column`, so that they're portable among databases.
+//!
+//! MySQL keeps `ENUM`s. PostgreSQL changes them to strings with check, then drops the enum types.
+//! SQLite keeps them as `text`, since it could not change the type of a column without rebuilding the table,
+//! and values are checked by the entities anyway.
+
+use sea_orm_migration::prelude::*;
+use sea_orm::{ConnectionTrait, DbBackend};
+use crate::m20261018_000000_create_postgres_enum_types::ENUM_COLUMNS;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() != DbBackend::Postgres {
+            return Ok(());
+        }
+        for (table, column, _, variants) in ENUM_COLUMNS {
+            let max_len = variants.iter().map(|v| v.len()).max().unwrap_or_default();
+            let variants = variants.iter()
+                .map(|v| format!("'{v}'"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            // columns created by a manager with strings already are skipped
+            let sql = format!("DO $$ BEGIN \
+                IF EXISTS (SELECT 1 FROM information_schema.columns \
+                WHERE table_schema = CURRENT_SCHEMA() AND table_name = '{table}' AND column_name = '{column}' \
+                AND data_type = 'USER-DEFINED') THEN \
+                ALTER TABLE {table} ALTER COLUMN {column} TYPE varchar({max_len}) USING {column}::text; \
+                ALTER TABLE {table} ADD CHECK ({column} IN ({variants})); \
+                END IF; \
+                END $$");
+            manager.get_connection().execute_unprepared(&sql).await?;
+        }
+        for (_, _, enum_name, _) in ENUM_COLUMNS {
+            manager.get_connection()
+                .execute_unprepared(&format!("DROP TYPE IF EXISTS {enum_name}"))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // strings are compatible with the entities, so they're kept
+        Ok(())
+    }
+}
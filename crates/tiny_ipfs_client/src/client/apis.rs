@@ -4,21 +4,44 @@ use crate::{IpfsClientError, dtos, ReqwestIpfsClient, IpfsClientResult};
 
 impl ReqwestIpfsClient {
     /// Get file from IPFS gateway.
-    #[cfg(not(feature = "no_gateway"))]
+    ///
+    /// `headers` are sent to the gateway, like `Range`.
+    /// Use `reqwest::Method::HEAD` as `method` to get headers only.
+    ///
+    /// Return the response if succeed, or if the range is not satisfiable,
+    /// so that the status and headers could be passed to users.
+    #[cfg(feature = "gateway")]
     #[tracing::instrument]
-    pub async fn get_file_by_gateway(&self, cid: &str, file_name: Option<&str>) -> IpfsClientResult<reqwest::Response> {
+    pub async fn get_file_by_gateway(&self,
+                                     cid: &str,
+                                     file_name: Option<&str>,
+                                     method: reqwest::Method,
+                                     headers: reqwest::header::HeaderMap)
+                                     -> IpfsClientResult<reqwest::Response> {
         let url = format!("http://{addr}/ipfs/{cid}?filename={file_name}&download=true",
                           addr = &self.gateway_address,
                           cid = cid,
                           file_name = file_name.unwrap_or(cid)
         );
 
-        let res = self.client
-            .get(url)
-            .send()
-            .await.map_err(|_e| {
+        // dropping the future cancels the request
+        let send = self.gateway_client
+            .request(method, url)
+            .headers(headers)
+            .send();
+        let res = match self.gateway_timeouts.first_byte {
+            Some(first_byte) => tokio::time::timeout(first_byte, send).await.map_err(|_| {
+                error!("IPFS gateway did not respond in {:?}. cid: {}", first_byte, cid);
+                IpfsClientError::Timeout
+            })?,
+            None => send.await,
+        }.map_err(|_e| {
             error!("Send gateway request failed. msg: {:?}", _e);
-            IpfsClientError::SendRequestFailed
+            if _e.is_timeout() {
+                IpfsClientError::Timeout
+            } else {
+                IpfsClientError::SendRequestFailed
+            }
         })?;
 
         let status = res.status();
@@ -27,6 +50,10 @@ impl ReqwestIpfsClient {
                 info!("Success get file. cid: {}", cid);
                 Ok(res)
             }
+            reqwest::StatusCode::RANGE_NOT_SATISFIABLE => {
+                debug!("Range of file is not satisfiable. cid: {}", cid);
+                Ok(res)
+            }
             reqwest::StatusCode::NOT_FOUND => {
                 error!("IPFS gateway not found");
                 Err(IpfsClientError::NotFound)
@@ -42,6 +69,36 @@ impl ReqwestIpfsClient {
         }
     }
 
+    /// Stream of the body of a response from `get_file_by_gateway`,
+    /// which ends with `IpfsClientError::Timeout` if no chunk arrives in the idle timeout.
+    ///
+    /// Dropping the stream cancels the request.
+    #[cfg(feature = "gateway")]
+    pub fn gateway_bytes_stream(&self, res: reqwest::Response)
+                                -> impl futures_util::Stream<Item=IpfsClientResult<bytes::Bytes>> + Send + 'static {
+        let idle = self.gateway_timeouts.idle;
+        futures_util::stream::unfold(Some(res), move |res| async move {
+            let mut res = res?;
+            let chunk = match idle {
+                Some(idle) => tokio::time::timeout(idle, res.chunk()).await.map_err(|_| {
+                    error!("IPFS gateway stream is idle for {:?}", idle);
+                    IpfsClientError::Timeout
+                }),
+                None => Ok(res.chunk().await),
+            };
+            match chunk {
+                Ok(Ok(Some(chunk))) => Some((Ok(chunk), Some(res))),
+                Ok(Ok(None)) => None,
+                Ok(Err(_e)) => {
+                    error!("Read gateway response failed. msg: {:?}", _e);
+                    Some((Err(IpfsClientError::SendRequestFailed), None))
+                }
+                // end the stream after an error
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
     /// Get IPFS node's basic information.
     #[tracing::instrument]
     pub async fn get_id_info(&self) -> IpfsClientResult<dtos::IdResponse> {
@@ -208,4 +265,189 @@ impl ReqwestIpfsClient {
             err => Err(Self::handle_rpc_status_code_error(err))
         }
     }
+
+    /// Remove an IPFS node from bootstrap list by ip address, port and peer id.
+    #[tracing::instrument]
+    pub async fn bootstrap_rm(&self, swarm_multi_address: &str, peer_id: &str) -> IpfsClientResult<()> {
+        let multi_addr = format!("{}/p2p/{}", swarm_multi_address, peer_id);
+        debug!("multi_addr: {}", multi_addr);
+        let url_content = format!("/bootstrap/rm?arg={multi_addr}",
+                                  multi_addr = multi_addr);
+        let res = self.ipfs_rpc_request(&url_content).await?;
+
+        let status = res.status();
+        match status {
+            _ if status.is_success() => {
+                info!("Success remove bootstrap. multi_addr: {}", multi_addr);
+                Ok(())
+            }
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR => {
+                error!("Not an expected Interval Server Error: {:?}", res.text().await);
+                Err(Self::handle_rpc_status_code_error(reqwest::StatusCode::INTERNAL_SERVER_ERROR))
+            }
+            err => Err(Self::handle_rpc_status_code_error(err))
+        }
+    }
+
+    /// Open a connection to a peer by a multi address which contains `/p2p/{peer_id}`.
+    #[tracing::instrument]
+    pub async fn swarm_connect(&self, multi_addr: &str) -> IpfsClientResult<()> {
+        let url_content = format!("/swarm/connect?arg={multi_addr}",
+                                  multi_addr = multi_addr);
+        let res = self.ipfs_rpc_request(&url_content).await?;
+
+        let status = res.status();
+        match status {
+            _ if status.is_success() => {
+                info!("Success connect to peer. multi_addr: {}", multi_addr);
+                Ok(())
+            }
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR => {
+                error!("Not an expected Interval Server Error: {:?}", res.text().await);
+                Err(Self::handle_rpc_status_code_error(reqwest::StatusCode::INTERNAL_SERVER_ERROR))
+            }
+            err => Err(Self::handle_rpc_status_code_error(err))
+        }
+    }
+
+    /// List peers with open connections.
+    #[tracing::instrument]
+    pub async fn swarm_peers(&self) -> IpfsClientResult<dtos::SwarmPeersResponse> {
+        let url_content = "/swarm/peers";
+        let res = self.ipfs_rpc_request(url_content).await?;
+
+        let status = res.status();
+        match status {
+            _ if status.is_success() => {
+                let list_res = res.json().await.map_err(|_e| {
+                    error!("Unexpected response body. msg: {:?}", _e);
+                    IpfsClientError::UnexpectedResponseBody
+                })?;
+                info!("Success list swarm peers");
+                Ok(list_res)
+            }
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR => {
+                error!("Not an expected Interval Server Error: {:?}", res.text().await);
+                Err(Self::handle_rpc_status_code_error(reqwest::StatusCode::INTERNAL_SERVER_ERROR))
+            }
+            err => Err(Self::handle_rpc_status_code_error(err))
+        }
+    }
+
+    /// Add a peer to the peering subsystem, which keeps the connection and re-dials when it drops.
+    ///
+    /// The multi address should contain `/p2p/{peer_id}`.
+    #[tracing::instrument]
+    pub async fn swarm_peering_add(&self, multi_addr: &str) -> IpfsClientResult<()> {
+        let url_content = format!("/swarm/peering/add?arg={multi_addr}",
+                                  multi_addr = multi_addr);
+        let res = self.ipfs_rpc_request(&url_content).await?;
+
+        let status = res.status();
+        match status {
+            _ if status.is_success() => {
+                info!("Success add peering. multi_addr: {}", multi_addr);
+                Ok(())
+            }
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR => {
+                error!("Not an expected Interval Server Error: {:?}", res.text().await);
+                Err(Self::handle_rpc_status_code_error(reqwest::StatusCode::INTERNAL_SERVER_ERROR))
+            }
+            err => Err(Self::handle_rpc_status_code_error(err))
+        }
+    }
+
+    /// List peers in the peering subsystem.
+    #[tracing::instrument]
+    pub async fn swarm_peering_ls(&self) -> IpfsClientResult<dtos::PeeringLsResponse> {
+        let url_content = "/swarm/peering/ls";
+        let res = self.ipfs_rpc_request(url_content).await?;
+
+        let status = res.status();
+        match status {
+            _ if status.is_success() => {
+                let list_res = res.json().await.map_err(|_e| {
+                    error!("Unexpected response body. msg: {:?}", _e);
+                    IpfsClientError::UnexpectedResponseBody
+                })?;
+                info!("Success list peering");
+                Ok(list_res)
+            }
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR => {
+                error!("Not an expected Interval Server Error: {:?}", res.text().await);
+                Err(Self::handle_rpc_status_code_error(reqwest::StatusCode::INTERNAL_SERVER_ERROR))
+            }
+            err => Err(Self::handle_rpc_status_code_error(err))
+        }
+    }
+
+    /// Remove a peer from the peering subsystem by peer id.
+    #[tracing::instrument]
+    pub async fn swarm_peering_rm(&self, peer_id: &str) -> IpfsClientResult<()> {
+        let url_content = format!("/swarm/peering/rm?arg={peer_id}",
+                                  peer_id = peer_id);
+        let res = self.ipfs_rpc_request(&url_content).await?;
+
+        let status = res.status();
+        match status {
+            _ if status.is_success() => {
+                info!("Success remove peering. peer_id: {}", peer_id);
+                Ok(())
+            }
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR => {
+                error!("Not an expected Interval Server Error: {:?}", res.text().await);
+                Err(Self::handle_rpc_status_code_error(reqwest::StatusCode::INTERNAL_SERVER_ERROR))
+            }
+            err => Err(Self::handle_rpc_status_code_error(err))
+        }
+    }
+
+    /// Get the size of the repo and its limit.
+    #[tracing::instrument]
+    pub async fn repo_stat(&self) -> IpfsClientResult<dtos::RepoStatResponse> {
+        let url_content = "/repo/stat?size-only=true";
+        let res = self.ipfs_rpc_request(url_content).await?;
+
+        let status = res.status();
+        match status {
+            _ if status.is_success() => {
+                let stat_res = res.json().await.map_err(|_e| {
+                    error!("Unexpected response body. msg: {:?}", _e);
+                    IpfsClientError::UnexpectedResponseBody
+                })?;
+                info!("Success get repo stat");
+                Ok(stat_res)
+            }
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR => {
+                error!("Not an expected Interval Server Error: {:?}", res.text().await);
+                Err(Self::handle_rpc_status_code_error(reqwest::StatusCode::INTERNAL_SERVER_ERROR))
+            }
+            err => Err(Self::handle_rpc_status_code_error(err))
+        }
+    }
+
+    /// Get the size of a DAG. The data would be fetched if not in local.
+    #[tracing::instrument]
+    pub async fn dag_stat(&self, cid: &str) -> IpfsClientResult<dtos::DagStatResponse> {
+        let url_content = format!("/dag/stat?arg={cid}&progress=false",
+                                  cid = cid);
+        let res = self.ipfs_rpc_request(&url_content).await?;
+
+        let status = res.status();
+        match status {
+            _ if status.is_success() => {
+                let stat_res = res.json().await.map_err(|_e| {
+                    error!("Unexpected response body. msg: {:?}", _e);
+                    IpfsClientError::UnexpectedResponseBody
+                })?;
+                info!("Success get dag stat. cid: {}", cid);
+                Ok(stat_res)
+            }
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR => {
+                error!("Not an expected Interval Server Error: {:?}", res.text().await);
+                Err(Self::handle_rpc_status_code_error(reqwest::StatusCode::INTERNAL_SERVER_ERROR))
+            }
+            err => Err(Self::handle_rpc_status_code_error(err))
+        }
+    }
 }
\ No newline at end of file
@@ -1,56 +1,88 @@
 #[allow(unused_imports)]
 use tracing::{error, debug, warn};
+#[cfg(feature = "gateway")]
+use std::time::Duration;
 use crate::IpfsClientError;
 
 mod apis;
 
 pub type IpfsClientResult<T> = Result<T, IpfsClientError>;
 
+/// Timeouts of gateway requests. No timeout if `None`.
+#[cfg(feature = "gateway")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GatewayTimeouts {
+    /// Timeout of connecting to the gateway.
+    pub connect: Option<Duration>,
+    /// Timeout from sending the request to receiving the response headers,
+    /// e.g. when the node is looking for the blocks of a file.
+    pub first_byte: Option<Duration>,
+    /// Max time between two chunks of the response body.
+    pub idle: Option<Duration>,
+}
+
 /// An IPFS client depend on `reqwest`.
+///
+/// Only RPC is accessed by default. With `gateway` feature, set the gateway by `with_gateway_address`.
 #[derive(Debug, Clone)]
 pub struct ReqwestIpfsClient {
-    #[cfg(not(feature = "no_gateway"))]
+    #[cfg(feature = "gateway")]
     pub gateway_address: String,
+    #[cfg(feature = "gateway")]
+    pub gateway_timeouts: GatewayTimeouts,
     pub rpc_address: String,
     client: reqwest::Client,
+    /// Client of gateway requests, which could have its own connect timeout.
+    #[cfg(feature = "gateway")]
+    gateway_client: reqwest::Client,
 }
 
 impl ReqwestIpfsClient {
     /// New with a new reqwest client.
-    pub fn new(
-        #[cfg(not(feature = "no_gateway"))] gateway_address: String,
-        rpc_address: String) -> Self {
-        ReqwestIpfsClient {
-            #[cfg(not(feature = "no_gateway"))]
-            gateway_address,
-            rpc_address,
-            client: reqwest::Client::new(),
-        }
+    pub fn new(rpc_address: String) -> Self {
+        Self::new_with_reqwest_client(rpc_address, reqwest::Client::new())
     }
 
     /// Relatively cheap to create (only address changed).
-    pub fn new_with_reqwest_client(
-        #[cfg(not(feature = "no_gateway"))] gateway_address: String,
-        rpc_address: String, client: reqwest::Client) -> Self {
+    pub fn new_with_reqwest_client(rpc_address: String, client: reqwest::Client) -> Self {
         ReqwestIpfsClient {
-            #[cfg(not(feature = "no_gateway"))]
-            gateway_address,
+            #[cfg(feature = "gateway")]
+            gateway_address: "127.0.0.1:8080".to_string(),
+            #[cfg(feature = "gateway")]
+            gateway_timeouts: GatewayTimeouts::default(),
             rpc_address,
+            #[cfg(feature = "gateway")]
+            gateway_client: client.clone(),
             client,
         }
     }
+
+    /// Set the address of the gateway, `127.0.0.1:8080` by default.
+    #[cfg(feature = "gateway")]
+    pub fn with_gateway_address(mut self, gateway_address: String) -> Self {
+        self.gateway_address = gateway_address;
+        self
+    }
+
+    /// Set timeouts of gateway requests.
+    ///
+    /// The connect timeout is set by a new reqwest client only for the gateway,
+    /// so RPC requests are not affected. Return the error if it fails to be built.
+    #[cfg(feature = "gateway")]
+    pub fn with_gateway_timeouts(mut self, timeouts: GatewayTimeouts) -> reqwest::Result<Self> {
+        if let Some(connect) = timeouts.connect {
+            self.gateway_client = reqwest::Client::builder()
+                .connect_timeout(connect)
+                .build()?;
+        }
+        self.gateway_timeouts = timeouts;
+        Ok(self)
+    }
 }
 
 impl Default for ReqwestIpfsClient {
     fn default() -> Self {
-        #[cfg(not(feature = "no_gateway"))]
-            {
-                ReqwestIpfsClient::new("127.0.0.1:8080".to_string(), "127.0.0.1:5001".to_string())
-            }
-        #[cfg(feature = "no_gateway")]
-            {
-                ReqwestIpfsClient::new("127.0.0.1:5001".to_string())
-            }
+        ReqwestIpfsClient::new("127.0.0.1:5001".to_string())
     }
 }
 
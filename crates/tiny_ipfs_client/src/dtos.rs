@@ -21,6 +21,30 @@ pub struct IdResponse {
     pub protocols: Vec<String>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RepoStatResponse {
+    /// Size in bytes that the repo is currently taking.
+    pub repo_size: u64,
+    /// Maximum datastore size (from configuration).
+    pub storage_max: u64,
+}
+
+/// Kubo before 0.20 returns `Size`, and later returns `TotalSize`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DagStatResponse {
+    pub size: Option<u64>,
+    pub total_size: Option<u64>,
+}
+
+impl DagStatResponse {
+    /// Total size of the DAG in bytes.
+    pub fn dag_size(&self) -> Option<u64> {
+        self.total_size.or(self.size)
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ListPinsResponse {
@@ -34,5 +58,31 @@ pub struct PinsInfoInList {
     pub r#type: models::PinType,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SwarmPeersResponse {
+    /// Might be `null` when no peer.
+    pub peers: Option<Vec<SwarmPeerInfo>>,
+}
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SwarmPeerInfo {
+    pub addr: String,
+    pub peer: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PeeringLsResponse {
+    /// Might be `null` when no peer.
+    pub peers: Option<Vec<PeeringPeerInfo>>,
+}
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PeeringPeerInfo {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub addrs: Vec<String>,
+}
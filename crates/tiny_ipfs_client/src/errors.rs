@@ -15,4 +15,14 @@ pub enum IpfsClientError {
     RpcInternalServerError,
     /// Failed to deserialize response body.
     UnexpectedResponseBody,
+    /// IPFS node did not respond in time.
+    Timeout,
 }
+
+impl std::fmt::Display for IpfsClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IPFS client error: {:?}", self)
+    }
+}
+
+impl std::error::Error for IpfsClientError {}
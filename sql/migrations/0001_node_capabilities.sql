@@ -0,0 +1,8 @@
+-- Record capabilities and versions of nodes.
+
+ALTER TABLE `node`
+  ADD COLUMN `agent_version` varchar(100) DEFAULT NULL COMMENT 'Agent version of IPFS node, like kubo/0.26.0/',
+  ADD COLUMN `multiaddrs` json DEFAULT NULL COMMENT 'Multi addresses advertised by IPFS node',
+  ADD COLUMN `repo_size` bigint DEFAULT NULL COMMENT 'Size of IPFS repo in bytes',
+  ADD COLUMN `storage_max` bigint DEFAULT NULL COMMENT 'Limit of IPFS repo in bytes',
+  ADD COLUMN `last_seen` timestamp NULL DEFAULT NULL COMMENT 'Last time the node passed health check';
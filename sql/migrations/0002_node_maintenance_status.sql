@@ -0,0 +1,4 @@
+-- Add `maintenance` to node status.
+
+ALTER TABLE `node`
+  MODIFY COLUMN `node_status` enum('online','unhealthy','offline','maintenance') NOT NULL;
@@ -0,0 +1,4 @@
+-- Record the creation time of pin requests. Required by IPFS Pinning Service API.
+
+ALTER TABLE `users_pins`
+  ADD COLUMN `created` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'Time the pin request was created';
@@ -0,0 +1,9 @@
+-- Record created/updated time, size, name, meta and origins of pins.
+
+ALTER TABLE `pin`
+  ADD COLUMN `created` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'Time the pin was created',
+  ADD COLUMN `updated` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'Time the pin was updated',
+  ADD COLUMN `size` bigint DEFAULT NULL COMMENT 'Size of the data in bytes',
+  ADD COLUMN `name` varchar(255) DEFAULT NULL COMMENT 'User-facing name of the pin',
+  ADD COLUMN `meta` json DEFAULT NULL COMMENT 'Metadata map of the pin',
+  ADD COLUMN `origins` json DEFAULT NULL COMMENT 'Multi addresses known to provide the data';
@@ -0,0 +1,6 @@
+-- Add expiry time of pins, and `Deleted` to pin status for expired pins.
+
+ALTER TABLE `pin`
+  MODIFY COLUMN `status` enum('Queued','Pinning','Pinned','Failed','NotFound','Deleted') NOT NULL COMMENT 'pin status',
+  ADD COLUMN `expires_at` timestamp NULL DEFAULT NULL COMMENT 'Time the pin expires. Never expires if null',
+  ADD KEY `pin_expires_at_index` (`expires_at`);
@@ -0,0 +1,16 @@
+-- Append-only audit log.
+
+CREATE TABLE `audit_log` (
+  `id` bigint NOT NULL AUTO_INCREMENT,
+  `created` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'Time the action happened',
+  `actor` varchar(255) NOT NULL COMMENT 'Who did the action, like system, admin, node:<peer id> or user:<user id>',
+  `action` enum('upload','add_pin_request','delete_pin_request','pin','unpin','rehome','expire','add_node','remove_node','re_bootstrap','set_maintenance','clear_maintenance') NOT NULL,
+  `cid` varchar(100) DEFAULT NULL COMMENT 'CID involved',
+  `node_id` varchar(100) DEFAULT NULL COMMENT 'Node involved',
+  `outcome` enum('success','failure') NOT NULL,
+  `detail` varchar(1000) DEFAULT NULL,
+  PRIMARY KEY (`id`),
+  KEY `audit_log_created_index` (`created`),
+  KEY `audit_log_cid_index` (`cid`),
+  KEY `audit_log_node_id_index` (`node_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci COMMENT='Append-only audit log';